@@ -0,0 +1,241 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{
+    error, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{client::Brand, selector::Selector};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Failed to read manifest: {0:?}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("Failed to parse manifest: {0:?}")]
+    Parse(PathBuf, #[source] serde_json::Error),
+    #[error("Manifest has no entries: {0:?}")]
+    NoEntries(PathBuf),
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Error from any step of downloading a manifest entry.
+pub type BoxError = Box<dyn error::Error + Send + Sync>;
+
+/// A single firmware to download.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    pub brand: Brand,
+    /// Car region. This is autodetected if unspecified.
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Output directory. Relative paths are relative to the directory
+    /// containing the manifest.
+    pub output: PathBuf,
+}
+
+impl ManifestEntry {
+    pub fn to_selectors(&self) -> Vec<Selector> {
+        let mut selectors = vec![];
+
+        if let Some(model) = &self.model {
+            selectors.push(Selector::Model(model.clone()));
+        }
+        if let Some(name) = &self.name {
+            selectors.push(Selector::Name(name.clone()));
+        }
+        if let Some(version) = &self.version {
+            selectors.push(Selector::Version(version.clone()));
+        }
+
+        selectors
+    }
+}
+
+impl fmt::Display for ManifestEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.brand.as_pretty_str())?;
+
+        if let Some(region) = &self.region {
+            write!(f, " {region}")?;
+        }
+
+        for selector in self.to_selectors() {
+            write!(f, " {selector}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// List of firmware to download with `nudl batch`. The manifest is a JSON file
+/// of the form:
+///
+/// ```json
+/// {
+///   "entries": [
+///     { "brand": "kia", "region": "US", "model": "SOME_ID", "output": "kia" }
+///   ]
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Load a manifest from a file. The output directories of all entries are
+    /// resolved relative to the directory containing the manifest.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path).map_err(|e| Error::Read(path.to_owned(), e))?;
+        let mut manifest: Self =
+            serde_json::from_slice(&data).map_err(|e| Error::Parse(path.to_owned(), e))?;
+
+        if manifest.entries.is_empty() {
+            return Err(Error::NoEntries(path.to_owned()));
+        }
+
+        let base = path.parent().unwrap_or(Path::new(""));
+
+        for entry in &mut manifest.entries {
+            entry.output = base.join(&entry.output);
+        }
+
+        Ok(manifest)
+    }
+}
+
+/// Result of downloading a single manifest entry.
+pub struct BatchResult {
+    pub entry: ManifestEntry,
+    pub error: Option<BoxError>,
+}
+
+/// Aggregated results for all manifest entries.
+#[derive(Default)]
+pub struct BatchReport {
+    pub results: Vec<BatchResult>,
+}
+
+impl BatchReport {
+    pub fn push<E: Into<BoxError>>(&mut self, entry: ManifestEntry, result: Result<(), E>) {
+        self.results.push(BatchResult {
+            entry,
+            error: result.err().map(Into::into),
+        });
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|r| r.error.is_some()).count()
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Batch summary: {} succeeded, {} failed",
+            self.results.len() - self.failed(),
+            self.failed(),
+        )?;
+
+        for result in &self.results {
+            let status = if result.error.is_some() {
+                "FAILED"
+            } else {
+                "OK"
+            };
+
+            writeln!(
+                f,
+                "  [{status}] {} -> {:?}",
+                result.entry, result.entry.output,
+            )?;
+
+            if let Some(e) = &result.error {
+                write!(f, "    {e}")?;
+
+                let mut source = e.source();
+                while let Some(e) = source {
+                    write!(f, ": {e}")?;
+                    source = e.source();
+                }
+
+                writeln!(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::TempDir;
+
+    #[test]
+    fn test_load_manifest() {
+        let temp_dir = TempDir::new();
+        let path = temp_dir.path().join("manifest.json");
+
+        fs::write(
+            &path,
+            r#"{
+                "entries": [
+                    { "brand": "kia", "region": "US", "model": "CAR1", "output": "kia" },
+                    { "brand": "genesis", "version": "V1", "output": "/abs/genesis" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let manifest = Manifest::load(&path).unwrap();
+        assert_eq!(manifest.entries.len(), 2);
+        assert_eq!(manifest.entries[0].output, temp_dir.path().join("kia"));
+        assert_eq!(
+            manifest.entries[0].to_selectors(),
+            [Selector::Model("CAR1".to_owned())],
+        );
+        assert_eq!(manifest.entries[0].to_string(), "Kia US -m CAR1");
+        assert_eq!(manifest.entries[1].output, Path::new("/abs/genesis"));
+        assert_eq!(manifest.entries[1].region, None);
+
+        let mut report = BatchReport::default();
+        report.push(manifest.entries[0].clone(), Ok::<_, Error>(()));
+        report.push(
+            manifest.entries[1].clone(),
+            Err(Error::NoEntries("inner".into())),
+        );
+        assert_eq!(report.failed(), 1);
+        assert!(
+            report.to_string().contains(
+                "[FAILED] Genesis -v V1 -> \"/abs/genesis\"\n    Manifest has no entries"
+            ),
+            "{report}",
+        );
+
+        fs::write(&path, r#"{ "entries": [] }"#).unwrap();
+        assert!(matches!(Manifest::load(&path), Err(Error::NoEntries(_))));
+
+        fs::write(
+            &path,
+            r#"{ "entries": [{ "brand": "bmw", "output": "x" }] }"#,
+        )
+        .unwrap();
+        assert!(matches!(Manifest::load(&path), Err(Error::Parse(_, _))));
+    }
+}
@@ -1,13 +1,24 @@
 // SPDX-FileCopyrightText: 2024-2026 Andrew Gunnerson
 // SPDX-License-Identifier: GPL-3.0-only
 
-use std::{fmt, path::PathBuf, str::FromStr};
+use std::{fmt, fs, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
 
-use anyhow::bail;
-use clap::{Args, Parser, Subcommand, ValueEnum};
+use anyhow::{Context, anyhow, bail};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use jiff::SignedDuration;
+use reqwest::Url;
 use tracing::Level;
 
-use crate::Selector;
+use nudl::{
+    client::{Brand, Credentials, NuClientBuilder, ProxyConfig},
+    download::{MinThroughput, Segmentation, StreamTimeouts},
+    model::SchemaMode,
+    ratelimit::{RateLimiter, TimeWindow},
+    recording::{Mode, Recording},
+    retry::RetryPolicy,
+    selector::Selector,
+    tls::SpkiPin,
+};
 
 const MAX_CONCURRENCY: u8 = 16;
 
@@ -30,41 +41,105 @@ impl FromStr for Concurrency {
     }
 }
 
-#[derive(Clone, Copy, Debug, ValueEnum)]
-pub enum Brand {
-    Hyundai,
-    Kia,
-    Genesis,
-}
-
-impl Brand {
-    pub fn as_code_str(self) -> &'static str {
-        match self {
-            Self::Hyundai => "HM",
-            Self::Kia => "KM",
-            Self::Genesis => "GN",
-        }
+/// Parse a non-zero number of bytes with an optional `K`, `M`, or `G` suffix,
+/// which are multiples of 1024.
+fn parse_bytes(s: &str) -> anyhow::Result<u64> {
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&s[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+
+    let n: u64 = digits.parse()?;
+    let bytes = n
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow!("value too large"))?;
+    if bytes == 0 {
+        bail!("value cannot be 0");
+    }
+
+    Ok(bytes)
+}
+
+/// Number of bytes per second. Accepts `K`, `M`, and `G` suffixes, which are
+/// multiples of 1024.
+#[derive(Clone, Copy, Debug)]
+pub struct ByteRate(pub u64);
+
+impl FromStr for ByteRate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_bytes(s).map(Self)
     }
+}
+
+/// Number of bytes. Accepts `K`, `M`, and `G` suffixes, which are multiples of
+/// 1024.
+#[derive(Clone, Copy, Debug)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_bytes(s).map(Self)
+    }
+}
+
+/// Positive time interval, like `30m` or `1h 30m`.
+#[derive(Clone, Copy, Debug)]
+pub struct Interval(pub Duration);
+
+impl FromStr for Interval {
+    type Err = anyhow::Error;
 
-    pub fn as_pretty_str(self) -> &'static str {
-        match self {
-            Self::Hyundai => "Hyundai",
-            Self::Kia => "Kia",
-            Self::Genesis => "Genesis",
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let duration: SignedDuration = s.parse()?;
+        if !duration.is_positive() {
+            bail!("interval must be positive");
         }
+
+        Ok(Self(duration.try_into()?))
     }
 }
 
-impl FromStr for Brand {
-    type Err = String;
+#[derive(Clone, Debug)]
+pub struct RegionApiUrl {
+    pub region: String,
+    pub url: Url,
+}
+
+impl FromStr for RegionApiUrl {
+    type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "HM" => Ok(Self::Hyundai),
-            "KM" => Ok(Self::Kia),
-            "GN" => Ok(Self::Genesis),
-            m => Err(m.to_owned()),
+        let (region, url) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected <REGION>=<URL>"))?;
+        if region.is_empty() {
+            bail!("region cannot be empty");
         }
+
+        Ok(Self {
+            region: region.to_owned(),
+            url: url.parse()?,
+        })
+    }
+}
+
+/// Output format for reports that are generated by nudl rather than returned
+/// by the server.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_possible_value().ok_or(fmt::Error)?.get_name())
     }
 }
 
@@ -81,6 +156,41 @@ impl fmt::Display for OutputFormat {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ProgressFormat {
+    Bars,
+    Detailed,
+    Jsonl,
+}
+
+impl fmt::Display for ProgressFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_possible_value().ok_or(fmt::Error)?.get_name())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ProgressGroup {
+    /// Progress output format.
+    ///
+    /// `bars`: Interactive progress bars.
+    /// `detailed`: Interactive progress bars, plus one bar for each active
+    /// download and post-processing task.
+    /// `jsonl`: One JSON object per line for each progress event. Other
+    /// human-readable output is written to stderr if the events are written to
+    /// stdout.
+    #[arg(long, value_name = "FORMAT", default_value_t = ProgressFormat::Bars)]
+    pub progress: ProgressFormat,
+
+    /// Write JSON lines progress events to a file instead of stdout.
+    ///
+    /// The events are written for every progress format, so this can be used
+    /// to log progress while showing progress bars. This can also be a path
+    /// like `/dev/fd/<num>` to write to an inherited file descriptor.
+    #[arg(long, value_name = "FILE", value_parser)]
+    pub progress_file: Option<PathBuf>,
+}
+
 #[derive(Debug, Args)]
 pub struct FamilyGroup {
     /// Car brand.
@@ -133,8 +243,363 @@ impl FirmwareSelectorGroup {
 #[derive(Debug, Args)]
 pub struct NetworkGroup {
     /// Ignore TLS certificate validation for HTTPS connections.
-    #[arg(long)]
+    #[arg(long, conflicts_with_all = ["ca_cert", "pin_public_key"])]
     pub ignore_tls_validation: bool,
+
+    /// Trust the certificates in a PEM file.
+    ///
+    /// This is in addition to the system trust store. This can be specified
+    /// multiple times.
+    #[arg(long, value_name = "FILE", value_parser)]
+    pub ca_cert: Vec<PathBuf>,
+
+    /// Pin the public key of the NU API servers.
+    ///
+    /// The value is the SHA-256 digest of the certificate's
+    /// SubjectPublicKeyInfo in the form `sha256//<base64>`, the same as curl's
+    /// `--pinnedpubkey`. The server's own certificate must match one of the
+    /// pins. This can be specified multiple times. Firmware downloads are not
+    /// pinned.
+    #[arg(long, value_name = "PIN")]
+    pub pin_public_key: Vec<SpkiPin>,
+
+    /// Base URL of the NU API.
+    ///
+    /// This is used for all regions that are not served by the EU API or
+    /// overridden by `--region-api-url`.
+    #[arg(long, value_name = "URL", env = "NUDL_API_URL")]
+    pub api_url: Option<Url>,
+
+    /// Base URL of the EU NU API.
+    ///
+    /// This is used for the `EU`, `RU`, and `TR` regions.
+    #[arg(long, value_name = "URL", env = "NUDL_API_URL_EU")]
+    pub api_url_eu: Option<Url>,
+
+    /// Base URL of the NU API for a specific region.
+    ///
+    /// This can be specified multiple times. When set via the environment
+    /// variable, multiple values are separated by commas.
+    #[arg(
+        long,
+        value_name = "REGION=URL",
+        env = "NUDL_REGION_API_URLS",
+        value_delimiter = ','
+    )]
+    pub region_api_url: Vec<RegionApiUrl>,
+
+    /// Fail if an API response has fields that nudl doesn't know about.
+    ///
+    /// By default, unknown fields are logged as a warning, included in
+    /// `json-raw` output, and otherwise ignored.
+    #[arg(long)]
+    pub strict_schema: bool,
+
+    /// Save every request and response to a directory.
+    ///
+    /// JSON responses are saved in full, but only the headers of firmware
+    /// downloads are saved. Credentials are redacted. The directory can be
+    /// used with `--replay` to rerun the same command without contacting the
+    /// server.
+    #[arg(long, value_name = "DIR", value_parser, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Serve responses from a directory created by `--record`.
+    ///
+    /// No requests are sent to the server. Requests that were not recorded
+    /// fail, as do firmware downloads.
+    #[arg(long, value_name = "DIR", value_parser)]
+    pub replay: Option<PathBuf>,
+
+    /// Maximum retries for API requests and downloads.
+    ///
+    /// Only transient failures, like timeouts, connection resets, server
+    /// errors, and HTTP 429, are retried.
+    #[arg(long, default_value = "3")]
+    pub retries: u8,
+
+    /// Maximum delay between retries.
+    ///
+    /// The delay starts at 1 second and doubles after each retry, with random
+    /// jitter. Delays requested by the server with `Retry-After` are also
+    /// limited to this value.
+    #[arg(long, value_name = "INTERVAL", default_value = "30s")]
+    pub retry_max_delay: Interval,
+
+    /// Timeout for establishing connections.
+    #[arg(long, value_name = "INTERVAL", default_value = "30s")]
+    pub connect_timeout: Interval,
+
+    /// Abort a download if no data is received for this long.
+    ///
+    /// The download is then retried from where it left off.
+    #[arg(long, value_name = "INTERVAL", default_value = "60s")]
+    pub idle_timeout: Interval,
+
+    /// Abort a download if it is slower than this many bytes per second.
+    ///
+    /// This applies to each download individually and is averaged over
+    /// `--min-throughput-window`. Time spent waiting due to `--limit-rate` is
+    /// not counted. The `K`, `M`, and `G` suffixes are multiples of 1024 (eg.
+    /// `100K`). The download is then retried from where it left off.
+    #[arg(long, value_name = "RATE")]
+    pub min_throughput: Option<ByteRate>,
+
+    /// Period over which the download speed is averaged for
+    /// `--min-throughput`.
+    #[arg(
+        long,
+        value_name = "INTERVAL",
+        default_value = "30s",
+        requires = "min_throughput"
+    )]
+    pub min_throughput_window: Interval,
+
+    #[command(flatten)]
+    pub proxy: ProxyGroup,
+}
+
+impl NetworkGroup {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.retries,
+            max_delay: self.retry_max_delay.0,
+            ..Default::default()
+        }
+    }
+
+    pub fn stream_timeouts(&self) -> StreamTimeouts {
+        StreamTimeouts {
+            idle: Some(self.idle_timeout.0),
+            min_throughput: self.min_throughput.map(|r| MinThroughput {
+                bytes_per_sec: r.0,
+                window: self.min_throughput_window.0,
+            }),
+        }
+    }
+
+    pub fn to_client_builder(&self) -> NuClientBuilder {
+        let mut builder = NuClientBuilder::new()
+            .ignore_tls_validation(self.ignore_tls_validation)
+            .ca_cert_files(self.ca_cert.clone())
+            .pinned_public_keys(self.pin_public_key.clone())
+            .proxy(self.proxy.to_proxy_config())
+            .retry_policy(self.retry_policy())
+            .connect_timeout(Some(self.connect_timeout.0))
+            .schema_mode(if self.strict_schema {
+                SchemaMode::Strict
+            } else {
+                SchemaMode::Lenient
+            });
+
+        if let Some(url) = &self.api_url {
+            builder = builder.api_url(url.as_str());
+        }
+        if let Some(url) = &self.api_url_eu {
+            builder = builder.api_url_eu(url.as_str());
+        }
+        for item in &self.region_api_url {
+            builder = builder.region_api_url(&item.region, item.url.as_str());
+        }
+
+        if let Some(dir) = &self.record {
+            builder = builder.recording(Some(Recording::new(dir.clone(), Mode::Record)));
+        } else if let Some(dir) = &self.replay {
+            builder = builder.recording(Some(Recording::new(dir.clone(), Mode::Replay)));
+        }
+
+        builder
+    }
+}
+
+#[derive(Args)]
+pub struct ProxyGroup {
+    /// Proxy for all requests, including firmware downloads.
+    ///
+    /// The `http`, `https`, `socks5`, and `socks5h` schemes are supported. If
+    /// unspecified, the standard `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY`, and
+    /// `NO_PROXY` environment variables are used.
+    #[arg(long, value_name = "URL", env = "NUDL_PROXY")]
+    pub proxy: Option<Url>,
+
+    /// Username for authenticating with the proxy.
+    #[arg(
+        long,
+        value_name = "USER",
+        env = "NUDL_PROXY_USER",
+        requires_all = ["proxy", "proxy_password"]
+    )]
+    pub proxy_user: Option<String>,
+
+    /// Password for authenticating with the proxy.
+    #[arg(
+        long,
+        value_name = "PASSWORD",
+        env = "NUDL_PROXY_PASSWORD",
+        hide_env_values = true,
+        requires = "proxy_user"
+    )]
+    pub proxy_password: Option<String>,
+
+    /// Comma-separated list of hosts that bypass the proxy.
+    ///
+    /// This uses the same format as the `NO_PROXY` environment variable, which
+    /// is used if this is unspecified.
+    #[arg(long, value_name = "HOSTS", env = "NUDL_NO_PROXY", requires = "proxy")]
+    pub no_proxy: Option<String>,
+}
+
+impl ProxyGroup {
+    pub fn to_proxy_config(&self) -> Option<ProxyConfig> {
+        let url = self.proxy.as_ref()?;
+
+        let credentials = match (&self.proxy_user, &self.proxy_password) {
+            (Some(user_id), Some(password)) => Some(Credentials {
+                user_id: user_id.clone(),
+                password: password.clone(),
+            }),
+            _ => None,
+        };
+
+        Some(ProxyConfig {
+            url: url.to_string(),
+            credentials,
+            no_proxy: self.no_proxy.clone(),
+        })
+    }
+}
+
+impl fmt::Debug for ProxyGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyGroup")
+            .field("proxy", &self.proxy)
+            .field("proxy_user", &self.proxy_user)
+            .field(
+                "proxy_password",
+                &self.proxy_password.as_ref().map(|_| "<redacted>"),
+            )
+            .field("no_proxy", &self.no_proxy)
+            .finish()
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct SegmentGroup {
+    /// Split large unsplit files into this many concurrent downloads.
+    ///
+    /// Each segment downloads a separate byte range into the same file, which
+    /// is preallocated up front. Files split into zips are not affected since
+    /// their splits are already downloaded concurrently. Interrupted segmented
+    /// downloads resume each segment where it left off. The maximum allowed is
+    /// 16.
+    #[arg(long, value_name = "N", default_value = "1")]
+    pub segments: Concurrency,
+
+    /// Minimum size of each segment.
+    ///
+    /// Files are split into fewer segments if needed so that no segment is
+    /// smaller than this. The `K`, `M`, and `G` suffixes are multiples of 1024.
+    #[arg(long, value_name = "SIZE", default_value = "64M")]
+    pub min_segment_size: ByteSize,
+}
+
+impl SegmentGroup {
+    pub fn to_segmentation(&self) -> Segmentation {
+        Segmentation {
+            max_segments: self.segments.0.into(),
+            min_segment_size: self.min_segment_size.0,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RateLimitGroup {
+    /// Maximum total download speed in bytes per second.
+    ///
+    /// The limit is shared by all concurrent downloads. The `K`, `M`, and `G`
+    /// suffixes are multiples of 1024 (eg. `20M`).
+    #[arg(long, value_name = "RATE")]
+    pub limit_rate: Option<ByteRate>,
+
+    /// Only apply the download speed limit during this daily time window.
+    ///
+    /// The window is in local time and is specified as `<START>-<END>` (eg.
+    /// `08:00-18:00`). If the end is before the start, the window wraps around
+    /// midnight. Outside of the window, downloads run at full speed.
+    #[arg(long, value_name = "START-END", requires = "limit_rate")]
+    pub limit_rate_window: Option<TimeWindow>,
+}
+
+impl RateLimitGroup {
+    pub fn to_rate_limiter(&self) -> Option<Arc<RateLimiter>> {
+        self.limit_rate
+            .map(|r| Arc::new(RateLimiter::new(r.0, self.limit_rate_window)))
+    }
+}
+
+#[derive(Args)]
+pub struct AuthGroup {
+    /// User ID for authenticated requests.
+    ///
+    /// Requests are anonymous by default. Credentials are only used for
+    /// listing the available firmware.
+    #[arg(long, value_name = "ID", env = "NUDL_USER_ID", requires = "password")]
+    pub user_id: Option<String>,
+
+    /// Password for authenticated requests.
+    #[arg(
+        long,
+        value_name = "PASSWORD",
+        env = "NUDL_PASSWORD",
+        hide_env_values = true,
+        requires = "user_id"
+    )]
+    pub password: Option<String>,
+
+    /// File containing credentials for authenticated requests.
+    ///
+    /// The first line is the user ID and the second line is the password.
+    /// `--user-id` and `--password` take precedence over this file.
+    #[arg(long, value_name = "FILE", value_parser)]
+    pub credentials_file: Option<PathBuf>,
+}
+
+impl AuthGroup {
+    pub fn to_credentials(&self) -> anyhow::Result<Option<Credentials>> {
+        if let (Some(user_id), Some(password)) = (&self.user_id, &self.password) {
+            return Ok(Some(Credentials {
+                user_id: user_id.clone(),
+                password: password.clone(),
+            }));
+        }
+
+        let Some(path) = &self.credentials_file else {
+            return Ok(None);
+        };
+
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read credentials file: {path:?}"))?;
+        let mut lines = contents.lines();
+
+        let (Some(user_id), Some(password)) = (lines.next(), lines.next()) else {
+            bail!("Credentials file must contain a user ID line and a password line: {path:?}");
+        };
+
+        Ok(Some(Credentials {
+            user_id: user_id.to_owned(),
+            password: password.to_owned(),
+        }))
+    }
+}
+
+impl fmt::Debug for AuthGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthGroup")
+            .field("user_id", &self.user_id)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("credentials_file", &self.credentials_file)
+            .finish()
+    }
 }
 
 /// List available firmware.
@@ -154,6 +619,167 @@ pub struct ListCli {
     #[arg(short, long, default_value_t = OutputFormat::Text)]
     pub output: OutputFormat,
 
+    #[command(flatten)]
+    pub auth: AuthGroup,
+
+    #[command(flatten)]
+    pub network: NetworkGroup,
+}
+
+/// Show the files that make up a firmware without downloading it.
+#[derive(Debug, Parser)]
+pub struct InfoCli {
+    #[command(flatten)]
+    pub family: FamilyGroup,
+
+    #[command(flatten)]
+    pub selector: FirmwareSelectorGroup,
+
+    /// Data output format.
+    ///
+    /// `text`: Summary of each file and its downloads.
+    /// `json`: Normalized data, including the resolved download names and
+    /// URLs.
+    /// `json-raw`: Raw data from the server.
+    #[arg(short, long, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    #[command(flatten)]
+    pub auth: AuthGroup,
+
+    #[command(flatten)]
+    pub network: NetworkGroup,
+}
+
+/// Find regions that have firmware for a brand.
+///
+/// A built-in list of candidate regions is probed, along with any regions
+/// specified with `-r`.
+#[derive(Debug, Parser)]
+pub struct RegionsCli {
+    /// Car brand.
+    #[arg(short, long)]
+    pub brand: Brand,
+
+    /// Additional region to probe.
+    ///
+    /// This can be specified multiple times.
+    #[arg(short, long = "region", value_name = "REGION")]
+    pub regions: Vec<String>,
+
+    /// Number of regions to probe concurrently.
+    ///
+    /// The maximum concurrency allowed is 16.
+    #[arg(short, long, default_value = "4")]
+    pub concurrency: Concurrency,
+
+    /// Data output format.
+    ///
+    /// `text`: Regions that have data, their API URL, and the number of cars.
+    /// `json`: Results for all probed regions.
+    #[arg(short, long, default_value_t = ReportFormat::Text)]
+    pub output: ReportFormat,
+
+    #[command(flatten)]
+    pub network: NetworkGroup,
+}
+
+/// Compare a model's firmware across regions.
+///
+/// Exits with an error if no region has the firmware.
+#[derive(Debug, Parser)]
+pub struct CompareCli {
+    /// Car brand.
+    #[arg(short, long)]
+    pub brand: Brand,
+
+    /// Model ID.
+    #[arg(short, long, value_name = "ID")]
+    pub model: String,
+
+    /// Region to compare.
+    ///
+    /// This must be specified at least twice.
+    #[arg(
+        short,
+        long = "region",
+        value_name = "REGION",
+        required = true,
+        num_args = 1
+    )]
+    pub regions: Vec<String>,
+
+    /// Data output format.
+    ///
+    /// `text`: Table of versions and files in each region.
+    /// `json`: Full comparison results.
+    #[arg(short, long, default_value_t = ReportFormat::Text)]
+    pub output: ReportFormat,
+
+    /// Exit with an error if the firmware files are not identical in every
+    /// region.
+    ///
+    /// Regions without firmware for the model also count as a difference.
+    #[arg(long)]
+    pub fail_on_diff: bool,
+
+    #[command(flatten)]
+    pub auth: AuthGroup,
+
+    #[command(flatten)]
+    pub network: NetworkGroup,
+}
+
+/// Save a snapshot of the firmware for every car.
+///
+/// Snapshots contain the raw data from the server and are stored as
+/// `<store>/<brand>/<region>/<timestamp>.json`.
+#[derive(Debug, Parser)]
+pub struct SnapshotCli {
+    #[command(flatten)]
+    pub family: FamilyGroup,
+
+    /// Snapshot store directory.
+    #[arg(short, long, value_parser, default_value = "snapshots")]
+    pub store: PathBuf,
+
+    #[command(flatten)]
+    pub auth: AuthGroup,
+
+    #[command(flatten)]
+    pub network: NetworkGroup,
+}
+
+/// Show changes between snapshots.
+///
+/// By default, the two most recent snapshots in the store are compared. This
+/// does not require network access.
+#[derive(Debug, Parser)]
+pub struct ChangesCli {
+    #[command(flatten)]
+    pub family: FamilyGroup,
+
+    /// Snapshot store directory.
+    #[arg(short, long, value_parser, default_value = "snapshots")]
+    pub store: PathBuf,
+
+    /// Data output format.
+    ///
+    /// `text`: One line per change.
+    /// `json`: One JSON object per line for each change.
+    #[arg(short, long, default_value_t = ReportFormat::Text)]
+    pub output: ReportFormat,
+
+    /// Periodically take new snapshots and report changes.
+    ///
+    /// The interval is specified like `30m` or `1h 30m`. New snapshots are only
+    /// saved to the store when something changed.
+    #[arg(short, long, value_name = "INTERVAL")]
+    pub watch: Option<Interval>,
+
+    #[command(flatten)]
+    pub auth: AuthGroup,
+
     #[command(flatten)]
     pub network: NetworkGroup,
 }
@@ -177,37 +803,240 @@ pub struct DownloadCli {
     #[arg(short, long, default_value = "4")]
     pub concurrency: Concurrency,
 
-    /// Maximum retries during download.
-    #[arg(long, default_value = "3")]
-    pub retries: u8,
+    /// Keep raw unextracted files.
+    #[arg(short, long)]
+    pub keep_raw: bool,
+
+    /// Extract split zips while they are still being downloaded.
+    ///
+    /// This allows extraction to finish sooner. Each split is deleted as soon
+    /// as it has been extracted, unless `--keep-raw` is specified, so the peak
+    /// disk space usage stays close to the firmware size.
+    #[arg(long)]
+    pub stream_extract: bool,
+
+    /// Keep streamed splits until the extracted file passes its CRC32 check.
+    ///
+    /// A corrupt split can then be replaced without downloading everything
+    /// again, at the cost of roughly double the disk space usage.
+    #[arg(long, requires = "stream_extract")]
+    pub keep_splits_until_verified: bool,
+
+    /// Show what would be downloaded without downloading anything.
+    ///
+    /// This lists the downloads that would be started or resumed, the files
+    /// that would be verified or extracted, the raw files that would be
+    /// deleted, and the number of bytes remaining. Nothing is written to the
+    /// output directory.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Don't fail if there may not be enough free space.
+    ///
+    /// The check is based on the worst case peak disk usage and may be too
+    /// pessimistic, for example when the output directory is on a
+    /// filesystem with compression.
+    #[arg(long)]
+    pub skip_space_check: bool,
+
+    #[command(flatten)]
+    pub segments: SegmentGroup,
+
+    #[command(flatten)]
+    pub rate_limit: RateLimitGroup,
+
+    #[command(flatten)]
+    pub progress: ProgressGroup,
+
+    #[command(flatten)]
+    pub auth: AuthGroup,
+
+    #[command(flatten)]
+    pub network: NetworkGroup,
+}
+
+/// Download firmware for multiple cars listed in a manifest.
+///
+/// The manifest is a JSON file containing an `entries` array. Each entry has a
+/// `brand`, an optional `region`, optional `model`, `name`, and `version`
+/// selectors, and an `output` directory. Relative output directories are
+/// relative to the directory containing the manifest. Entries are downloaded
+/// one at a time and a summary is printed at the end.
+#[derive(Debug, Parser)]
+pub struct BatchCli {
+    /// Path to manifest file.
+    #[arg(value_parser)]
+    pub manifest: PathBuf,
+
+    /// Download and post-processing concurrency.
+    ///
+    /// The maximum concurrency allowed is 16.
+    #[arg(short, long, default_value = "4")]
+    pub concurrency: Concurrency,
 
     /// Keep raw unextracted files.
     #[arg(short, long)]
     pub keep_raw: bool,
 
+    /// Extract split zips while they are still being downloaded.
+    #[arg(long)]
+    pub stream_extract: bool,
+
+    /// Keep streamed splits until the extracted file passes its CRC32 check.
+    #[arg(long, requires = "stream_extract")]
+    pub keep_splits_until_verified: bool,
+
+    /// Stop at the first entry that fails.
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Don't fail if there may not be enough free space.
+    ///
+    /// The check is based on the worst case peak disk usage and may be too
+    /// pessimistic, for example when the output directory is on a
+    /// filesystem with compression.
+    #[arg(long)]
+    pub skip_space_check: bool,
+
+    #[command(flatten)]
+    pub segments: SegmentGroup,
+
+    #[command(flatten)]
+    pub rate_limit: RateLimitGroup,
+
+    #[command(flatten)]
+    pub progress: ProgressGroup,
+
+    #[command(flatten)]
+    pub auth: AuthGroup,
+
     #[command(flatten)]
     pub network: NetworkGroup,
 }
 
 /// Verify CRC32 of existing firmware.
 #[derive(Debug, Parser)]
+// Downloads are only retried when repairing.
+#[command(group(
+    ArgGroup::new("repair_retries")
+        .args(["retries", "retry_max_delay"])
+        .multiple(true)
+        .requires("repair")
+))]
 pub struct VerifyCli {
     /// Firmware directory.
     #[arg(short, long, value_parser, default_value = ".")]
     pub directory: PathBuf,
 
-    /// Verification concurrency.
+    /// Verification, download, and post-processing concurrency.
+    ///
+    /// The maximum concurrency allowed is 16.
+    #[arg(short, long, default_value = "4")]
+    pub concurrency: Concurrency,
+
+    /// Re-download files that are missing or corrupt.
+    ///
+    /// The car is found on the server based on the brand, model ID, and
+    /// firmware version in the `.ver` file. Repairing only proceeds if the
+    /// firmware on the server is identical to what the `.ver` file describes.
+    #[arg(long)]
+    pub repair: bool,
+
+    /// Car region to use when repairing.
+    ///
+    /// This is autodetected (likely by GeoIP) if unspecified.
+    #[arg(short, long, requires = "repair")]
+    pub region: Option<String>,
+
+    /// Don't fail if there may not be enough free space.
+    ///
+    /// The check is based on the worst case peak disk usage and may be too
+    /// pessimistic, for example when the output directory is on a
+    /// filesystem with compression.
+    #[arg(long)]
+    pub skip_space_check: bool,
+
+    #[command(flatten)]
+    pub segments: SegmentGroup,
+
+    #[command(flatten)]
+    pub rate_limit: RateLimitGroup,
+
+    #[command(flatten)]
+    pub progress: ProgressGroup,
+
+    #[command(flatten)]
+    pub auth: AuthGroup,
+
+    #[command(flatten)]
+    pub network: NetworkGroup,
+}
+
+/// Extract split zips that were kept with `--keep-raw`.
+///
+/// This does not require network access. The split zips are found based on
+/// their file names and the output CRC32 checksums are checked against the
+/// `.ver` file.
+#[derive(Debug, Parser)]
+pub struct ExtractCli {
+    /// Firmware directory.
+    #[arg(short, long, value_parser, default_value = ".")]
+    pub directory: PathBuf,
+
+    /// Extraction concurrency.
     ///
     /// The maximum concurrency allowed is 16.
     #[arg(short, long, default_value = "4")]
     pub concurrency: Concurrency,
+
+    /// Keep raw split zips after extraction.
+    #[arg(short, long)]
+    pub keep_raw: bool,
+
+    #[command(flatten)]
+    pub progress: ProgressGroup,
+}
+
+/// Copy firmware to a removable drive for the head unit.
+///
+/// The `.ver` file and firmware files are copied to the root of the drive in
+/// the layout that the head unit expects. Each file is synced to the drive as
+/// it is copied and the copy is verified against the `.ver` file afterwards.
+/// The drive's filesystem type and capacity are checked before anything is
+/// copied.
+#[derive(Debug, Parser)]
+pub struct InstallMediaCli {
+    /// Firmware directory.
+    #[arg(value_parser)]
+    pub directory: PathBuf,
+
+    /// Mount point of the drive.
+    #[arg(value_parser)]
+    pub mountpoint: PathBuf,
+
+    /// Verification concurrency.
+    ///
+    /// The maximum concurrency allowed is 16.
+    #[arg(short, long, default_value = "1")]
+    pub concurrency: Concurrency,
+
+    #[command(flatten)]
+    pub progress: ProgressGroup,
 }
 
 #[derive(Debug, Subcommand)]
 pub enum Command {
     List(ListCli),
+    Info(InfoCli),
+    Regions(RegionsCli),
+    Compare(CompareCli),
+    Snapshot(SnapshotCli),
+    Changes(ChangesCli),
     Download(DownloadCli),
+    Batch(BatchCli),
     Verify(VerifyCli),
+    Extract(ExtractCli),
+    InstallMedia(InstallMediaCli),
 }
 
 #[derive(Debug, Parser)]
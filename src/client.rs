@@ -3,33 +3,46 @@
 
 use std::{
     borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
     fmt::{self, Debug},
+    path::PathBuf,
     str::{self, FromStr},
+    sync::Arc,
+    time::Duration,
 };
 
 use base64::{Engine, engine::general_purpose::STANDARD};
 use bytes::Bytes;
+use clap::ValueEnum;
 use futures_core::Stream;
 use jiff::{Zoned, civil::DateTime};
-use reqwest::{Client, ClientBuilder, RequestBuilder, StatusCode, header};
+use reqwest::{
+    Certificate, Client, ClientBuilder, NoProxy, Proxy, Request, RequestBuilder, Response,
+    StatusCode, Url,
+    header::{self, HeaderMap},
+};
+use rustls::{ClientConfig, crypto::CryptoProvider};
 use serde::{
-    Serialize,
+    Deserialize, Serialize,
     de::{DeserializeOwned, IgnoredAny},
 };
 use thiserror::Error;
-use tracing::debug;
+use tokio::time;
+use tracing::{debug, info, warn};
 
 use crate::{
-    cli::Brand,
     crypto,
     model::{
         self, Car, CarDownloadData, CarListData, CarListRequest, File, GuidData, RegionStatusData,
-        ResponseData,
+        ResponseData, Schema, SchemaMode,
     },
+    recording::{self, Exchange, Mode, RecordedRequest, RecordedResponse, Recording},
+    retry::{self, Classify, RetryPolicy, Retryability},
+    tls::{self, PinningVerifier, SpkiPin},
 };
 
-const BASE_URL: &str = "https://api.map-care.com/api/v3";
-const BASE_URL_EU: &str = "https://apieu.map-care.com/api/v3";
+pub const BASE_URL: &str = "https://api.map-care.com/api/v3";
+pub const BASE_URL_EU: &str = "https://apieu.map-care.com/api/v3";
 const USER_AGENT: &str = "curl/7.74.0-DEV";
 
 const EU_EEA_ISO_3166_1: &[&str] = &[
@@ -68,6 +81,7 @@ const EU_EEA_ISO_3166_1: &[&str] = &[
 ];
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum Error {
     #[error("Starting offset matches file size")]
     AlreadyComplete,
@@ -89,6 +103,28 @@ pub enum Error {
     Base64Decode(#[from] base64::DecodeError),
     #[error("HTTP request error: {0}")]
     Request(#[from] reqwest::Error),
+    #[error("HTTP {status} for URL: {url}")]
+    HttpStatus {
+        url: String,
+        status: StatusCode,
+        /// Delay requested by the server via the `Retry-After` header.
+        retry_after: Option<Duration>,
+    },
+    #[error("Expected Content-Range for bytes {expected}, but got {actual:?}")]
+    BadContentRange {
+        expected: String,
+        actual: Option<String>,
+    },
+    #[error("Failed to decode response from {0}: {1}")]
+    Decode(String, serde_json::Error),
+    #[error("Recording error: {0}")]
+    Recording(#[from] recording::Error),
+    #[error("TLS error: {0}")]
+    Tls(#[from] tls::Error),
+    #[error("TLS configuration error: {0}")]
+    TlsConfig(#[from] rustls::Error),
+    #[error("No rustls crypto provider is installed")]
+    NoCryptoProvider,
     #[error("Crypto error: {0}")]
     Crypto(#[from] crypto::Error),
     #[error("Model error: {0}")]
@@ -97,14 +133,63 @@ pub enum Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
-/// Get the base URL for a region.
-fn base_url(region: &str) -> &'static str {
-    match region {
-        "EU" | "RU" | "TR" => BASE_URL_EU,
-        _ => BASE_URL,
+impl Classify for Error {
+    fn retryability(&self) -> Retryability {
+        match self {
+            Self::Request(e) => {
+                if let Some(status) = e.status() {
+                    Retryability::from_status(status, None)
+                } else if e.is_builder() || e.is_redirect() || tls::is_certificate_error(e) {
+                    // Retrying will not fix a certificate that failed
+                    // validation or pinning.
+                    Retryability::Fatal
+                } else {
+                    // Timeouts, connection failures, and connections that
+                    // were reset or closed while receiving the body.
+                    Retryability::Retryable { retry_after: None }
+                }
+            }
+            Self::HttpStatus {
+                status,
+                retry_after,
+                ..
+            } => Retryability::from_status(*status, *retry_after),
+            _ => Retryability::Fatal,
+        }
     }
 }
 
+/// Parse the first and last byte positions from a `Content-Range` header.
+fn parse_content_range(value: &str) -> Option<(u64, u64)> {
+    let (range, _) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+
+    Some((first.parse().ok()?, last.parse().ok()?))
+}
+
+/// Fail with [`Error::HttpStatus`] if the response status is not successful.
+fn check_status(url: &str, status: StatusCode, headers: &HeaderMap) -> Result<()> {
+    if status.is_success() {
+        return Ok(());
+    }
+
+    Err(Error::HttpStatus {
+        url: url.to_owned(),
+        status,
+        retry_after: retry::parse_retry_after(headers),
+    })
+}
+
+/// Regions to probe when discovering which regions have data. This includes
+/// all known regions and other plausible ISO 3166-1 country codes.
+pub const CANDIDATE_REGIONS: &[&str] = &[
+    "AE", "AR", "AU", "BR", "CA", "CL", "CN", "CO", "EG", "EU", "ID", "IL", "IN", "JP", "KR", "KZ",
+    "ME", "MX", "MY", "NZ", "PE", "PH", "RU", "SA", "SG", "TH", "TR", "TW", "UA", "US", "VN", "ZA",
+];
+
+/// Regions that are served by the EU API endpoint by default.
+const EU_API_REGIONS: &[&str] = &["EU", "RU", "TR"];
+
 /// A type representing the Authorization field for NU requests.
 #[derive(Clone, Debug)]
 struct Authorization(DateTime);
@@ -132,6 +217,83 @@ impl fmt::Display for Authorization {
     }
 }
 
+/// Encrypt a field for the `/car/list` request.
+fn encrypt_field(value: &str) -> String {
+    if value.is_empty() {
+        return String::new();
+    }
+
+    STANDARD.encode(crypto::encrypt(value.as_bytes()))
+}
+
+/// User credentials for authenticated requests.
+#[derive(Clone)]
+pub struct Credentials {
+    pub user_id: String,
+    pub password: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("user_id", &self.user_id)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Proxy server for all requests, including firmware downloads.
+#[derive(Clone, Debug)]
+pub struct ProxyConfig {
+    /// Proxy URL. The `http`, `https`, `socks5`, and `socks5h` schemes are
+    /// supported.
+    pub url: String,
+    /// Credentials for authenticating with the proxy.
+    pub credentials: Option<Credentials>,
+    /// Comma-separated list of hosts that bypass the proxy, in the same format
+    /// as the `NO_PROXY` environment variable. If unset, `NO_PROXY` is used.
+    pub no_proxy: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Brand {
+    Hyundai,
+    Kia,
+    Genesis,
+}
+
+impl Brand {
+    pub fn as_code_str(self) -> &'static str {
+        match self {
+            Self::Hyundai => "HM",
+            Self::Kia => "KM",
+            Self::Genesis => "GN",
+        }
+    }
+
+    pub fn as_pretty_str(self) -> &'static str {
+        match self {
+            Self::Hyundai => "Hyundai",
+            Self::Kia => "Kia",
+            Self::Genesis => "Genesis",
+        }
+    }
+}
+
+impl FromStr for Brand {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "HM" => Ok(Self::Hyundai),
+            "KM" => Ok(Self::Kia),
+            "GN" => Ok(Self::Genesis),
+            m => Err(m.to_owned()),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum BrandInfo {
     Known(Brand),
@@ -140,7 +302,7 @@ pub enum BrandInfo {
 
 impl BrandInfo {
     pub fn new(brand: &str) -> Self {
-        match Brand::from_str(brand) {
+        match brand.parse() {
             Ok(b) => Self::Known(b),
             Err(b) => Self::Unknown(b),
         }
@@ -168,6 +330,15 @@ impl Serialize for BrandInfo {
     }
 }
 
+/// Cars from the `/car/list` API along with the server's authorization status
+/// for the request.
+#[derive(Clone, Debug, Serialize)]
+pub struct CarList {
+    /// Raw [`CarListData::user_auth`] value. Its meaning is not known.
+    pub user_auth: String,
+    pub cars: Vec<CarInfo>,
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct CarInfo {
     /// Two character brand code.
@@ -183,6 +354,9 @@ pub struct CarInfo {
     pub name: String,
     /// Firmware version numbers.
     pub versions: Vec<String>,
+    /// Storage medium that the head unit reads firmware from. Either `SD` or
+    /// `USB`.
+    pub media_type: String,
     /// Unknown integer value.
     #[serde(skip)]
     pub mcode: String,
@@ -211,13 +385,14 @@ impl TryFrom<Car> for CarInfo {
             model: car.vcl_name,
             name,
             versions: car.sw_vers,
+            media_type: car.media_type,
             mcode: car.mcode,
         })
     }
 }
 
-#[derive(Clone, Debug)]
-enum ZipNamingScheme {
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ZipNamingScheme {
     NotZip,
     NotSplit {
         name: String,
@@ -290,7 +465,69 @@ impl ZipNamingScheme {
         })
     }
 
-    fn name(&self, index: u32) -> String {
+    /// Find all sets of split zips among the specified file names. Returns
+    /// the naming scheme and number of splits for each set. Only the
+    /// [`Self::Legacy`] and [`Self::Standard`] schemes are detected. Sets with
+    /// missing splits at the end cannot be distinguished from complete sets.
+    pub fn detect(names: &BTreeSet<String>) -> Vec<(Self, u32)> {
+        let mut result = vec![];
+
+        for name in names {
+            if let Some(base_name) = name.strip_suffix(".zip") {
+                let count = (1..)
+                    .take_while(|i| names.contains(&format!("{base_name}.z{i:02}")))
+                    .count() as u32
+                    + 1;
+
+                let scheme = Self::Standard {
+                    base_name: base_name.to_owned(),
+                    count,
+                };
+                result.push((scheme, count));
+            }
+
+            // Look for the first file in the legacy scheme. The number is the
+            // last run of digits.
+            let Some(end) = name.rfind(|c: char| c.is_ascii_digit()) else {
+                continue;
+            };
+            let start = name[..end]
+                .rfind(|c: char| !c.is_ascii_digit())
+                .map_or(0, |i| i + 1);
+            let number = &name[start..=end];
+
+            if number.len() < 3 || number.parse::<u32>() != Ok(1) {
+                continue;
+            }
+
+            let scheme = Self::Legacy {
+                prefix: name[..start].to_owned(),
+                suffix: name[end + 1..].to_owned(),
+                digits: number.len() as u8,
+            };
+            let count = (0..)
+                .take_while(|i| names.contains(&scheme.name(*i)))
+                .count() as u32;
+
+            if count > 1 {
+                result.push((scheme, count));
+            }
+        }
+
+        result
+    }
+
+    /// Short human-readable name of the naming scheme.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::NotZip => "not-zip",
+            Self::NotSplit { .. } => "not-split",
+            Self::Legacy { .. } => "legacy",
+            Self::Standard { .. } => "standard",
+        }
+    }
+
+    pub fn name(&self, index: u32) -> String {
         match self {
             Self::NotZip => String::new(),
             Self::NotSplit { name } => name.clone(),
@@ -337,6 +574,8 @@ pub struct FileInfo {
     zip_size: u64,
     /// Naming scheme for split zip files.
     zip_naming: ZipNamingScheme,
+    /// Whether the server returned a size that overflowed.
+    size_overflowed: bool,
 }
 
 impl TryFrom<File> for FileInfo {
@@ -362,6 +601,7 @@ impl TryFrom<File> for FileInfo {
 
         // For older models, the server returns the sizes as signed 32-bit
         // integers that overflow.
+        let size_overflowed = size < 0 || zip_size < 0;
         if size < 0 {
             size = (size as i32).cast_unsigned().into();
         }
@@ -389,6 +629,7 @@ impl TryFrom<File> for FileInfo {
             zip_count,
             zip_size: zip_size.cast_unsigned(),
             zip_naming,
+            size_overflowed,
         })
     }
 }
@@ -412,6 +653,18 @@ impl FileInfo {
         self.join_path(&self.name)
     }
 
+    /// Naming scheme for the split zip files.
+    pub fn zip_naming(&self) -> &ZipNamingScheme {
+        &self.zip_naming
+    }
+
+    /// Whether the server reported a size that overflowed a signed 32-bit
+    /// integer. If so, the sizes may have wrapped around more than once and
+    /// cannot be trusted.
+    pub fn has_overflowed_size(&self) -> bool {
+        self.size_overflowed
+    }
+
     /// Whether this file is composed of split zips.
     pub fn is_split(&self) -> bool {
         self.zip_count > 0
@@ -479,6 +732,21 @@ pub struct FirmwareInfo {
     pub files: Vec<FileInfo>,
 }
 
+impl FirmwareInfo {
+    /// Whether the reported sizes can be trusted. Sizes that overflowed may
+    /// have wrapped around to a positive value, so this also requires the
+    /// total size to match the sum of the file sizes.
+    pub fn has_reliable_sizes(&self) -> bool {
+        self.size == self.files.iter().map(|f| f.size).sum::<u64>()
+            && !self.files.iter().any(|f| f.has_overflowed_size())
+    }
+
+    /// Get the full URL for a specific download of a file.
+    pub fn download_url(&self, file: &FileInfo, index: u32) -> String {
+        format!("{}/{}", self.base_url, file.download_remote_path(index))
+    }
+}
+
 impl TryFrom<CarDownloadData> for FirmwareInfo {
     type Error = Error;
 
@@ -537,12 +805,34 @@ impl TryFrom<CarDownloadData> for FirmwareInfo {
 #[derive(Clone)]
 pub struct NuClientBuilder {
     ignore_tls_validation: bool,
+    api_url: String,
+    api_url_eu: String,
+    region_api_urls: BTreeMap<String, String>,
+    credentials: Option<Credentials>,
+    proxy: Option<ProxyConfig>,
+    ca_cert_files: Vec<PathBuf>,
+    pinned_public_keys: Vec<SpkiPin>,
+    schema_mode: SchemaMode,
+    recording: Option<Recording>,
+    retry_policy: RetryPolicy,
+    connect_timeout: Option<Duration>,
 }
 
 impl NuClientBuilder {
     pub fn new() -> Self {
         Self {
             ignore_tls_validation: false,
+            api_url: BASE_URL.to_owned(),
+            api_url_eu: BASE_URL_EU.to_owned(),
+            region_api_urls: BTreeMap::new(),
+            credentials: None,
+            proxy: None,
+            ca_cert_files: vec![],
+            pinned_public_keys: vec![],
+            schema_mode: SchemaMode::default(),
+            recording: None,
+            retry_policy: RetryPolicy::default(),
+            connect_timeout: None,
         }
     }
 
@@ -553,42 +843,356 @@ impl NuClientBuilder {
         self
     }
 
+    /// Set the base URL for the API endpoint used by most regions. By default,
+    /// this is [`BASE_URL`].
+    pub fn api_url(mut self, url: impl Into<String>) -> Self {
+        self.api_url = url.into();
+        self
+    }
+
+    /// Set the base URL for the API endpoint used by the `EU`, `RU`, and `TR`
+    /// regions. By default, this is [`BASE_URL_EU`].
+    pub fn api_url_eu(mut self, url: impl Into<String>) -> Self {
+        self.api_url_eu = url.into();
+        self
+    }
+
+    /// Route requests for the specified region to a specific base URL. This
+    /// takes precedence over [`Self::api_url`] and [`Self::api_url_eu`].
+    pub fn region_api_url(mut self, region: impl Into<String>, url: impl Into<String>) -> Self {
+        self.region_api_urls.insert(region.into(), url.into());
+        self
+    }
+
+    /// Set the credentials to use for listing cars. By default, requests are
+    /// anonymous.
+    pub fn credentials(mut self, credentials: Option<Credentials>) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Send all requests through a proxy. By default, the proxy is configured
+    /// from the standard `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY`, and
+    /// `NO_PROXY` environment variables.
+    pub fn proxy(mut self, proxy: Option<ProxyConfig>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Trust the certificates in the specified PEM files in addition to the
+    /// system trust store.
+    pub fn ca_cert_files(mut self, paths: Vec<PathBuf>) -> Self {
+        self.ca_cert_files = paths;
+        self
+    }
+
+    /// Require the end-entity certificate for the API hosts to have a public
+    /// key matching one of the specified pins. Firmware downloads are not
+    /// pinned because they are served by a CDN. By default, no pinning is
+    /// done.
+    pub fn pinned_public_keys(mut self, pins: Vec<SpkiPin>) -> Self {
+        self.pinned_public_keys = pins;
+        self
+    }
+
+    /// Set how to handle response fields that are not part of the known
+    /// schema. By default, [`SchemaMode::Lenient`] is used.
+    pub fn schema_mode(mut self, mode: SchemaMode) -> Self {
+        self.schema_mode = mode;
+        self
+    }
+
+    /// Record every request and response to a directory or replay them from
+    /// one instead of contacting the server. By default, nothing is recorded.
+    pub fn recording(mut self, recording: Option<Recording>) -> Self {
+        self.recording = recording;
+        self
+    }
+
+    /// Set how to retry API requests that fail with transient errors. This
+    /// does not apply to firmware downloads, which are retried by the caller.
+    /// By default, [`RetryPolicy::default`] is used.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Set the timeout for establishing connections, including for firmware
+    /// downloads. By default, there is no timeout.
+    pub fn connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
     /// Build the [`NuClient`] with the current options. This will fail if the
-    /// TLS backend fails to initialize.
+    /// CA certificates cannot be loaded or if the TLS backend fails to
+    /// initialize.
     pub fn build(&self) -> Result<NuClient> {
         NuClient::with_options(self)
     }
 }
 
+impl Default for NuClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Client for interacting with the NU service.
 pub struct NuClient {
     client: Client,
+    api_url: String,
+    api_url_eu: String,
+    region_api_urls: BTreeMap<String, String>,
+    credentials: Option<Credentials>,
+    schema_mode: SchemaMode,
+    recording: Option<Recording>,
+    retry_policy: RetryPolicy,
 }
 
 impl NuClient {
     fn with_options(options: &NuClientBuilder) -> Result<Self> {
         debug!("TLS validation enabled: {}", !options.ignore_tls_validation);
-
-        let client = ClientBuilder::new()
+        debug!("API URL: {}", options.api_url);
+        debug!("EU API URL: {}", options.api_url_eu);
+        debug!("Region API URLs: {:?}", options.region_api_urls);
+        debug!("Credentials: {:?}", options.credentials);
+        debug!("Proxy: {:?}", options.proxy);
+        debug!("CA certificate files: {:?}", options.ca_cert_files);
+        debug!("Pinned public keys: {:?}", options.pinned_public_keys);
+        debug!("Recording: {:?}", options.recording);
+        debug!("Retry policy: {:?}", options.retry_policy);
+        debug!("Connect timeout: {:?}", options.connect_timeout);
+
+        let mut builder = ClientBuilder::new()
             .danger_accept_invalid_certs(options.ignore_tls_validation)
-            .referer(false)
-            .build()?;
+            .referer(false);
+
+        if let Some(timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if !options.ignore_tls_validation {
+            let mut ca_certs = vec![];
+            for path in &options.ca_cert_files {
+                ca_certs.extend(tls::load_certs(path)?);
+            }
 
-        Ok(Self { client })
+            if !options.pinned_public_keys.is_empty() {
+                builder =
+                    builder.tls_backend_preconfigured(Self::pinned_tls_config(options, ca_certs)?);
+            } else if !ca_certs.is_empty() {
+                let certs = ca_certs
+                    .iter()
+                    .map(|c| Certificate::from_der(c))
+                    .collect::<reqwest::Result<Vec<_>>>()?;
+
+                builder = builder.tls_certs_merge(certs);
+            }
+        }
+
+        // Setting an explicit proxy disables the environment variables.
+        if let Some(config) = &options.proxy {
+            let mut proxy = Proxy::all(&config.url)?;
+
+            if let Some(credentials) = &config.credentials {
+                proxy = proxy.basic_auth(&credentials.user_id, &credentials.password);
+            }
+
+            let no_proxy = match &config.no_proxy {
+                Some(hosts) => NoProxy::from_string(hosts),
+                None => NoProxy::from_env(),
+            };
+
+            builder = builder.proxy(proxy.no_proxy(no_proxy));
+        }
+
+        let client = builder.build()?;
+
+        // The server does not accept duplicate separators.
+        let trim = |url: &str| url.trim_end_matches('/').to_owned();
+
+        Ok(Self {
+            client,
+            api_url: trim(&options.api_url),
+            api_url_eu: trim(&options.api_url_eu),
+            region_api_urls: options
+                .region_api_urls
+                .iter()
+                .map(|(region, url)| (region.clone(), trim(url)))
+                .collect(),
+            credentials: options.credentials.clone(),
+            schema_mode: options.schema_mode,
+            recording: options.recording.clone(),
+            // Replayed responses never change, so retrying is pointless.
+            retry_policy: match &options.recording {
+                Some(r) if r.mode() == Mode::Replay => RetryPolicy::none(),
+                _ => options.retry_policy.clone(),
+            },
+        })
     }
 
-    async fn exec<T: Debug + DeserializeOwned>(request: RequestBuilder) -> Result<T> {
-        let response = request
-            .header(header::USER_AGENT, USER_AGENT)
-            .send()
-            .await?
-            .error_for_status()?;
+    /// Build a TLS configuration that uses the system trust store plus
+    /// `ca_certs` and also checks the public key pins for the API hosts.
+    fn pinned_tls_config(
+        options: &NuClientBuilder,
+        ca_certs: Vec<rustls::pki_types::CertificateDer<'static>>,
+    ) -> Result<ClientConfig> {
+        let provider = CryptoProvider::get_default()
+            .cloned()
+            .ok_or(Error::NoCryptoProvider)?;
+
+        let hosts = [&options.api_url, &options.api_url_eu]
+            .into_iter()
+            .chain(options.region_api_urls.values())
+            .filter_map(|u| Url::parse(u).ok()?.host_str().map(|h| h.to_owned()))
+            .collect();
+        debug!("Pinned hosts: {hosts:?}");
+
+        let inner =
+            rustls_platform_verifier::Verifier::new_with_extra_roots(ca_certs, provider.clone())?;
+        let verifier =
+            PinningVerifier::new(Arc::new(inner), hosts, options.pinned_public_keys.clone());
+
+        let mut config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(config)
+    }
+
+    /// Get the base URL for a region.
+    pub fn base_url(&self, region: &str) -> &str {
+        if let Some(url) = self.region_api_urls.get(region) {
+            url
+        } else if EU_API_REGIONS.contains(&region) {
+            &self.api_url_eu
+        } else {
+            &self.api_url
+        }
+    }
+
+    /// Send an API request and return the response status and body. If
+    /// recording is enabled, the exchange is saved or replayed.
+    async fn send(&self, request: Request) -> Result<(StatusCode, HeaderMap, Bytes)> {
+        let Some(recording) = &self.recording else {
+            let response = self.client.execute(request).await?;
+            let status = response.status();
+            let headers = response.headers().clone();
+
+            return Ok((status, headers, response.bytes().await?));
+        };
+
+        let recorded = RecordedRequest::new(&request);
+
+        match recording.mode() {
+            Mode::Record => {
+                let response = self.client.execute(request).await?;
+                let status = response.status();
+                let headers = response.headers().clone();
+                let body = response.bytes().await?;
+
+                recording.save(&Exchange {
+                    request: recorded,
+                    response: RecordedResponse::new(status, &headers, Some(&body)),
+                })?;
+
+                Ok((status, headers, body))
+            }
+            Mode::Replay => {
+                let response = recording.load(&recorded)?;
+
+                Ok((
+                    response.status()?,
+                    response.header_map(),
+                    response.body_bytes().into(),
+                ))
+            }
+        }
+    }
+
+    /// Send an API request and return the response body. Transient failures
+    /// are retried according to the retry policy.
+    async fn fetch(&self, request: Request) -> Result<Bytes> {
+        let url = request.url().to_string();
+        let mut attempt = 0;
+
+        loop {
+            // API request bodies are always buffered, so they can be cloned.
+            let attempt_request = request.try_clone().expect("Request body is not a stream");
+
+            let result = self
+                .send(attempt_request)
+                .await
+                .and_then(|(status, headers, body)| {
+                    check_status(&url, status, &headers)?;
+                    Ok(body)
+                });
+
+            match result {
+                Ok(body) => return Ok(body),
+                Err(e) => {
+                    let Some(delay) = self.retry_policy.retry_delay(attempt, &e) else {
+                        return Err(e);
+                    };
+                    attempt += 1;
 
-        let url = response.url().to_owned();
+                    warn!(
+                        "[Attempt #{attempt}/{}] Request to {url} failed: {e}",
+                        u16::from(self.retry_policy.max_retries) + 1,
+                    );
 
-        let json: ResponseData<T> = response.json().await?;
+                    time::sleep(delay).await;
+                }
+            }
+        }
+    }
+
+    /// Save the headers of a firmware download response if recording is
+    /// enabled. The body is not recorded.
+    fn record_headers(&self, request: RecordedRequest, response: &Response) -> Result<()> {
+        if let Some(recording) = &self.recording
+            && recording.mode() == Mode::Record
+        {
+            recording.save(&Exchange {
+                request,
+                response: RecordedResponse::new(response.status(), response.headers(), None),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    async fn exec<T: Debug + DeserializeOwned + Schema>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T> {
+        let request = request.header(header::USER_AGENT, USER_AGENT).build()?;
+        let url = request.url().to_string();
+
+        let body = self.fetch(request).await?;
+        let json: ResponseData<T> =
+            serde_json::from_slice(&body).map_err(|e| Error::Decode(url.clone(), e))?;
         json.error_for_status()?;
 
+        let mut unknown = BTreeSet::new();
+        json.unknown_fields("", &mut unknown);
+
+        if !unknown.is_empty() {
+            let unknown = unknown.into_iter().collect::<Vec<_>>();
+
+            match self.schema_mode {
+                SchemaMode::Lenient => warn!(
+                    "Schema drift in response to {url}: unknown fields: {}",
+                    unknown.join(", "),
+                ),
+                SchemaMode::Strict => return Err(model::Error::UnknownFields(unknown).into()),
+            }
+        }
+
         debug!("Response to {url}: {json:#?}");
 
         Ok(json.data)
@@ -598,8 +1202,8 @@ impl NuClient {
     /// returns ISO country codes, which may not match a valid region.
     pub async fn get_region(&self) -> Result<String> {
         // The last path component doesn't matter.
-        let url = format!("{BASE_URL}/region/status/KR");
-        let data: RegionStatusData = Self::exec(self.client.get(&url)).await?;
+        let url = format!("{}/region/status/KR", self.api_url);
+        let data: RegionStatusData = self.exec(self.client.get(&url)).await?;
 
         if EU_EEA_ISO_3166_1.contains(&data.region.as_str()) {
             return Ok("EU".to_owned());
@@ -608,90 +1212,144 @@ impl NuClient {
         Ok(data.region)
     }
 
-    /// Check that a region code is actually valid.
-    pub async fn validate_region(&self, brand: &str, region: &str) -> Result<()> {
-        let platform_url = format!("{}/car/platform/{brand}/{}", base_url(region), region);
+    /// Check if a region has any platforms for the brand.
+    pub async fn has_region_data(&self, brand: &str, region: &str) -> Result<bool> {
+        let platform_url = format!("{}/car/platform/{brand}/{region}", self.base_url(region));
 
-        let is_valid = match Self::exec::<Vec<IgnoredAny>>(self.client.get(&platform_url)).await {
-            Ok(platforms) => !platforms.is_empty(),
+        match self
+            .exec::<Vec<IgnoredAny>>(self.client.get(&platform_url))
+            .await
+        {
+            Ok(platforms) => Ok(!platforms.is_empty()),
             // The server sometimes returns a response with no data field or
             // returns HTTP 499. These are both indicative of a bad region.
-            Err(Error::Request(e))
-                if e.is_decode() || e.status().is_some_and(|s| s.as_u16() == 499) =>
-            {
-                false
-            }
-            Err(e) => return Err(e),
-        };
-        if !is_valid {
+            Err(Error::Decode(_, _)) => Ok(false),
+            Err(Error::HttpStatus { status, .. }) if status.as_u16() == 499 => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check that a region code is actually valid.
+    pub async fn validate_region(&self, brand: &str, region: &str) -> Result<()> {
+        if !self.has_region_data(brand, region).await? {
             return Err(Error::RegionNoData(region.to_owned()));
         }
 
         Ok(())
     }
 
+    /// Check if a region has data for the brand and if so, return the number
+    /// of cars that it lists.
+    pub async fn probe_region(&self, brand: &str, region: &str) -> Result<Option<usize>> {
+        if !self.has_region_data(brand, region).await? {
+            return Ok(None);
+        }
+
+        let guid = self.get_guid(region).await?;
+        let cars = self.get_cars(region, &guid, brand).await?;
+
+        Ok(Some(cars.len()))
+    }
+
     /// Request a GUID from the server. A GUID is required for requesting
     /// firmware information with [`Self::get_cars`].
     pub async fn get_guid(&self, region: &str) -> Result<String> {
-        let url = format!("{}/guid/{region}", base_url(region));
-        let data: GuidData = Self::exec(self.client.get(&url)).await?;
+        let url = format!("{}/guid/{region}", self.base_url(region));
+        let data: GuidData = self.exec(self.client.get(&url)).await?;
 
         Ok(data.guid)
     }
 
     /// Get the raw data from the `/car/list` API.
     pub async fn get_cars_raw(&self, region: &str, guid: &str, brand: &str) -> Result<CarListData> {
-        let url = format!("{}/car/list", base_url(region));
+        let url = format!("{}/car/list", self.base_url(region));
+
+        // Credentials are only sent if they were provided. Dealer/technician
+        // accounts are not known to provide access to any additional firmware,
+        // but the server reports whether it accepted them in `userAuth`.
+        let (user_id, user_pw) = match &self.credentials {
+            Some(c) => (encrypt_field(&c.user_id), encrypt_field(&c.password)),
+            None => (String::new(), String::new()),
+        };
 
-        // Only anonymous requests are supported at the moment. There is not
-        // really a benefit to using authenticated requests as an end user.
-        // Dealer/technician credentials may potentially provide access for more
-        // firmware, but that is just a guess.
         let request_json = CarListRequest {
             brand: brand.to_owned(),
             guid: guid.to_owned(),
             region: region.to_owned(),
-            user_id: "".to_owned(),
-            user_pw: "".to_owned(),
+            user_id,
+            user_pw,
+            // The official client always sends `U`, even when logged in.
             user_type: "U".to_owned(),
         };
 
         let authorization = Authorization::new();
 
-        Self::exec(
-            self.client
-                .post(&url)
-                .header(header::AUTHORIZATION, authorization.to_string())
-                .json(&request_json),
-        )
-        .await
+        let data: CarListData = self
+            .exec(
+                self.client
+                    .post(&url)
+                    .header(header::AUTHORIZATION, authorization.to_string())
+                    .json(&request_json),
+            )
+            .await?;
+
+        if let Some(credentials) = &self.credentials {
+            info!(
+                "User authorization for {:?}: {}",
+                credentials.user_id, data.user_auth,
+            );
+        }
+
+        Ok(data)
+    }
+
+    /// Get the list of cars and information about their latest firmware, along
+    /// with the server's authorization status. Old firmware versions are not
+    /// provided by the NU service.
+    pub async fn get_car_list(&self, region: &str, guid: &str, brand: &str) -> Result<CarList> {
+        let data = self.get_cars_raw(region, guid, brand).await?;
+
+        Ok(CarList {
+            user_auth: data.user_auth,
+            cars: data
+                .cars
+                .into_iter()
+                .map(CarInfo::try_from)
+                .collect::<Result<_>>()?,
+        })
     }
 
     /// Get the list of cars and information about their latest firmware. Old
     /// firmware versions are not provided by the NU service.
     pub async fn get_cars(&self, region: &str, guid: &str, brand: &str) -> Result<Vec<CarInfo>> {
-        let data = self.get_cars_raw(region, guid, brand).await?;
-
-        data.cars.into_iter().map(CarInfo::try_from).collect()
+        Ok(self.get_car_list(region, guid, brand).await?.cars)
     }
 
-    /// Get the list of firmware files for the specified car.
-    pub async fn get_firmware_info(&self, region: &str, car: &CarInfo) -> Result<FirmwareInfo> {
-        let url = format!("{}/car/download/{}", base_url(region), car.code);
+    /// Get the raw data from the `/car/download/<code>` API, where `code` is
+    /// [`CarInfo::code`].
+    pub async fn get_firmware_info_raw(&self, region: &str, code: &str) -> Result<CarDownloadData> {
+        let url = format!("{}/car/download/{code}", self.base_url(region));
 
         let authorization = Authorization::new();
-        let data: CarDownloadData = Self::exec(
+
+        self.exec(
             self.client
                 .get(&url)
                 .header(header::AUTHORIZATION, authorization.to_string()),
         )
-        .await?;
+        .await
+    }
+
+    /// Get the list of firmware files for the specified car.
+    pub async fn get_firmware_info(&self, region: &str, car: &CarInfo) -> Result<FirmwareInfo> {
+        let data = self.get_firmware_info_raw(region, &car.code).await?;
 
         FirmwareInfo::try_from(data)
     }
 
     /// Create an async byte stream for downloading the specified firmware with
-    /// the specified byte range.
+    /// the specified byte range. `end` is exclusive. If it is [`None`], the
+    /// range extends to the end of the file.
     ///
     /// Specifying a non-zero `start` value will result in a partial download,
     /// allowing interrupted downloads to be resumed.
@@ -701,28 +1359,46 @@ impl NuClient {
         file: &FileInfo,
         index: u32,
         start: u64,
+        end: Option<u64>,
     ) -> Result<impl Stream<Item = reqwest::Result<Bytes>>> {
-        let url = format!("{}/{}", firmware.base_url, file.download_remote_path(index));
-        debug!("Requesting bytes {start}- from: {url}");
+        let url = firmware.download_url(file, index);
+        // HTTP ranges are inclusive.
+        let range = match end {
+            Some(end) => format!("{start}-{}", end.saturating_sub(1)),
+            None => format!("{start}-"),
+        };
+        debug!("Requesting bytes {range} from: {url}");
 
-        let r = self
+        if let Some(recording) = &self.recording
+            && recording.mode() == Mode::Replay
+        {
+            return Err(recording::Error::FirmwareReplay(url).into());
+        }
+
+        let request = self
             .client
             .get(&url)
             .header(header::USER_AGENT, USER_AGENT)
-            .header(header::RANGE, format!("bytes={start}-"))
-            .send()
-            .await?;
+            .header(header::RANGE, format!("bytes={range}"))
+            .build()?;
+        let recorded = RecordedRequest::new(&request);
+
+        let r = self.client.execute(request).await?;
+        self.record_headers(recorded, &r)?;
 
         let status = r.status();
 
         if status == StatusCode::RANGE_NOT_SATISFIABLE {
-            let head_r = self
+            let head_request = self
                 .client
                 .head(&url)
                 .header(header::USER_AGENT, USER_AGENT)
-                .send()
-                .await?
-                .error_for_status()?;
+                .build()?;
+            let recorded = RecordedRequest::new(&head_request);
+
+            let head_r = self.client.execute(head_request).await?;
+            self.record_headers(recorded, &head_r)?;
+            check_status(&url, head_r.status(), head_r.headers())?;
             let size = head_r
                 .headers()
                 .get(header::CONTENT_LENGTH)
@@ -734,7 +1410,7 @@ impl NuClient {
             }
         }
 
-        r.error_for_status_ref()?;
+        check_status(&url, status, r.headers())?;
 
         if status != StatusCode::PARTIAL_CONTENT {
             let size = r
@@ -749,6 +1425,22 @@ impl NuClient {
             if size.is_none_or(|s| s > 0 || status != StatusCode::OK) {
                 return Err(Error::BadHttpResponse(StatusCode::PARTIAL_CONTENT, status));
             }
+        } else {
+            // Otherwise, the data would be written to the wrong offset.
+            let actual = r
+                .headers()
+                .get(header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok());
+            let valid = actual
+                .and_then(parse_content_range)
+                .is_some_and(|(first, last)| first == start && end.is_none_or(|e| last + 1 == e));
+
+            if !valid {
+                return Err(Error::BadContentRange {
+                    expected: range,
+                    actual: actual.map(|v| v.to_owned()),
+                });
+            }
         }
 
         Ok(r.bytes_stream())
@@ -760,6 +1452,7 @@ mod tests {
     use jiff::civil::date;
 
     use super::*;
+    use crate::mock::{MockCar, MockFile, MockServer, TempDir, mock_car, test_data};
 
     #[test]
     fn test_authorization() {
@@ -770,4 +1463,362 @@ mod tests {
             "Basic y4KNGY3f0lkJ/GWYoxnmRQ==",
         );
     }
+
+    #[test]
+    fn test_detect_zip_naming() {
+        let names = [
+            "a.z01",
+            "a.z02",
+            "a.zip",
+            "b.zip",
+            "c001.bin",
+            "c002.bin",
+            "c003.bin",
+            "d001.bin",
+            "e.z02",
+            "e.zip",
+            "other.txt",
+        ]
+        .into_iter()
+        .map(|n| n.to_owned())
+        .collect::<BTreeSet<_>>();
+
+        let standard = |base_name: &str, count| {
+            let scheme = ZipNamingScheme::Standard {
+                base_name: base_name.to_owned(),
+                count,
+            };
+            (scheme, count)
+        };
+
+        assert_eq!(
+            ZipNamingScheme::detect(&names),
+            [
+                standard("a", 3),
+                standard("b", 1),
+                (
+                    ZipNamingScheme::Legacy {
+                        prefix: "c".to_owned(),
+                        suffix: ".bin".to_owned(),
+                        digits: 3,
+                    },
+                    3,
+                ),
+                standard("e", 1),
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        let server = MockServer::start(vec![]).await;
+        // The API host does not exist, so this only works if the request is
+        // sent to the proxy.
+        let client = NuClientBuilder::new()
+            .api_url("http://nudl.invalid/api/v3")
+            .proxy(Some(ProxyConfig {
+                url: server.url(),
+                credentials: None,
+                no_proxy: None,
+            }))
+            .build()
+            .unwrap();
+
+        client.get_guid("US").await.unwrap();
+        assert!(
+            server.requests()[0].starts_with("GET http://nudl.invalid/api/v3/"),
+            "{:?}",
+            server.requests(),
+        );
+
+        // Nothing is listening on the proxy, so this only works if the request
+        // bypasses it.
+        let client = NuClientBuilder::new()
+            .api_url(server.api_url())
+            .proxy(Some(ProxyConfig {
+                url: "socks5://127.0.0.1:1".to_owned(),
+                credentials: None,
+                no_proxy: Some("127.0.0.1".to_owned()),
+            }))
+            .build()
+            .unwrap();
+
+        client.get_guid("US").await.unwrap();
+    }
+
+    #[test]
+    fn test_base_url() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        let client = NuClientBuilder::new()
+            .api_url("http://localhost:8080/api/v3/")
+            .api_url_eu("http://localhost:8081/api/v3")
+            .region_api_url("TR", "http://localhost:8082/api/v3")
+            .region_api_url("US", "http://localhost:8083/api/v3")
+            .build()
+            .unwrap();
+
+        assert_eq!(client.base_url("KR"), "http://localhost:8080/api/v3");
+        assert_eq!(client.base_url("EU"), "http://localhost:8081/api/v3");
+        assert_eq!(client.base_url("RU"), "http://localhost:8081/api/v3");
+        assert_eq!(client.base_url("TR"), "http://localhost:8082/api/v3");
+        assert_eq!(client.base_url("US"), "http://localhost:8083/api/v3");
+    }
+
+    #[tokio::test]
+    async fn test_probe_region() {
+        let car = MockCar {
+            files: vec![MockFile::new("file.bin", test_data(16, 1), 0)],
+            ..mock_car()
+        };
+        let server = MockServer::start(vec![car]).await;
+        server.add_region("CA");
+        let client = server.client();
+
+        assert_eq!(client.probe_region("KM", "US").await.unwrap(), Some(1));
+        assert_eq!(client.probe_region("KM", "CA").await.unwrap(), Some(1));
+        assert_eq!(client.probe_region("KM", "KR").await.unwrap(), None);
+        assert_eq!(client.probe_region("HM", "US").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_pinned_public_keys() {
+        let server = MockServer::start(vec![]).await;
+        let pin = "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+            .parse()
+            .unwrap();
+
+        // Pinning only applies to TLS connections.
+        let client = NuClientBuilder::new()
+            .api_url(server.api_url())
+            .pinned_public_keys(vec![pin])
+            .build()
+            .unwrap();
+
+        client.get_guid("US").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_cars_and_firmware_info() {
+        let car = MockCar {
+            name: "2026 Car\u{a0}Name".to_owned(),
+            files: vec![
+                MockFile::new("split.tar", test_data(4096, 1), 3),
+                MockFile::new("unsplit.bin", test_data(16, 2), 0).with_directory("sub/dir"),
+            ],
+            ..mock_car()
+        };
+        let server = MockServer::start(vec![car.clone()]).await;
+        let client = server.client();
+
+        assert_eq!(client.get_region().await.unwrap(), "US");
+        client.validate_region("KM", "US").await.unwrap();
+        assert!(matches!(
+            client.validate_region("KM", "KR").await,
+            Err(Error::RegionNoData(r)) if r == "KR",
+        ));
+        assert!(matches!(
+            client.validate_region("HM", "US").await,
+            Err(Error::RegionNoData(_)),
+        ));
+
+        let guid = client.get_guid("US").await.unwrap();
+        let cars = client.get_cars("US", &guid, "KM").await.unwrap();
+        assert_eq!(cars.len(), 1);
+        assert_eq!(cars[0].id, "CAR1");
+        assert_eq!(cars[0].name, "2026 Car Name");
+        assert_eq!(cars[0].versions, car.versions);
+        assert_eq!(cars[0].media_type, "USB");
+        assert!(client.get_cars("US", &guid, "HM").await.unwrap().is_empty());
+
+        let firmware = client.get_firmware_info("US", &cars[0]).await.unwrap();
+        assert_eq!(firmware.update_version, "26Q1");
+        assert_eq!(firmware.size, 4096 + 16);
+        assert_eq!(firmware.files.len(), 2);
+
+        let split = &firmware.files[0];
+        assert!(split.is_split());
+        assert_eq!(split.crc32, crc32fast::hash(&car.files[0].data));
+        assert_eq!(split.size, 4096);
+        assert_eq!(split.download_count(), 3);
+        assert_eq!(split.download_name(0), "split.z01");
+        assert_eq!(split.download_name(1), "split.z02");
+        assert_eq!(split.download_name(2), "split.zip");
+        assert_eq!(split.download_remote_path(2), "CAR1%2Fsplit.zip");
+        assert_eq!(split.zip_naming().kind(), "standard");
+        let url = firmware.download_url(split, 2);
+        assert!(url.starts_with(&server.url()), "{url}");
+        assert!(url.ends_with("/CAR1%2Fsplit.zip"), "{url}");
+
+        let unsplit = &firmware.files[1];
+        assert!(!unsplit.is_split());
+        assert_eq!(unsplit.path(), "sub/dir/unsplit.bin");
+        assert_eq!(unsplit.download_count(), 1);
+        assert_eq!(unsplit.download_size(), 16);
+        assert_eq!(unsplit.zip_naming().kind(), "not-zip");
+    }
+
+    #[tokio::test]
+    async fn test_get_cars_authenticated() {
+        let car = MockCar {
+            files: vec![MockFile::new("file.bin", test_data(16, 1), 0)],
+            ..mock_car()
+        };
+        let server = MockServer::start(vec![car]).await;
+        server.add_account("user", "pass");
+
+        let client_with = |user_id: &str, password: &str| {
+            NuClientBuilder::new()
+                .api_url(server.api_url())
+                .credentials(Some(Credentials {
+                    user_id: user_id.to_owned(),
+                    password: password.to_owned(),
+                }))
+                .build()
+                .unwrap()
+        };
+
+        let guid = server.client().get_guid("US").await.unwrap();
+
+        let data = server.client().get_cars_raw("US", &guid, "KM").await;
+        assert_eq!(data.unwrap().user_auth, "N");
+
+        let data = client_with("user", "pass")
+            .get_cars_raw("US", &guid, "KM")
+            .await;
+        assert_eq!(data.unwrap().user_auth, "M");
+
+        let list = client_with("user", "pass")
+            .get_car_list("US", &guid, "KM")
+            .await
+            .unwrap();
+        assert_eq!(list.user_auth, "M");
+        assert_eq!(list.cars.len(), 1);
+
+        let data = client_with("user", "wrong")
+            .get_cars_raw("US", &guid, "KM")
+            .await;
+        assert_eq!(data.unwrap().user_auth, "N");
+    }
+
+    #[tokio::test]
+    async fn test_schema_drift() {
+        let car = MockCar {
+            files: vec![MockFile::new("file.bin", test_data(16, 1), 0)],
+            ..mock_car()
+        };
+        let server = MockServer::start(vec![car]).await;
+        server.add_unknown_car_field("newField", serde_json::json!("value"));
+
+        let client = server.client();
+        let guid = client.get_guid("US").await.unwrap();
+
+        let data = client.get_cars_raw("US", &guid, "KM").await.unwrap();
+        assert_eq!(
+            data.cars[0].unknown.get("newField"),
+            Some(&serde_json::json!("value")),
+        );
+        let raw = serde_json::to_value(&data).unwrap();
+        assert_eq!(raw["cars"][0]["newField"], "value");
+
+        let cars = client.get_cars("US", &guid, "KM").await.unwrap();
+        assert_eq!(cars[0].id, "CAR1");
+
+        let strict = NuClientBuilder::new()
+            .api_url(server.api_url())
+            .schema_mode(SchemaMode::Strict)
+            .build()
+            .unwrap();
+        let err = strict.get_cars_raw("US", &guid, "KM").await.unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                Error::Model(model::Error::UnknownFields(f)) if f == &["data.cars[].newField"],
+            ),
+            "{err:?}",
+        );
+        strict.get_guid("US").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_replay() {
+        let car = MockCar {
+            files: vec![MockFile::new("file.bin", test_data(16, 1), 0)],
+            ..mock_car()
+        };
+        let server = MockServer::start(vec![car]).await;
+        let temp_dir = TempDir::new();
+        let api_url = server.api_url();
+
+        let client_with = |mode| {
+            NuClientBuilder::new()
+                .api_url(&api_url)
+                .credentials(Some(Credentials {
+                    user_id: "user".to_owned(),
+                    password: "pass".to_owned(),
+                }))
+                .recording(Some(Recording::new(temp_dir.path().to_owned(), mode)))
+                .build()
+                .unwrap()
+        };
+
+        let client = client_with(Mode::Record);
+        let guid = client.get_guid("US").await.unwrap();
+        let cars = client.get_cars("US", &guid, "KM").await.unwrap();
+        let firmware = client.get_firmware_info("US", &cars[0]).await.unwrap();
+        drop(
+            client
+                .download(&firmware, &firmware.files[0], 0, 4, None)
+                .await
+                .unwrap(),
+        );
+        assert!(!client.has_region_data("KM", "KR").await.unwrap());
+
+        let requests = server.requests().len();
+        let names = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), requests, "{names:?}");
+
+        let mut downloads = 0;
+
+        for name in &names {
+            let data = std::fs::read_to_string(temp_dir.path().join(name)).unwrap();
+            assert!(!data.contains(&encrypt_field("pass")), "{data}");
+
+            // Only the headers of firmware downloads are recorded.
+            let exchange: Exchange = serde_json::from_str(&data).unwrap();
+            if exchange.request.range.is_some() {
+                assert_eq!(exchange.request.range.as_deref(), Some("bytes=4-"));
+                assert_eq!(exchange.response.status, 206);
+                assert!(exchange.response.body.is_none());
+                assert!(exchange.response.body_text.is_none());
+                downloads += 1;
+            }
+        }
+
+        assert_eq!(downloads, 1);
+
+        drop(server);
+
+        let client = client_with(Mode::Replay);
+        let guid = client.get_guid("US").await.unwrap();
+        let replayed = client.get_cars("US", &guid, "KM").await.unwrap();
+        assert_eq!(replayed[0].versions, cars[0].versions);
+        let replayed = client.get_firmware_info("US", &cars[0]).await.unwrap();
+        assert_eq!(replayed.files[0].crc32, firmware.files[0].crc32);
+        assert!(!client.has_region_data("KM", "KR").await.unwrap());
+
+        assert!(matches!(
+            client
+                .download(&firmware, &firmware.files[0], 0, 4, None)
+                .await,
+            Err(Error::Recording(recording::Error::FirmwareReplay(_))),
+        ));
+        assert!(matches!(
+            client.get_guid("KR").await,
+            Err(Error::Recording(recording::Error::NotRecorded(_, _))),
+        ));
+    }
 }
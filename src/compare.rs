@@ -0,0 +1,385 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{collections::BTreeSet, fmt};
+
+use serde::Serialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::client::{CarInfo, FirmwareInfo};
+
+/// Checksum and size of a firmware file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct FileSummary {
+    pub crc32: u32,
+    pub size: u64,
+}
+
+impl fmt::Display for FileSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08X} {}", self.crc32, self.size)
+    }
+}
+
+/// A firmware file in a single region.
+#[derive(Clone, Debug, Serialize)]
+pub struct RegionFile {
+    pub path: String,
+    #[serde(flatten)]
+    pub summary: FileSummary,
+}
+
+/// Firmware for a model in a single region.
+#[derive(Clone, Debug, Serialize)]
+pub struct RegionFirmware {
+    pub versions: Vec<String>,
+    pub update_version: String,
+    /// Files sorted by path.
+    pub files: Vec<RegionFile>,
+}
+
+impl RegionFirmware {
+    pub fn new(car: &CarInfo, firmware: &FirmwareInfo) -> Self {
+        let mut files = firmware
+            .files
+            .iter()
+            .map(|f| RegionFile {
+                path: f.path(),
+                summary: FileSummary {
+                    crc32: f.crc32,
+                    size: f.size,
+                },
+            })
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Self {
+            versions: car.versions.clone(),
+            update_version: firmware.update_version.clone(),
+            files,
+        }
+    }
+
+    fn file(&self, path: &str) -> Option<FileSummary> {
+        self.files
+            .binary_search_by(|f| f.path.as_str().cmp(path))
+            .ok()
+            .map(|i| self.files[i].summary)
+    }
+}
+
+/// Firmware for a model in a region or the reason why it is unavailable.
+#[derive(Clone, Debug, Serialize)]
+pub struct RegionResult {
+    pub region: String,
+    pub firmware: Option<RegionFirmware>,
+    pub error: Option<String>,
+}
+
+/// A single file across all regions.
+#[derive(Clone, Debug, Serialize)]
+pub struct FileComparison {
+    pub path: String,
+    /// File in each region, in the same order as [`Comparison::regions`].
+    /// This is [`None`] if the region does not have the file.
+    pub regions: Vec<Option<FileSummary>>,
+    pub identical: bool,
+}
+
+/// Comparison of a model's firmware across regions.
+#[derive(Clone, Debug, Serialize)]
+pub struct Comparison {
+    pub model: String,
+    pub regions: Vec<RegionResult>,
+    pub versions_identical: bool,
+    pub update_version_identical: bool,
+    pub files: Vec<FileComparison>,
+    /// Whether every region has byte-identical firmware files. The version
+    /// strings are not compared because they include the market code.
+    pub identical: bool,
+}
+
+/// Check if all items are equal. Missing items are never equal to anything.
+fn all_equal<T: PartialEq>(mut iter: impl Iterator<Item = Option<T>>) -> bool {
+    let Some(Some(first)) = iter.next() else {
+        return false;
+    };
+
+    iter.all(|item| item.as_ref() == Some(&first))
+}
+
+impl Comparison {
+    pub fn new(model: String, regions: Vec<RegionResult>) -> Self {
+        let firmware = || regions.iter().map(|r| r.firmware.as_ref());
+        // Regions without firmware are reported separately and do not make
+        // the remaining regions differ.
+        let available = || firmware().flatten();
+
+        let versions_identical = all_equal(available().map(|f| Some(&f.versions)));
+        let update_version_identical = all_equal(available().map(|f| Some(&f.update_version)));
+
+        let paths = firmware()
+            .flatten()
+            .flat_map(|f| f.files.iter().map(|f| f.path.as_str()))
+            .collect::<BTreeSet<_>>();
+
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let regions = firmware()
+                    .map(|f| f.and_then(|f| f.file(path)))
+                    .collect::<Vec<_>>();
+
+                FileComparison {
+                    path: path.to_owned(),
+                    identical: all_equal(available().map(|f| f.file(path))),
+                    regions,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let identical = regions.iter().all(|r| r.firmware.is_some())
+            && !files.is_empty()
+            && files.iter().all(|f| f.identical);
+
+        Self {
+            model,
+            regions,
+            versions_identical,
+            update_version_identical,
+            files,
+            identical,
+        }
+    }
+
+    /// Regions that have no firmware for the model.
+    pub fn missing_regions(&self) -> impl Iterator<Item = &str> {
+        self.regions
+            .iter()
+            .filter(|r| r.firmware.is_none())
+            .map(|r| r.region.as_str())
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const HEADING_REGION: &str = "REGION";
+        const HEADING_UPDATE_VERSION: &str = "UPDATE";
+        const HEADING_VERSIONS: &str = "VERSIONS";
+        const HEADING_FILE: &str = "FILE";
+        const MISSING: &str = "-";
+
+        let region_width = self
+            .regions
+            .iter()
+            .map(|r| r.region.width())
+            .max()
+            .unwrap_or_default()
+            .max(HEADING_REGION.width());
+        let update_width = self
+            .regions
+            .iter()
+            .filter_map(|r| r.firmware.as_ref())
+            .map(|f| f.update_version.width())
+            .max()
+            .unwrap_or_default()
+            .max(HEADING_UPDATE_VERSION.width());
+
+        writeln!(f, "Model: {}", self.model)?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{HEADING_REGION:region_width$} {HEADING_UPDATE_VERSION:update_width$} \
+            {HEADING_VERSIONS}",
+        )?;
+
+        for result in &self.regions {
+            match (&result.firmware, &result.error) {
+                (Some(firmware), _) => writeln!(
+                    f,
+                    "{:region_width$} {:update_width$} {}",
+                    result.region,
+                    firmware.update_version,
+                    firmware.versions.join(", "),
+                )?,
+                (None, error) => writeln!(
+                    f,
+                    "{:region_width$} {MISSING:update_width$} {}",
+                    result.region,
+                    error.as_deref().unwrap_or(MISSING),
+                )?,
+            }
+        }
+
+        if !self.files.is_empty() {
+            // Differing files are marked with `*`.
+            let path_width = self
+                .files
+                .iter()
+                .map(|c| c.path.width())
+                .max()
+                .unwrap_or_default()
+                .max(HEADING_FILE.width());
+            let column_width = self
+                .regions
+                .iter()
+                .map(|r| r.region.width())
+                .chain(self.files.iter().flat_map(|c| {
+                    c.regions
+                        .iter()
+                        .map(|s| s.map_or(MISSING.width(), |s| s.to_string().width()))
+                }))
+                .max()
+                .unwrap_or_default();
+
+            writeln!(f)?;
+            write!(f, "  {HEADING_FILE:path_width$}")?;
+            for result in &self.regions {
+                write!(f, "  {:column_width$}", result.region)?;
+            }
+            writeln!(f)?;
+
+            for comparison in &self.files {
+                let marker = if comparison.identical { ' ' } else { '*' };
+                write!(f, "{marker} {:path_width$}", comparison.path)?;
+
+                for summary in &comparison.regions {
+                    let value = summary.map_or_else(|| MISSING.to_owned(), |s| s.to_string());
+                    write!(f, "  {value:column_width$}")?;
+                }
+
+                writeln!(f)?;
+            }
+        }
+
+        writeln!(f)?;
+
+        for region in self.missing_regions() {
+            writeln!(f, "Region {region} has no firmware")?;
+        }
+
+        let differing = self.files.iter().filter(|c| !c.identical).count();
+
+        if self.files.is_empty() {
+            write!(f, "No firmware to compare")?;
+        } else if differing > 0 {
+            write!(
+                f,
+                "Firmware differs between regions ({differing} of {} files differ)",
+                self.files.len(),
+            )?;
+        } else if self.identical {
+            write!(f, "Firmware is identical in all regions")?;
+        } else {
+            write!(f, "Firmware is identical in all regions that have it")?;
+        }
+
+        let versions_differ = !self.versions_identical || !self.update_version_identical;
+
+        if !self.files.is_empty() && versions_differ {
+            write!(f, "\nVersion strings differ between regions")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn firmware(update_version: &str, files: &[(&str, u32)]) -> RegionFirmware {
+        RegionFirmware {
+            versions: vec!["CAR1.USA.S5W_L.001.001.260101".to_owned()],
+            update_version: update_version.to_owned(),
+            files: files
+                .iter()
+                .map(|(path, crc32)| RegionFile {
+                    path: (*path).to_owned(),
+                    summary: FileSummary {
+                        crc32: *crc32,
+                        size: 1,
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    fn result(region: &str, firmware: Option<RegionFirmware>) -> RegionResult {
+        RegionResult {
+            region: region.to_owned(),
+            firmware,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let comparison = Comparison::new(
+            "CAR1".to_owned(),
+            vec![
+                result("US", Some(firmware("26Q1", &[("a", 1), ("b", 2)]))),
+                result("CA", Some(firmware("26Q1", &[("a", 1), ("b", 2)]))),
+            ],
+        );
+        assert!(comparison.identical);
+        assert!(comparison.to_string().ends_with("identical in all regions"));
+
+        // The version strings include the market code, so they differ even
+        // if the files are byte-identical.
+        let mut ca = firmware("26Q1_CA", &[("a", 1), ("b", 2)]);
+        ca.versions = vec!["CAR1.CAN.S5W_L.001.001.260101".to_owned()];
+        let comparison = Comparison::new(
+            "CAR1".to_owned(),
+            vec![
+                result("US", Some(firmware("26Q1", &[("a", 1), ("b", 2)]))),
+                result("CA", Some(ca)),
+            ],
+        );
+        assert!(comparison.identical);
+        assert!(!comparison.versions_identical);
+        assert!(!comparison.update_version_identical);
+        assert!(
+            comparison
+                .to_string()
+                .ends_with("identical in all regions\nVersion strings differ between regions"),
+            "{comparison}",
+        );
+
+        let comparison = Comparison::new(
+            "CAR1".to_owned(),
+            vec![
+                result("US", Some(firmware("26Q1", &[("a", 1), ("b", 2)]))),
+                result("CA", Some(firmware("26Q1", &[("a", 1), ("c", 3)]))),
+                result("KR", Some(firmware("26Q1", &[("a", 1), ("b", 4)]))),
+            ],
+        );
+        assert!(!comparison.identical);
+        assert!(comparison.versions_identical);
+        assert!(comparison.update_version_identical);
+        assert_eq!(
+            comparison
+                .files
+                .iter()
+                .map(|c| (c.path.as_str(), c.identical))
+                .collect::<Vec<_>>(),
+            [("a", true), ("b", false), ("c", false)],
+        );
+        assert_eq!(comparison.files[2].regions[0], None);
+
+        let comparison = Comparison::new(
+            "CAR1".to_owned(),
+            vec![
+                result("US", Some(firmware("26Q1", &[("a", 1)]))),
+                result("CA", None),
+                result("KR", Some(firmware("26Q1", &[("a", 1)]))),
+            ],
+        );
+        assert!(!comparison.identical);
+        assert!(comparison.files[0].identical);
+        assert_eq!(comparison.missing_regions().collect::<Vec<_>>(), ["CA"]);
+        let text = comparison.to_string();
+        assert!(text.contains("Region CA has no firmware"), "{text}");
+        assert!(!text.contains("differs"), "{text}");
+    }
+}
@@ -2,24 +2,25 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 use std::{
-    collections::VecDeque,
-    io::{self, Read, Seek, SeekFrom, Write},
+    collections::{HashMap, VecDeque},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
-    sync::{Arc, atomic::AtomicBool},
+    sync::{Arc, Mutex, atomic::AtomicBool, mpsc as std_mpsc},
     time::Duration,
 };
 
-use anyhow::{Context, Result, bail};
 use cap_std::fs::{Dir, Metadata, OpenOptions};
 use crc32fast::Hasher;
-use flate2::read::DeflateDecoder;
+use flate2::{bufread, read::DeflateDecoder};
 use rawzip::{CompressionMethod, RECOMMENDED_BUFFER_SIZE, ZipArchive};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use tokio::{
     fs::File,
     io::{AsyncSeekExt, AsyncWriteExt},
-    sync::mpsc,
-    task::{self, JoinSet},
-    time,
+    sync::mpsc::{self, error::SendError},
+    task::{self, JoinError, JoinSet},
+    time::{self, Instant},
 };
 use tokio_stream::StreamExt;
 use tracing::{debug, trace, warn};
@@ -30,23 +31,135 @@ use zipunsplitlib::{
 
 use crate::{
     cancel::{CancelOnDrop, check_cancel},
-    client::{self, CarInfo, FirmwareInfo, NuClient},
+    client::{self, CarInfo, FileInfo, FirmwareInfo, NuClient},
+    filesystem,
     progress::{THROTTLE_DELAY, ThrottledProgress},
+    ratelimit::RateLimiter,
+    retry::{Classify, RetryPolicy, Retryability},
     version::VersionInfo,
+    zipstream::{self, LocalHeader},
 };
 
 const DOWNLOAD_EXT: &str = concat!(env!("CARGO_PKG_NAME"), "_download");
 const EXTRACT_EXT: &str = concat!(env!("CARGO_PKG_NAME"), "_extract");
 const VERIFY_EXT: &str = concat!(env!("CARGO_PKG_NAME"), "_verify");
+const SEGMENTS_EXT: &str = concat!(env!("CARGO_PKG_NAME"), "_segments");
+
+/// How often each segment of a segmented download syncs its data to disk and
+/// saves its progress to the resume state.
+const SEGMENT_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often to check for cancellation while waiting for a split to finish
+/// downloading during streaming extraction.
+const SPLIT_WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Maximum size of the data following the compressed data in a split zip. This
+/// only needs to hold the central directory and end of central directory
+/// records for a single entry.
+const MAX_ZIP_TRAILER_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Failed to create directory: {0:?}")]
+    CreateDir(String, #[source] io::Error),
+    #[error("Failed to open directory: {0:?}")]
+    OpenDir(String, #[source] io::Error),
+    #[error("Failed to open file: {0:?}")]
+    OpenFile(String, #[source] io::Error),
+    #[error("Failed to create file: {0:?}")]
+    CreateFile(String, #[source] io::Error),
+    #[error("Failed to stat: {0:?}")]
+    Stat(String, #[source] io::Error),
+    #[error("Failed to read file: {0:?}")]
+    ReadFile(String, #[source] io::Error),
+    #[error("Failed to write file: {0:?}")]
+    WriteFile(String, #[source] io::Error),
+    #[error("Failed to delete file: {0:?}")]
+    DeleteFile(String, #[source] io::Error),
+    #[error("Failed to move file: {0:?} -> {1:?}")]
+    MoveFile(String, String, #[source] io::Error),
+    #[error("Failed to download: {0:?}")]
+    Download(String, #[source] client::Error),
+    #[error("No data received for {timeout:?}: {path:?}")]
+    IdleTimeout { path: String, timeout: Duration },
+    #[error("Throughput of {actual} bytes/s is below the minimum of {minimum} bytes/s: {path:?}")]
+    LowThroughput {
+        path: String,
+        actual: u64,
+        minimum: u64,
+    },
+    #[error("Download ended at offset {actual}, but expected {expected}: {path:?}")]
+    IncompleteSegment {
+        path: String,
+        actual: u64,
+        expected: u64,
+    },
+    #[error("Failed to parse segment state: {0:?}")]
+    ParseSegments(String, #[source] serde_json::Error),
+    #[error("Segment state does not match file size of {size} bytes: {path:?}")]
+    SegmentsMismatch { path: String, size: u64 },
+    #[error("Failed to read split zip: {0:?}")]
+    ReadZip(String, #[source] io::Error),
+    #[error("Unexpected zip entry: {0:?}")]
+    UnexpectedZipEntry(String),
+    #[error("Missing zip entry: {0:?}")]
+    MissingZipEntry(String),
+    #[error("Unsupported zip compression method: {0}")]
+    UnsupportedCompression(String),
+    #[error("Expected CRC32 {expected:08X}, but have {actual:08X}: {path:?}")]
+    InvalidCrc32 {
+        path: String,
+        actual: u32,
+        expected: u32,
+    },
+    #[error("Expected pieces to total {expected} bytes, but have {actual} bytes: {path:?}")]
+    InvalidSize {
+        path: String,
+        actual: u64,
+        expected: u64,
+    },
+    #[error(
+        "Not enough free space: {} bytes needed, but only {available} bytes available\n\
+        \x20 Remaining downloads: {} bytes\n\
+        \x20 Extracted output: {} bytes\n\
+        \x20 Split zips deleted after extraction: {} bytes\n\
+        \x20 Split zips kept while extracting: {} bytes",
+        .estimate.peak(), .estimate.download, .estimate.extract, .estimate.release,
+        .estimate.overlap,
+    )]
+    InsufficientSpace {
+        estimate: SpaceEstimate,
+        available: u64,
+    },
+    #[error(transparent)]
+    Progress(SendError<ProgressMessage>),
+    #[error(transparent)]
+    Cancelled(io::Error),
+    #[error(transparent)]
+    Panic(JoinError),
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
 
-const RETRY_DELAY: Duration = Duration::from_secs(1);
+impl Classify for Error {
+    fn retryability(&self) -> Retryability {
+        match self {
+            Self::Download(_, e) => e.retryability(),
+            Self::IdleTimeout { .. }
+            | Self::LowThroughput { .. }
+            | Self::IncompleteSegment { .. } => Retryability::Retryable { retry_after: None },
+            _ => Retryability::Fatal,
+        }
+    }
+}
 
 /// Delete a file, but don't error out if the path doesn't exist.
 fn delete_if_exists(directory: &Dir, path: &Path) -> Result<()> {
     if let Err(e) = directory.remove_file(path)
         && e.kind() != io::ErrorKind::NotFound
     {
-        return Err(e).context(format!("Failed to delete file: {path:?}"));
+        return Err(Error::DeleteFile(path.to_string_lossy().into_owned(), e));
     }
 
     Ok(())
@@ -56,10 +169,141 @@ fn stat_if_exists(directory: &Dir, path: &Path) -> Result<Option<Metadata>> {
     match directory.metadata(path) {
         Ok(m) => Ok(Some(m)),
         Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
-        Err(e) => Err(e).with_context(|| format!("Failed to stat: {path:?}")),
+        Err(e) => Err(Error::Stat(path.to_string_lossy().into_owned(), e)),
     }
 }
 
+/// Wrap an error from one of the zip parsers.
+fn zip_error(path: &str, e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Error {
+    Error::ReadZip(path.to_owned(), io::Error::other(e))
+}
+
+/// Extract the single entry named `name` from the split zip consisting of
+/// `paths` in `directory`. The entry's CRC32 must match `crc32`. If
+/// `expected_size` is specified, the splits must total that many bytes. The
+/// output is written to a temporary file that is renamed to `name` once
+/// complete.
+pub fn extract_split_zip(
+    directory: &Arc<Dir>,
+    paths: Vec<PathBuf>,
+    name: &str,
+    crc32: u32,
+    expected_size: Option<u64>,
+    mut progress: impl FnMut(u64) -> Result<()>,
+    cancel_signal: &AtomicBool,
+) -> Result<()> {
+    // Split files use the ancient split zip mechanism from the DOS era and
+    // there are basically no libraries or tools that support reading them.
+    // Instead, we'll create a copy-on-write virtual file that presents them as
+    // a single concatenated file and then fix the file offsets in memory so
+    // that it looks like a regular zip.
+    let opener = SubdirOpener {
+        dir: directory.clone(),
+        paths,
+    };
+    let joined = JoinedFile::new(opener).map_err(|e| Error::ReadZip(name.to_owned(), e))?;
+
+    let actual_size = joined.len();
+
+    if let Some(expected_size) = expected_size
+        && actual_size != expected_size
+    {
+        return Err(Error::InvalidSize {
+            path: name.to_owned(),
+            actual: actual_size,
+            expected: expected_size,
+        });
+    }
+
+    let split_ranges = joined.splits();
+    let mut cow_file =
+        MemoryCowFile::new(joined, 4096).map_err(|e| Error::ReadZip(name.to_owned(), e))?;
+    split::fix_offsets(&mut cow_file, &split_ranges).map_err(|e| zip_error(name, e))?;
+    cow_file
+        .rewind()
+        .map_err(|e| Error::ReadZip(name.to_owned(), e))?;
+
+    check_cancel(cancel_signal).map_err(Error::Cancelled)?;
+
+    let mut buffer = vec![0u8; RECOMMENDED_BUFFER_SIZE];
+    let zip = ZipArchive::from_seekable(cow_file, &mut buffer).map_err(|e| zip_error(name, e))?;
+    let mut entries = zip.entries(&mut buffer);
+    let mut entry_info = None;
+
+    while let Some(cd_entry) = entries.next_entry().map_err(|e| zip_error(name, e))? {
+        let path = cd_entry.file_path();
+        let path = String::from_utf8_lossy(path.as_bytes());
+
+        if path == name {
+            entry_info = Some((
+                cd_entry.wayfinder(),
+                cd_entry.compression_method(),
+                cd_entry.crc32(),
+            ));
+        } else {
+            return Err(Error::UnexpectedZipEntry(path.into_owned()));
+        }
+    }
+
+    let Some((wayfinder, compression_method, entry_crc32)) = entry_info else {
+        return Err(Error::MissingZipEntry(name.to_owned()));
+    };
+
+    let entry = zip.get_entry(wayfinder).map_err(|e| zip_error(name, e))?;
+
+    // Only need to check the metadata field. ZipVerifier verifies the actual
+    // digest after reading to EOF.
+    if entry_crc32 != crc32 {
+        return Err(Error::InvalidCrc32 {
+            path: name.to_owned(),
+            actual: entry_crc32,
+            expected: crc32,
+        });
+    }
+
+    let reader: Box<dyn Read> = match compression_method {
+        CompressionMethod::STORE => Box::new(entry.reader()),
+        CompressionMethod::DEFLATE => Box::new(DeflateDecoder::new(entry.reader())),
+        c => return Err(Error::UnsupportedCompression(format!("{c:?}"))),
+    };
+    let mut reader = entry.verifying_reader(reader);
+
+    let extract_path = format!("{name}.{EXTRACT_EXT}");
+    let mut file = directory
+        .create(&extract_path)
+        .map_err(|e| Error::CreateFile(extract_path.clone(), e))?;
+    let mut buf = [0u8; 8192];
+
+    loop {
+        check_cancel(cancel_signal).map_err(Error::Cancelled)?;
+
+        let n = reader
+            .read(&mut buf)
+            .map_err(|e| Error::ReadZip(name.to_owned(), e))?;
+        if n == 0 {
+            break;
+        }
+
+        file.write_all(&buf[..n])
+            .map_err(|e| Error::WriteFile(extract_path.clone(), e))?;
+
+        progress(n as u64)?;
+    }
+
+    check_cancel(cancel_signal).map_err(Error::Cancelled)?;
+
+    file.sync_all()
+        .map_err(|e| Error::WriteFile(extract_path.clone(), e))?;
+
+    drop(file);
+
+    directory
+        .rename(&extract_path, directory, name)
+        .map_err(|e| Error::MoveFile(extract_path, name.to_owned(), e))?;
+
+    Ok(())
+}
+
 #[derive(Clone, Copy, Debug)]
 struct DownloadParams {
     file_index: usize,
@@ -75,14 +319,16 @@ struct PostProcessParams {
 
 #[derive(Debug)]
 struct InitialState {
-    /// Number of bytes already downloaded. This is based on the sum of the
-    /// raw download sizes.
-    dl_bytes: u64,
-    /// Number of bytes post processed. This is based on the sum of the final
+    /// Number of bytes already downloaded by file. This is based on the sum
+    /// of the raw download sizes.
+    dl_bytes: Vec<u64>,
+    /// Number of bytes post processed by file. This is based on the final
     /// output file sizes.
-    pp_bytes: u64,
+    pp_bytes: Vec<u64>,
     /// Number of remaining raw downloads by file.
     dl_remain: Vec<u32>,
+    /// Whether the output file already exists by file.
+    complete: Vec<bool>,
     /// Complete set of remaining download tasks.
     dl_tasks: VecDeque<DownloadParams>,
     /// Initial set of post-processing tasks that can be immediately executed.
@@ -91,16 +337,292 @@ struct InitialState {
     pp_tasks: VecDeque<PostProcessParams>,
 }
 
+/// Disk space needed to complete a download.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpaceEstimate {
+    /// Raw download bytes that still need to be written.
+    pub download: u64,
+    /// Output bytes that still need to be extracted from split zips.
+    pub extract: u64,
+    /// Raw bytes of split zips that are deleted after extraction. Each file's
+    /// share is limited to its extracted size.
+    pub release: u64,
+    /// Raw bytes from [`Self::release`] that still exist while the files
+    /// being extracted concurrently are written.
+    pub overlap: u64,
+}
+
+impl SpaceEstimate {
+    fn new(firmware: &FirmwareInfo, state: &InitialState, options: &DownloadOptions) -> Self {
+        let mut result = Self::default();
+        let mut released = vec![];
+        let mut overlapping = vec![];
+
+        for (f_i, file_info) in firmware.files.iter().enumerate() {
+            if state.complete[f_i] {
+                continue;
+            }
+
+            result.download += file_info
+                .download_size()
+                .saturating_sub(state.dl_bytes[f_i]);
+
+            if file_info.is_split() {
+                result.extract += file_info.size;
+
+                if !options.keep_raw {
+                    let share = file_info.download_size().min(file_info.size);
+                    released.push(share);
+
+                    // When streamed splits are deleted, only the split being
+                    // read still exists. The splits are assumed to be about
+                    // the same size.
+                    overlapping.push(if options.deletes_streamed_splits() {
+                        let count = u64::from(file_info.download_count());
+                        share.min(file_info.download_size().div_ceil(count))
+                    } else {
+                        share
+                    });
+                }
+            }
+        }
+
+        result.release = released.iter().sum();
+
+        // In the worst case, the files with the largest split zips are the ones
+        // being extracted at the same time.
+        overlapping.sort_unstable_by(|a, b| b.cmp(a));
+        result.overlap = overlapping.iter().take(options.concurrency).sum();
+
+        result
+    }
+
+    /// Peak disk space usage. In the worst case, every download completes
+    /// before any extraction finishes. A file's split zips are only deleted
+    /// after its output is extracted and verified, so they exist alongside
+    /// the output while up to the post-processing concurrency limit of files
+    /// are being extracted. With streaming extraction, each split is deleted
+    /// as soon as it has been read instead, so only one split per file being
+    /// extracted exists alongside the output. If the split zips are kept,
+    /// everything exists at the same time.
+    pub fn peak(&self) -> u64 {
+        (self.download + self.extract + self.overlap).saturating_sub(self.release)
+    }
+
+    /// Fail if the peak usage exceeds the available space. If the sizes are
+    /// not reliable, only a warning is logged.
+    fn check(&self, available: u64, reliable: bool) -> Result<()> {
+        let peak = self.peak();
+
+        if peak <= available {
+            debug!("Enough free space: {peak} <= {available} bytes");
+            return Ok(());
+        }
+
+        let error = Error::InsufficientSpace {
+            estimate: *self,
+            available,
+        };
+
+        if reliable {
+            return Err(error);
+        }
+
+        warn!("{error}");
+        warn!("Continuing anyway because the server reported sizes that overflowed");
+
+        Ok(())
+    }
+}
+
 enum TaskResult {
     Download((usize, u32, Result<()>)),
     PostProcess((usize, Result<()>)),
 }
 
+/// Unit of work that is scheduled by the [`Downloader`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Task {
+    /// Raw download of a split or unsplit file.
+    Download {
+        file_index: usize,
+        download_index: u32,
+    },
+    /// Extraction or verification of an output file.
+    PostProcess { file_index: usize },
+}
+
+/// Progress events. Files are identified by their index in
+/// [`FirmwareInfo::files`].
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
 pub enum ProgressMessage {
-    TotalDownload(u64),
-    TotalPostProcess(u64),
-    Download(u64),
-    PostProcess(u64),
+    /// Total size of all raw downloads.
+    TotalDownload {
+        bytes: u64,
+    },
+    /// Total size of all output files.
+    TotalPostProcess {
+        bytes: u64,
+    },
+    /// Progress that was already made by a previous run.
+    Resumed {
+        file_index: usize,
+        download_bytes: u64,
+        post_process_bytes: u64,
+    },
+    Download {
+        file_index: usize,
+        download_index: u32,
+        bytes: u64,
+    },
+    PostProcess {
+        file_index: usize,
+        bytes: u64,
+    },
+    TaskStarted {
+        task: Task,
+        /// Bytes that were already downloaded by a previous run. This is
+        /// always 0 for post-processing tasks.
+        start_offset: u64,
+    },
+    TaskCompleted {
+        task: Task,
+    },
+    /// A raw download failed and will be retried.
+    TaskRetry {
+        task: Task,
+        attempt: u8,
+        error: String,
+    },
+}
+
+/// A raw download that still needs to be performed.
+#[derive(Clone, Debug, Serialize)]
+pub struct PlannedDownload {
+    pub download_index: u32,
+    /// Path relative to the output directory.
+    pub path: String,
+    /// Number of bytes already downloaded by a previous run. Downloads with a
+    /// non-zero offset are resumed.
+    pub start_offset: u64,
+}
+
+/// Post-processing step for an output file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlannedAction {
+    /// The output file already exists and there is nothing to do.
+    None,
+    /// The output file already exists, but leftover splits may need to be
+    /// deleted.
+    Clean,
+    /// The unsplit download will be checked against the CRC32 checksum.
+    Verify,
+    /// The output file will be extracted from the split zips.
+    Extract,
+}
+
+/// Work remaining for a single output file.
+#[derive(Clone, Debug, Serialize)]
+pub struct FilePlan {
+    pub path: String,
+    pub downloads: Vec<PlannedDownload>,
+    pub action: PlannedAction,
+    /// Raw files that will be deleted once post-processing completes.
+    pub delete: Vec<String>,
+    /// Number of raw download bytes that already exist.
+    pub downloaded_bytes: u64,
+    /// Number of raw download bytes that still need to be downloaded. This is
+    /// inaccurate if the server reports incorrect sizes.
+    pub remaining_bytes: u64,
+}
+
+/// Everything that [`Downloader::download`] would do for an output directory.
+#[derive(Clone, Debug, Serialize)]
+pub struct DownloadPlan {
+    /// Files in the same order as [`FirmwareInfo::files`].
+    pub files: Vec<FilePlan>,
+    /// Total number of raw download bytes that still need to be downloaded.
+    pub remaining_bytes: u64,
+    /// Estimated peak disk space usage. See [`SpaceEstimate::peak`].
+    pub peak_space_bytes: u64,
+}
+
+/// Reader that presents split zips as a single concatenated stream. If a split
+/// has not finished downloading yet, reads block until the download task
+/// reports that it has completed. If `delete` is set, each split is deleted as
+/// soon as it has been fully read.
+struct SplitStreamReader<'a> {
+    directory: &'a Dir,
+    file_info: &'a FileInfo,
+    split_rx: std_mpsc::Receiver<u32>,
+    cancel_signal: &'a AtomicBool,
+    delete: bool,
+    index: u32,
+    file: Option<cap_std::fs::File>,
+    bytes_read: u64,
+}
+
+impl SplitStreamReader<'_> {
+    fn open_split(&mut self) -> io::Result<cap_std::fs::File> {
+        let path = self.file_info.download_name(self.index);
+
+        loop {
+            check_cancel(self.cancel_signal)?;
+
+            match self.directory.open(&path) {
+                Ok(f) => return Ok(f),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+
+            match self.split_rx.recv_timeout(SPLIT_WAIT_INTERVAL) {
+                Ok(_) | Err(std_mpsc::RecvTimeoutError::Timeout) => {}
+                Err(std_mpsc::RecvTimeoutError::Disconnected) => {
+                    // All downloads have completed, so this is the last chance
+                    // for the file to exist.
+                    return self.directory.open(&path).map_err(|e| {
+                        io::Error::new(e.kind(), format!("Split was never downloaded: {path}"))
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl Read for SplitStreamReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            check_cancel(self.cancel_signal)?;
+
+            if self.file.is_none() {
+                if self.index == self.file_info.download_count() {
+                    return Ok(0);
+                }
+
+                self.file = Some(self.open_split()?);
+            }
+
+            let file = self.file.as_mut().unwrap();
+
+            let n = file.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.bytes_read += n as u64;
+                return Ok(n);
+            }
+
+            self.file = None;
+
+            if self.delete {
+                self.directory
+                    .remove_file(self.file_info.download_name(self.index))?;
+            }
+
+            self.index += 1;
+        }
+    }
 }
 
 struct SubdirOpener {
@@ -118,14 +640,319 @@ impl Opener for SubdirOpener {
     }
 }
 
+/// Minimum average throughput of a raw download stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MinThroughput {
+    pub bytes_per_sec: u64,
+    /// Period over which the throughput is averaged.
+    pub window: Duration,
+}
+
+/// Limits for detecting raw download streams that have stalled. When a limit
+/// is exceeded, the stream is aborted and the download is retried from the
+/// current offset according to the retry policy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamTimeouts {
+    /// Maximum time to wait for the next chunk of data.
+    pub idle: Option<Duration>,
+    /// Minimum throughput. Time spent waiting for the rate limiter is not
+    /// counted.
+    pub min_throughput: Option<MinThroughput>,
+}
+
+/// Tracks the progress of a raw download stream to detect stalls.
+struct StallMonitor {
+    timeouts: StreamTimeouts,
+    last_data: Instant,
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+impl StallMonitor {
+    fn new(timeouts: StreamTimeouts, now: Instant) -> Self {
+        Self {
+            timeouts,
+            last_data: now,
+            window_start: now,
+            window_bytes: 0,
+        }
+    }
+
+    /// Get the time at which the stream should be checked again, even if no
+    /// data arrives.
+    fn deadline(&self) -> Option<Instant> {
+        let idle = self.timeouts.idle.map(|t| self.last_data + t);
+        let window = self
+            .timeouts
+            .min_throughput
+            .map(|m| self.window_start + m.window);
+
+        idle.into_iter().chain(window).min()
+    }
+
+    /// Don't count the specified duration, like when waiting for the rate
+    /// limiter.
+    fn exclude(&mut self, duration: Duration) {
+        self.last_data += duration;
+        self.window_start += duration;
+    }
+
+    /// Record that `bytes` were received at `now` and check if any limits were
+    /// exceeded.
+    fn update(&mut self, path: &str, now: Instant, bytes: u64) -> Result<()> {
+        if bytes > 0 {
+            self.last_data = now;
+            self.window_bytes += bytes;
+        }
+
+        if let Some(timeout) = self.timeouts.idle
+            && now.duration_since(self.last_data) >= timeout
+        {
+            return Err(Error::IdleTimeout {
+                path: path.to_owned(),
+                timeout,
+            });
+        }
+
+        if let Some(min) = self.timeouts.min_throughput {
+            let elapsed = now.duration_since(self.window_start);
+
+            if elapsed >= min.window {
+                let actual = (self.window_bytes as f64 / elapsed.as_secs_f64()) as u64;
+                if actual < min.bytes_per_sec {
+                    return Err(Error::LowThroughput {
+                        path: path.to_owned(),
+                        actual,
+                        minimum: min.bytes_per_sec,
+                    });
+                }
+
+                self.window_start = now;
+                self.window_bytes = 0;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Splitting of large unsplit files into byte ranges that are downloaded
+/// concurrently into the same file. Split zips are never segmented because
+/// they are already downloaded concurrently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segmentation {
+    /// Maximum number of segments per file. A value of 1 disables
+    /// segmentation.
+    pub max_segments: u32,
+    /// Minimum size of each segment. Files are split into fewer segments if
+    /// needed to satisfy this.
+    pub min_segment_size: u64,
+}
+
+impl Segmentation {
+    pub const DEFAULT_MIN_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+    /// Don't segment any downloads.
+    pub fn none() -> Self {
+        Self {
+            max_segments: 1,
+            min_segment_size: Self::DEFAULT_MIN_SEGMENT_SIZE,
+        }
+    }
+
+    /// Number of segments to split `len` bytes into.
+    fn count(&self, len: u64) -> u32 {
+        let count = len / self.min_segment_size.max(1);
+
+        count.clamp(1, self.max_segments.max(1).into()) as u32
+    }
+}
+
+impl Default for Segmentation {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// A byte range of a segmented download.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+struct Segment {
+    start: u64,
+    /// Exclusive end offset.
+    end: u64,
+    /// Number of bytes after `start` that have been synced to disk.
+    done: u64,
+}
+
+impl Segment {
+    /// Offset from which the segment should be resumed.
+    fn offset(&self) -> u64 {
+        self.start + self.done
+    }
+
+    fn is_complete(&self) -> bool {
+        self.offset() >= self.end
+    }
+}
+
+/// Resume state of a segmented download. The download temp file is
+/// preallocated, so unlike with regular downloads, its size does not indicate
+/// how much has been downloaded.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+struct SegmentState {
+    /// Total size of the file.
+    size: u64,
+    segments: Vec<Segment>,
+}
+
+impl SegmentState {
+    /// Split the remaining bytes from `start` to `size` into `count` segments.
+    /// The bytes before `start` were already downloaded without segmentation.
+    fn new(size: u64, start: u64, count: u32) -> Self {
+        let mut segments = vec![];
+
+        if start > 0 {
+            segments.push(Segment {
+                start: 0,
+                end: start,
+                done: start,
+            });
+        }
+
+        let count = u64::from(count.max(1));
+        let len = (size - start) / count;
+
+        for i in 0..count {
+            let segment_start = start + i * len;
+            let segment_end = if i == count - 1 {
+                size
+            } else {
+                segment_start + len
+            };
+
+            segments.push(Segment {
+                start: segment_start,
+                end: segment_end,
+                done: 0,
+            });
+        }
+
+        Self { size, segments }
+    }
+
+    /// Number of bytes that have been synced to disk.
+    fn downloaded(&self) -> u64 {
+        self.segments.iter().map(|s| s.done).sum()
+    }
+
+    /// Load the state from `path` if it exists. The state must be for a file
+    /// of `size` bytes.
+    fn load(directory: &Dir, path: &str, size: u64) -> Result<Option<Self>> {
+        let data = match directory.read(path) {
+            Ok(d) => d,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::ReadFile(path.to_owned(), e)),
+        };
+
+        let state: Self =
+            serde_json::from_slice(&data).map_err(|e| Error::ParseSegments(path.to_owned(), e))?;
+
+        let valid = state.size == size
+            && state
+                .segments
+                .iter()
+                .all(|s| s.start <= s.end && s.end <= size && s.done <= s.end - s.start);
+        if !valid {
+            return Err(Error::SegmentsMismatch {
+                path: path.to_owned(),
+                size,
+            });
+        }
+
+        Ok(Some(state))
+    }
+
+    /// Atomically replace the state at `path`.
+    fn save(&self, directory: &Dir, path: &str) -> Result<()> {
+        let temp_path = format!("{path}.{DOWNLOAD_EXT}");
+        let data = serde_json::to_vec(self).expect("Segment state is always serializable");
+
+        let mut file = directory
+            .create(&temp_path)
+            .map_err(|e| Error::CreateFile(temp_path.clone(), e))?;
+        file.write_all(&data)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| Error::WriteFile(temp_path.clone(), e))?;
+        drop(file);
+
+        directory
+            .rename(&temp_path, directory, path)
+            .map_err(|e| Error::MoveFile(temp_path, path.to_owned(), e))
+    }
+}
+
+/// Resume state of an in-progress segmented download, shared by the tasks
+/// downloading each segment.
+struct SegmentTracker {
+    directory: Arc<Dir>,
+    path: String,
+    state: Mutex<SegmentState>,
+}
+
+impl SegmentTracker {
+    fn end(&self, index: usize) -> u64 {
+        self.state.lock().unwrap().segments[index].end
+    }
+
+    /// Record that the data for segment `index` up to `offset` has been synced
+    /// to disk and save the state.
+    fn checkpoint(&self, index: usize, offset: u64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let segment = &mut state.segments[index];
+        segment.done = offset.clamp(segment.start, segment.end) - segment.start;
+
+        state.save(&self.directory, &self.path)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DownloadOptions {
+    /// Maximum number of concurrent download tasks and concurrent
+    /// post-processing tasks.
+    pub concurrency: usize,
+    /// How to retry raw downloads that fail with transient errors.
+    pub retry_policy: RetryPolicy,
+    /// Keep the raw split zips after extraction.
+    pub keep_raw: bool,
+    /// Extract split zips while the splits are still being downloaded instead
+    /// of waiting for all of them to complete.
+    pub stream_extract: bool,
+    /// When streaming, keep the splits until the extracted output passes its
+    /// CRC32 check instead of deleting each one as soon as it has been read.
+    pub keep_splits_until_verified: bool,
+    /// Limiter for the total download speed across all download tasks.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// Limits for aborting stalled raw downloads.
+    pub timeouts: StreamTimeouts,
+    /// How to split large unsplit files into concurrent downloads.
+    pub segmentation: Segmentation,
+    /// Don't fail if the estimated peak disk usage exceeds the free space.
+    pub skip_space_check: bool,
+}
+
+impl DownloadOptions {
+    /// Whether streaming extraction deletes each split after reading it.
+    fn deletes_streamed_splits(&self) -> bool {
+        self.stream_extract && !self.keep_raw && !self.keep_splits_until_verified
+    }
+}
+
 pub struct Downloader {
     directory: Arc<Dir>,
     client: Arc<NuClient>,
     car: Arc<CarInfo>,
     firmware: Arc<FirmwareInfo>,
-    concurrency: usize,
-    retries: u8,
-    keep_raw: bool,
+    options: DownloadOptions,
     progress_tx: mpsc::Sender<ProgressMessage>,
 }
 
@@ -135,20 +962,16 @@ impl Downloader {
         client: NuClient,
         car: CarInfo,
         firmware: FirmwareInfo,
-        concurrency: usize,
-        retries: u8,
-        keep_raw: bool,
+        options: DownloadOptions,
     ) -> (Self, mpsc::Receiver<ProgressMessage>) {
-        let (progress_tx, progress_rx) = mpsc::channel(2 * concurrency);
+        let (progress_tx, progress_rx) = mpsc::channel(2 * options.concurrency);
 
         let result = Self {
             directory: Arc::new(directory),
             client: Arc::new(client),
             car: Arc::new(car),
             firmware: Arc::new(firmware),
-            concurrency,
-            retries,
-            keep_raw,
+            options,
             progress_tx,
         };
 
@@ -162,57 +985,71 @@ impl Downloader {
 
         directory
             .write(&path, info.to_string())
-            .with_context(|| format!("Failed to write file: {path}"))
+            .map_err(|e| Error::WriteFile(path, e))
     }
 
+    /// Compute the work that remains to be done. If `base_directory` is
+    /// [`None`], then the output directory does not exist yet and everything
+    /// needs to be downloaded from scratch.
     fn compute_initial_state(
-        base_directory: &Dir,
+        base_directory: Option<&Dir>,
         firmware: &FirmwareInfo,
+        stream_extract: bool,
         cancel_signal: &AtomicBool,
     ) -> Result<InitialState> {
-        let mut dl_bytes = 0;
-        let mut pp_bytes = 0;
+        let mut dl_bytes = vec![0u64; firmware.files.len()];
+        let mut pp_bytes = vec![0u64; firmware.files.len()];
         let mut dl_remain = vec![0u32; firmware.files.len()];
+        let mut complete = vec![false; firmware.files.len()];
         let mut dl_tasks = VecDeque::new();
         let mut pp_tasks = VecDeque::new();
 
         for (f_i, file_info) in firmware.files.iter().enumerate() {
-            check_cancel(cancel_signal)?;
+            check_cancel(cancel_signal).map_err(Error::Cancelled)?;
 
             let remain = &mut dl_remain[f_i];
 
             let owned_directory: Dir;
-            let directory = if let Some(name) = &file_info.directory {
-                match base_directory.open_dir(name) {
+            let directory = match (base_directory, &file_info.directory) {
+                (None, _) => None,
+                (Some(base_directory), Some(name)) => match base_directory.open_dir(name) {
                     Ok(d) => {
                         owned_directory = d;
-                        &owned_directory
+                        Some(&owned_directory)
                     }
-                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                        // No output file or split exists.
-                        for dl_i in 0..file_info.download_count() {
-                            dl_tasks.push_back(DownloadParams {
-                                file_index: f_i,
-                                download_index: dl_i,
-                                start_offset: 0,
-                            });
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(Error::OpenDir(name.clone(), e)),
+                },
+                (Some(base_directory), None) => Some(base_directory),
+            };
 
-                            *remain += 1;
-                        }
-                        continue;
-                    }
-                    Err(e) => {
-                        return Err(e).with_context(|| format!("Failed to open directory: {name}"));
-                    }
+            let Some(directory) = directory else {
+                // No output file or split exists.
+                for dl_i in 0..file_info.download_count() {
+                    dl_tasks.push_back(DownloadParams {
+                        file_index: f_i,
+                        download_index: dl_i,
+                        start_offset: 0,
+                    });
+
+                    *remain += 1;
                 }
-            } else {
-                base_directory
+
+                if stream_extract && file_info.is_split() {
+                    pp_tasks.push_back(PostProcessParams {
+                        file_index: f_i,
+                        clean_only: false,
+                    });
+                }
+
+                continue;
             };
 
             if stat_if_exists(directory, Path::new(&file_info.name))?.is_some() {
                 // Downloaded and post-processed.
-                dl_bytes += file_info.download_size();
-                pp_bytes += file_info.size;
+                dl_bytes[f_i] += file_info.download_size();
+                pp_bytes[f_i] += file_info.size;
+                complete[f_i] = true;
 
                 // Make sure splits are cleaned up.
                 if file_info.is_split() {
@@ -226,12 +1063,12 @@ impl Downloader {
             }
 
             for dl_i in 0..file_info.download_count() {
-                check_cancel(cancel_signal)?;
+                check_cancel(cancel_signal).map_err(Error::Cancelled)?;
 
                 // Completed raw download.
                 let path = file_info.download_name(dl_i);
                 if let Some(m) = stat_if_exists(directory, Path::new(&path))? {
-                    dl_bytes += m.len();
+                    dl_bytes[f_i] += m.len();
                     continue;
                 }
 
@@ -239,18 +1076,28 @@ impl Downloader {
                     // Unverified, but completed raw download of unsplit file.
                     let verify_path = format!("{path}.{VERIFY_EXT}");
                     if let Some(m) = stat_if_exists(directory, Path::new(&verify_path))? {
-                        dl_bytes += m.len();
+                        dl_bytes[f_i] += m.len();
                         continue;
                     }
                 }
 
-                // Incomplete raw download.
+                // Incomplete raw download. Segmented downloads are preallocated,
+                // so the progress comes from the resume state instead.
                 let download_path = format!("{path}.{DOWNLOAD_EXT}");
-                let download_size = stat_if_exists(directory, Path::new(&download_path))?
-                    .map(|m| m.len())
-                    .unwrap_or_default();
+                let segments_path = format!("{path}.{SEGMENTS_EXT}");
+                let segments = if file_info.is_split() {
+                    None
+                } else {
+                    SegmentState::load(directory, &segments_path, file_info.size)?
+                };
+                let download_size = match segments {
+                    Some(s) => s.downloaded(),
+                    None => stat_if_exists(directory, Path::new(&download_path))?
+                        .map(|m| m.len())
+                        .unwrap_or_default(),
+                };
 
-                dl_bytes += download_size;
+                dl_bytes[f_i] += download_size;
                 dl_tasks.push_back(DownloadParams {
                     file_index: f_i,
                     download_index: dl_i,
@@ -260,7 +1107,9 @@ impl Downloader {
                 *remain += 1;
             }
 
-            if *remain == 0 {
+            // When streaming, extraction starts immediately and waits for the
+            // splits as they are downloaded.
+            if *remain == 0 || (stream_extract && file_info.is_split()) {
                 pp_tasks.push_back(PostProcessParams {
                     file_index: f_i,
                     clean_only: false,
@@ -272,20 +1121,110 @@ impl Downloader {
             dl_bytes,
             pp_bytes,
             dl_remain,
+            complete,
             dl_tasks,
             pp_tasks,
         })
     }
 
+    /// Compute what [`Self::download`] would do without downloading or writing
+    /// anything. If `directory` is [`None`], then the output directory does
+    /// not exist yet.
+    pub fn plan(
+        directory: Option<&Dir>,
+        firmware: &FirmwareInfo,
+        options: &DownloadOptions,
+    ) -> Result<DownloadPlan> {
+        let state = Self::compute_initial_state(
+            directory,
+            firmware,
+            options.stream_extract,
+            &AtomicBool::new(false),
+        )?;
+        let space = SpaceEstimate::new(firmware, &state, options);
+        let mut files = firmware
+            .files
+            .iter()
+            .enumerate()
+            .map(|(f_i, file_info)| {
+                let action = match (state.complete[f_i], file_info.is_split()) {
+                    (true, false) => PlannedAction::None,
+                    (true, true) => PlannedAction::Clean,
+                    (false, false) => PlannedAction::Verify,
+                    (false, true) => PlannedAction::Extract,
+                };
+
+                FilePlan {
+                    path: file_info.path(),
+                    downloads: vec![],
+                    action,
+                    delete: vec![],
+                    downloaded_bytes: state.dl_bytes[f_i],
+                    remaining_bytes: file_info
+                        .download_size()
+                        .saturating_sub(state.dl_bytes[f_i]),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for params in &state.dl_tasks {
+            let file_info = &firmware.files[params.file_index];
+
+            files[params.file_index].downloads.push(PlannedDownload {
+                download_index: params.download_index,
+                path: file_info.download_path(params.download_index),
+                start_offset: params.start_offset,
+            });
+        }
+
+        if !options.keep_raw {
+            for (file_info, file_plan) in firmware.files.iter().zip(&mut files) {
+                if !file_info.is_split() {
+                    continue;
+                }
+
+                for dl_i in 0..file_info.download_count() {
+                    let path = file_info.download_path(dl_i);
+
+                    // Only leftover splits that actually exist are deleted
+                    // when cleaning.
+                    let exists = match (file_plan.action, directory) {
+                        (PlannedAction::Clean, Some(d)) => {
+                            stat_if_exists(d, Path::new(&path))?.is_some()
+                        }
+                        _ => true,
+                    };
+
+                    if exists {
+                        file_plan.delete.push(path);
+                    }
+                }
+            }
+        }
+
+        let remaining_bytes = files.iter().map(|f| f.remaining_bytes).sum();
+
+        Ok(DownloadPlan {
+            files,
+            remaining_bytes,
+            peak_space_bytes: space.peak(),
+        })
+    }
+
     /// Download a single raw file (eg. a split). The download begins at the
-    /// current file offset of `file`. The file data and metadata will be synced
-    /// to disk when complete.
+    /// current file offset of `file`. If `segment` is specified, the download
+    /// stops at the end of that segment and its progress is periodically saved.
+    /// The file data and metadata will be synced to disk when complete.
+    #[allow(clippy::too_many_arguments)]
     async fn download_raw_to_file(
         file: &mut File,
         client: Arc<NuClient>,
         firmware: Arc<FirmwareInfo>,
         file_index: usize,
         download_index: u32,
+        segment: Option<(&SegmentTracker, usize)>,
+        timeouts: StreamTimeouts,
+        rate_limiter: Option<&RateLimiter>,
         progress_tx: mpsc::Sender<ProgressMessage>,
     ) -> Result<()> {
         let file_info = &firmware.files[file_index];
@@ -294,47 +1233,348 @@ impl Downloader {
         let start = file
             .stream_position()
             .await
-            .context("Failed to get file position")?;
-        debug!("[{path}] Downloading from offset: {start}");
+            .map_err(|e| Error::WriteFile(path.clone(), e))?;
+        let end = segment.map(|(tracker, index)| tracker.end(index));
+        debug!("[{path}] Downloading from offset: {start}, until: {end:?}");
+
+        let request = client.download(&firmware, file_info, download_index, start, end);
+        let response = match timeouts.idle {
+            Some(timeout) => {
+                time::timeout(timeout, request)
+                    .await
+                    .map_err(|_| Error::IdleTimeout {
+                        path: path.clone(),
+                        timeout,
+                    })?
+            }
+            None => request.await,
+        };
 
-        let mut stream = match client
-            .download(&firmware, file_info, download_index, start)
-            .await
-        {
+        let mut stream = match response {
             Ok(s) => s,
             Err(client::Error::AlreadyComplete) => {
                 debug!("[{path}] Download already complete");
                 return Ok(());
             }
-            Err(e) => return Err(e.into()),
+            Err(e) => return Err(Error::Download(path, e)),
         };
 
-        let mut progress =
-            ThrottledProgress::new(progress_tx, ProgressMessage::Download, THROTTLE_DELAY);
+        let mut progress = ThrottledProgress::new(
+            progress_tx,
+            |bytes| ProgressMessage::Download {
+                file_index,
+                download_index,
+                bytes,
+            },
+            THROTTLE_DELAY,
+        );
+
+        let mut monitor = StallMonitor::new(timeouts, Instant::now());
+        let mut offset = start;
+        let mut last_checkpoint = Instant::now();
+
+        loop {
+            let next = match monitor.deadline() {
+                Some(deadline) => match time::timeout_at(deadline, stream.next()).await {
+                    Ok(n) => n,
+                    Err(_) => {
+                        monitor.update(&path, Instant::now(), 0)?;
+                        continue;
+                    }
+                },
+                None => stream.next().await,
+            };
+            let Some(data) = next else {
+                break;
+            };
 
-        while let Some(data) = stream.next().await {
-            let data = data?;
+            let mut data = data.map_err(|e| Error::Download(path.clone(), e.into()))?;
             trace!("[{path}] Received {} bytes", data.len());
 
-            file.write_all(&data)
+            // Never write past the segment, even if the server sends more.
+            if let Some(end) = end
+                && data.len() as u64 > end - offset
+            {
+                warn!("[{path}] Server sent data past the end of the range: {end}");
+                data.truncate((end - offset) as usize);
+            }
+
+            monitor.update(&path, Instant::now(), data.len() as u64)?;
+
+            if let Some(limiter) = rate_limiter {
+                let wait_start = Instant::now();
+                limiter.acquire(data.len() as u64).await;
+                monitor.exclude(wait_start.elapsed());
+            }
+
+            file.write_all(&data)
+                .await
+                .map_err(|e| Error::WriteFile(path.clone(), e))?;
+            offset += data.len() as u64;
+
+            progress
+                .update(data.len() as u64)
+                .await
+                .map_err(Error::Progress)?;
+
+            // The data must be on disk before the resume state claims it is.
+            if let Some((tracker, index)) = segment
+                && last_checkpoint.elapsed() >= SEGMENT_SAVE_INTERVAL
+            {
+                file.sync_data()
+                    .await
+                    .map_err(|e| Error::WriteFile(path.clone(), e))?;
+                task::block_in_place(|| tracker.checkpoint(index, offset))?;
+                last_checkpoint = Instant::now();
+            }
+
+            if end == Some(offset) {
+                break;
+            }
+        }
+
+        progress.flush().await.map_err(Error::Progress)?;
+
+        if let Some(expected) = end
+            && offset != expected
+        {
+            return Err(Error::IncompleteSegment {
+                path,
+                actual: offset,
+                expected,
+            });
+        }
+
+        file.sync_all()
+            .await
+            .map_err(|e| Error::WriteFile(path, e))?;
+
+        if let Some((tracker, index)) = segment {
+            task::block_in_place(|| tracker.checkpoint(index, offset))?;
+        }
+
+        Ok(())
+    }
+
+    /// Download a single raw file or segment, retrying transient failures
+    /// according to `retry_policy`. Each attempt resumes from where the
+    /// previous one left off.
+    #[allow(clippy::too_many_arguments)]
+    async fn download_raw_with_retry(
+        file: &mut File,
+        client: &Arc<NuClient>,
+        firmware: &Arc<FirmwareInfo>,
+        file_index: usize,
+        download_index: u32,
+        segment: Option<(&SegmentTracker, usize)>,
+        retry_policy: &RetryPolicy,
+        timeouts: StreamTimeouts,
+        rate_limiter: Option<&RateLimiter>,
+        progress_tx: &mpsc::Sender<ProgressMessage>,
+    ) -> Result<()> {
+        let mut attempt = 0;
+
+        loop {
+            let ret = Self::download_raw_to_file(
+                file,
+                client.clone(),
+                firmware.clone(),
+                file_index,
+                download_index,
+                segment,
+                timeouts,
+                rate_limiter,
+                progress_tx.clone(),
+            )
+            .await;
+
+            match ret {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    let Some(delay) = retry_policy.retry_delay(attempt, &e) else {
+                        return Err(e);
+                    };
+                    attempt += 1;
+
+                    let file_info = &firmware.files[file_index];
+                    let download_path = file_info.download_path(download_index);
+                    let target = match segment {
+                        Some((_, index)) => {
+                            format!("{download_path}.{DOWNLOAD_EXT} (segment #{index})")
+                        }
+                        None => format!("{download_path}.{DOWNLOAD_EXT}"),
+                    };
+
+                    warn!(
+                        "[Attempt #{attempt}/{}] Failed to download to: {target}: {e}",
+                        u16::from(retry_policy.max_retries) + 1,
+                    );
+
+                    progress_tx
+                        .send(ProgressMessage::TaskRetry {
+                            task: Task::Download {
+                                file_index,
+                                download_index,
+                            },
+                            attempt,
+                            error: e.to_string(),
+                        })
+                        .await
+                        .map_err(Error::Progress)?;
+
+                    time::sleep(delay).await;
+                }
+            }
+        }
+    }
+
+    /// Open the temp file for a raw download, creating it if needed.
+    async fn open_download_file(directory: Arc<Dir>, path: String) -> Result<File> {
+        task::spawn_blocking({
+            let path = path.clone();
+
+            move || {
+                directory.open_with(
+                    &path,
+                    OpenOptions::new().create(true).read(true).write(true),
+                )
+            }
+        })
+        .await
+        .map_err(Error::Panic)?
+        .map(|f| File::from_std(f.into_std()))
+        .map_err(|e| Error::OpenFile(path, e))
+    }
+
+    /// Get the resume state for a segmented download of an unsplit file. If
+    /// there is no existing state, the reported sizes are reliable, and the
+    /// remaining bytes after `start` are large enough to be split, then a new
+    /// state is saved and the temp file is preallocated. Otherwise, [`None`] is returned and the file should be
+    /// downloaded normally.
+    fn prepare_segments(
+        directory: &Dir,
+        firmware: &FirmwareInfo,
+        file_index: usize,
+        download_path: &str,
+        segments_path: &str,
+        start: u64,
+        segmentation: Segmentation,
+    ) -> Result<Option<SegmentState>> {
+        let file_info = &firmware.files[file_index];
+
+        if file_info.is_split() {
+            return Ok(None);
+        }
+
+        if let Some(state) = SegmentState::load(directory, segments_path, file_info.size)? {
+            return Ok(Some(state));
+        }
+
+        // The ranges are computed from the reported size. If it can't be
+        // trusted, the file is downloaded normally until the end.
+        if !firmware.has_reliable_sizes() {
+            return Ok(None);
+        }
+
+        let count = segmentation.count(file_info.size.saturating_sub(start));
+        if count <= 1 {
+            return Ok(None);
+        }
+
+        // The state is saved first so that a preallocated file is never
+        // mistaken for a complete regular download.
+        let state = SegmentState::new(file_info.size, start, count);
+        state.save(directory, segments_path)?;
+
+        let file = directory
+            .open_with(download_path, OpenOptions::new().create(true).write(true))
+            .map_err(|e| Error::OpenFile(download_path.to_owned(), e))?;
+        filesystem::preallocate(&file, file_info.size)
+            .map_err(|e| Error::WriteFile(download_path.to_owned(), e))?;
+
+        debug!(
+            "[{download_path}] Split into {count} segments: {:?}",
+            state.segments,
+        );
+
+        Ok(Some(state))
+    }
+
+    /// Download the incomplete segments of a segmented download concurrently.
+    /// Each segment writes to its own region of the preallocated temp file.
+    #[allow(clippy::too_many_arguments)]
+    async fn download_segments(
+        directory: Arc<Dir>,
+        download_path: String,
+        tracker: Arc<SegmentTracker>,
+        client: Arc<NuClient>,
+        firmware: Arc<FirmwareInfo>,
+        file_index: usize,
+        download_index: u32,
+        retry_policy: RetryPolicy,
+        timeouts: StreamTimeouts,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        progress_tx: mpsc::Sender<ProgressMessage>,
+    ) -> Result<()> {
+        let pending = tracker
+            .state
+            .lock()
+            .unwrap()
+            .segments
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| !s.is_complete())
+            .map(|(i, s)| (i, s.offset()))
+            .collect::<Vec<_>>();
+        let mut tasks = JoinSet::new();
+
+        for (index, offset) in pending {
+            let directory = directory.clone();
+            let download_path = download_path.clone();
+            let tracker = tracker.clone();
+            let client = client.clone();
+            let firmware = firmware.clone();
+            let retry_policy = retry_policy.clone();
+            let rate_limiter = rate_limiter.clone();
+            let progress_tx = progress_tx.clone();
+
+            tasks.spawn(async move {
+                // Each segment needs its own file offset.
+                let mut file = Self::open_download_file(directory, download_path.clone()).await?;
+                file.seek(SeekFrom::Start(offset))
+                    .await
+                    .map_err(|e| Error::WriteFile(download_path, e))?;
+
+                Self::download_raw_with_retry(
+                    &mut file,
+                    &client,
+                    &firmware,
+                    file_index,
+                    download_index,
+                    Some((&tracker, index)),
+                    &retry_policy,
+                    timeouts,
+                    rate_limiter.as_deref(),
+                    &progress_tx,
+                )
                 .await
-                .with_context(|| format!("Failed to write {} bytes", data.len()))?;
-
-            progress.update(data.len() as u64).await?;
+            });
         }
 
-        progress.flush().await?;
-
-        file.sync_all().await.context("Failed to sync file")?;
+        // Dropping the join set on failure aborts the remaining segments.
+        while let Some(result) = tasks.join_next().await {
+            result.map_err(Error::Panic)??;
+        }
 
         Ok(())
     }
 
     /// Download a single raw file to `directory`. If the temp file for the
-    /// download already exists, then the download is resumed. When complete,
-    /// the temp file is renamed to the target file name for split files or with
-    /// the [`VERIFY_EXT`] extension for unsplit files. Thus, this function is
-    /// not idempotent.
+    /// download already exists, then the download is resumed. Large unsplit
+    /// files are downloaded as concurrent segments according to
+    /// `segmentation`. When complete, the temp file is renamed to the target
+    /// file name for split files or with the [`VERIFY_EXT`] extension for
+    /// unsplit files. Thus, this function is not idempotent.
     #[allow(clippy::too_many_arguments)]
     async fn download_raw(
         base_directory: Arc<Dir>,
@@ -343,12 +1583,16 @@ impl Downloader {
         file_index: usize,
         download_index: u32,
         start: u64,
-        retries: u8,
+        retry_policy: RetryPolicy,
+        timeouts: StreamTimeouts,
+        segmentation: Segmentation,
+        rate_limiter: Option<Arc<RateLimiter>>,
         progress_tx: mpsc::Sender<ProgressMessage>,
     ) -> Result<()> {
         let file_info = &firmware.files[file_index];
         let path = file_info.download_name(download_index);
         let download_path = format!("{path}.{DOWNLOAD_EXT}");
+        let segments_path = format!("{path}.{SEGMENTS_EXT}");
 
         let directory = if let Some(name) = &file_info.directory {
             task::spawn_blocking({
@@ -357,68 +1601,80 @@ impl Downloader {
                 move || {
                     base_directory
                         .create_dir_all(&name)
-                        .with_context(|| format!("Failed to create directory: {name}"))?;
+                        .map_err(|e| Error::CreateDir(name.clone(), e))?;
 
                     base_directory
                         .open_dir(&name)
                         .map(Arc::new)
-                        .with_context(|| format!("Failed to open directory: {name}"))
+                        .map_err(|e| Error::OpenDir(name, e))
                 }
             })
-            .await??
+            .await
+            .map_err(Error::Panic)??
         } else {
             base_directory
         };
 
-        let mut file = task::spawn_blocking({
-            let directory = directory.clone();
-            let download_path = download_path.clone();
+        let segments = task::block_in_place(|| {
+            Self::prepare_segments(
+                &directory,
+                &firmware,
+                file_index,
+                &download_path,
+                &segments_path,
+                start,
+                segmentation,
+            )
+        })?;
+
+        if let Some(state) = segments {
+            let tracker = Arc::new(SegmentTracker {
+                directory: directory.clone(),
+                path: segments_path.clone(),
+                state: Mutex::new(state),
+            });
+
+            Self::download_segments(
+                directory.clone(),
+                download_path.clone(),
+                tracker,
+                client,
+                firmware.clone(),
+                file_index,
+                download_index,
+                retry_policy,
+                timeouts,
+                rate_limiter,
+                progress_tx,
+            )
+            .await?;
 
-            move || {
-                directory.open_with(
-                    &download_path,
-                    OpenOptions::new().create(true).read(true).write(true),
-                )
-            }
-        })
-        .await?
-        .map(|f| File::from_std(f.into_std()))
-        .with_context(|| format!("Failed to open file: {download_path}"))?;
+            // Without the state, the full size temp file is treated as a
+            // complete regular download if the rename below is interrupted.
+            task::block_in_place(|| delete_if_exists(&directory, Path::new(&segments_path)))?;
+        } else {
+            let mut file =
+                Self::open_download_file(directory.clone(), download_path.clone()).await?;
 
-        file.seek(SeekFrom::Start(start))
-            .await
-            .with_context(|| format!("Failed to seek to {start}: {download_path}"))?;
+            file.seek(SeekFrom::Start(start))
+                .await
+                .map_err(|e| Error::WriteFile(download_path.clone(), e))?;
 
-        for attempt in 0..=retries {
-            let ret = Self::download_raw_to_file(
+            Self::download_raw_with_retry(
                 &mut file,
-                client.clone(),
-                firmware.clone(),
+                &client,
+                &firmware,
                 file_index,
                 download_index,
-                progress_tx.clone(),
+                None,
+                &retry_policy,
+                timeouts,
+                rate_limiter.as_deref(),
+                &progress_tx,
             )
-            .await;
-
-            match ret {
-                Ok(()) => break,
-                Err(e) if attempt == retries => {
-                    return Err(e)
-                        .with_context(|| format!("Failed to download to: {download_path}"));
-                }
-                Err(e) => {
-                    warn!(
-                        "[Attempt #{}/{}] Failed to download to: {download_path}: {e:#}",
-                        attempt + 1,
-                        u16::from(retries) + 1,
-                    );
-                    time::sleep(RETRY_DELAY).await;
-                }
-            }
+            .await?;
         }
 
-        drop(file);
-
         let rename_path = if file_info.is_split() {
             path
         } else {
@@ -426,7 +1682,7 @@ impl Downloader {
         };
 
         task::block_in_place(|| directory.rename(&download_path, &directory, &rename_path))
-            .with_context(|| format!("Failed to move file: {download_path} -> {rename_path}"))?;
+            .map_err(|e| Error::MoveFile(download_path, rename_path, e))?;
 
         Ok(())
     }
@@ -439,7 +1695,10 @@ impl Downloader {
         file_index: usize,
         download_index: u32,
         start: u64,
-        retries: u8,
+        retry_policy: RetryPolicy,
+        timeouts: StreamTimeouts,
+        segmentation: Segmentation,
+        rate_limiter: Option<Arc<RateLimiter>>,
         progress_tx: mpsc::Sender<ProgressMessage>,
     ) -> TaskResult {
         let result = Self::download_raw(
@@ -449,7 +1708,10 @@ impl Downloader {
             file_index,
             download_index,
             start,
-            retries,
+            retry_policy,
+            timeouts,
+            segmentation,
+            rate_limiter,
             progress_tx,
         )
         .await;
@@ -471,45 +1733,50 @@ impl Downloader {
 
         let mut file = directory
             .open(&verify_path)
-            .with_context(|| format!("Failed to open file: {verify_path}"))?;
+            .map_err(|e| Error::OpenFile(verify_path.clone(), e))?;
 
         let mut hasher = Hasher::new();
         let mut buf = [0u8; 8192];
 
-        let mut progress =
-            ThrottledProgress::new(progress_tx, ProgressMessage::PostProcess, THROTTLE_DELAY);
+        let mut progress = ThrottledProgress::new(
+            progress_tx,
+            |bytes| ProgressMessage::PostProcess { file_index, bytes },
+            THROTTLE_DELAY,
+        );
 
         loop {
-            check_cancel(cancel_signal)?;
+            check_cancel(cancel_signal).map_err(Error::Cancelled)?;
 
             let n = file
                 .read(&mut buf)
-                .with_context(|| format!("Failed to read file: {verify_path}"))?;
+                .map_err(|e| Error::ReadFile(verify_path.clone(), e))?;
             if n == 0 {
                 break;
             }
 
             hasher.update(&buf[..n]);
 
-            progress.update_blocking(n as u64)?;
+            progress
+                .update_blocking(n as u64)
+                .map_err(Error::Progress)?;
         }
 
-        progress.flush_blocking()?;
+        progress.flush_blocking().map_err(Error::Progress)?;
 
         let digest = hasher.finalize();
         if digest != file_info.crc32 {
-            bail!(
-                "Expected CRC32 {:08X}, but have {digest:08X}: {}",
-                file_info.crc32,
-                file_info.path(),
-            );
+            return Err(Error::InvalidCrc32 {
+                path: file_info.path(),
+                actual: digest,
+                expected: file_info.crc32,
+            });
         }
 
         drop(file);
 
         directory
             .rename(&verify_path, directory, &file_info.name)
-            .with_context(|| format!("Failed to move file: {verify_path} -> {}", file_info.name))?;
+            .map_err(|e| Error::MoveFile(verify_path, file_info.name.clone(), e))?;
 
         Ok(())
     }
@@ -524,122 +1791,169 @@ impl Downloader {
         let file_info = &firmware.files[file_index];
         assert!(file_info.is_split(), "#{file_index} is not a split file");
 
-        // Split files use the ancient split zip mechanism from the DOS era and
-        // there are basically no libraries or tools that support reading them.
-        // Instead, we'll create a copy-on-write virtual file that presents them
-        // as a single concatenated file and then fix the file offsets in memory
-        // so that it looks like a regular zip.
-        let opener = SubdirOpener {
-            dir: directory.clone(),
-            paths: (0..file_info.download_count())
-                .map(|i| PathBuf::from(file_info.download_name(i)))
-                .collect(),
-        };
-        let joined = JoinedFile::new(opener).context("Failed to add splits to joined view")?;
+        let paths = (0..file_info.download_count())
+            .map(|i| PathBuf::from(file_info.download_name(i)))
+            .collect();
 
-        let expected_size = file_info.download_size();
-        let actual_size = joined.len();
+        let mut progress = ThrottledProgress::new(
+            progress_tx,
+            |bytes| ProgressMessage::PostProcess { file_index, bytes },
+            THROTTLE_DELAY,
+        );
+
+        extract_split_zip(
+            directory,
+            paths,
+            &file_info.name,
+            file_info.crc32,
+            Some(file_info.download_size()),
+            |n| progress.update_blocking(n).map_err(Error::Progress),
+            cancel_signal,
+        )?;
+
+        progress.flush_blocking().map_err(Error::Progress)?;
 
-        if actual_size != expected_size {
-            bail!(
-                "Expected pieces to total {expected_size} bytes, but have {actual_size} bytes: {}",
-                file_info.path(),
-            );
-        }
-
-        let split_ranges = joined.splits();
-        let mut cow_file = MemoryCowFile::new(joined, 4096)?;
-        split::fix_offsets(&mut cow_file, &split_ranges)
-            .with_context(|| format!("Failed to fix split zip offsets: {}", file_info.path()))?;
-        cow_file.rewind()?;
-
-        check_cancel(cancel_signal)?;
-
-        let mut buffer = vec![0u8; RECOMMENDED_BUFFER_SIZE];
-        let zip = ZipArchive::from_seekable(cow_file, &mut buffer)?;
-        let mut entries = zip.entries(&mut buffer);
-        let mut entry_info = None;
-
-        while let Some(cd_entry) = entries.next_entry().context("Failed to list zip entries")? {
-            let path = cd_entry.file_path();
-            let path = str::from_utf8(path.as_bytes())
-                .with_context(|| format!("Non-UTF-8 zip entry path: {path:?}"))?;
-
-            if path == file_info.name {
-                entry_info = Some((
-                    cd_entry.wayfinder(),
-                    cd_entry.compression_method(),
-                    cd_entry.crc32(),
-                ));
-            } else {
-                bail!("Unexpected zip entry: {path:?}");
-            }
-        }
+        Ok(())
+    }
 
-        let Some((wayfinder, compression_method, crc32)) = entry_info else {
-            bail!("Missing zip entry: {}", file_info.name);
-        };
+    /// Extract a split zip by reading the splits sequentially as they finish
+    /// downloading. `split_rx` receives the download index of each split as it
+    /// completes. The channel must be closed once there are no more pending
+    /// downloads for this file. If `delete` is set, each split is deleted
+    /// after it has been read, so a failed extraction must download them
+    /// again.
+    fn extract_streaming(
+        directory: &Dir,
+        firmware: &FirmwareInfo,
+        file_index: usize,
+        split_rx: std_mpsc::Receiver<u32>,
+        delete: bool,
+        progress_tx: mpsc::Sender<ProgressMessage>,
+        cancel_signal: &AtomicBool,
+    ) -> Result<()> {
+        let file_info = &firmware.files[file_index];
+        assert!(file_info.is_split(), "#{file_index} is not a split file");
 
-        let entry = zip
-            .get_entry(wayfinder)
-            .with_context(|| format!("Failed to open zip entry: {}", file_info.name))?;
+        let mut reader = BufReader::new(SplitStreamReader {
+            directory,
+            file_info,
+            split_rx,
+            cancel_signal,
+            delete,
+            index: 0,
+            file: None,
+            bytes_read: 0,
+        });
+
+        let header = LocalHeader::read(&mut reader).map_err(|e| zip_error(&file_info.path(), e))?;
+        if header.name != file_info.name {
+            return Err(Error::UnexpectedZipEntry(header.name));
+        }
 
-        // Only need to check the metadata field. ZipVerifier verifies the
-        // actual digest after reading to EOF.
-        if crc32 != file_info.crc32 {
-            bail!(
-                "Expected CRC32 {:08X}, but have {crc32:08X}: {}",
-                file_info.crc32,
-                file_info.name,
-            );
+        if let Some(crc32) = header.crc32
+            && crc32 != file_info.crc32
+        {
+            return Err(Error::InvalidCrc32 {
+                path: file_info.path(),
+                actual: crc32,
+                expected: file_info.crc32,
+            });
         }
 
-        let reader: Box<dyn Read> = match compression_method {
-            CompressionMethod::STORE => Box::new(entry.reader()),
-            CompressionMethod::DEFLATE => Box::new(DeflateDecoder::new(entry.reader())),
-            c => bail!("Unsupported zip compression method: {c:?}"),
+        let mut entry_reader: Box<dyn Read> = match header.compression_method {
+            zipstream::METHOD_STORE => Box::new(
+                (&mut reader).take(
+                    header
+                        .stored_size()
+                        .map_err(|e| zip_error(&file_info.path(), e))?,
+                ),
+            ),
+            zipstream::METHOD_DEFLATE => Box::new(bufread::DeflateDecoder::new(&mut reader)),
+            c => return Err(Error::UnsupportedCompression(c.to_string())),
         };
-        let mut reader = entry.verifying_reader(reader);
 
         let extract_path = format!("{}.{EXTRACT_EXT}", file_info.name);
         let mut file = directory
             .create(&extract_path)
-            .with_context(|| format!("Failed to create file: {extract_path}"))?;
+            .map_err(|e| Error::CreateFile(extract_path.clone(), e))?;
+        let mut hasher = Hasher::new();
         let mut buf = [0u8; 8192];
 
-        let mut progress =
-            ThrottledProgress::new(progress_tx, ProgressMessage::PostProcess, THROTTLE_DELAY);
+        let mut progress = ThrottledProgress::new(
+            progress_tx,
+            |bytes| ProgressMessage::PostProcess { file_index, bytes },
+            THROTTLE_DELAY,
+        );
 
         loop {
-            check_cancel(cancel_signal)?;
+            check_cancel(cancel_signal).map_err(Error::Cancelled)?;
 
-            let n = reader
+            let n = entry_reader
                 .read(&mut buf)
-                .with_context(|| format!("Failed to read split files: {}", file_info.path()))?;
+                .map_err(|e| Error::ReadZip(file_info.path(), e))?;
             if n == 0 {
                 break;
             }
 
+            hasher.update(&buf[..n]);
+
             file.write_all(&buf[..n])
-                .with_context(|| format!("Failed to write data: {extract_path}"))?;
+                .map_err(|e| Error::WriteFile(extract_path.clone(), e))?;
+
+            progress
+                .update_blocking(n as u64)
+                .map_err(Error::Progress)?;
+        }
+
+        progress.flush_blocking().map_err(Error::Progress)?;
 
-            progress.update_blocking(n as u64)?;
+        drop(entry_reader);
+
+        let digest = hasher.finalize();
+        if digest != file_info.crc32 {
+            return Err(Error::InvalidCrc32 {
+                path: file_info.path(),
+                actual: digest,
+                expected: file_info.crc32,
+            });
+        }
+
+        // Make sure the central directory agrees with the local header. This
+        // also waits for and consumes the remaining splits.
+        let trailer_crc32 = header
+            .read_trailer(&mut reader, MAX_ZIP_TRAILER_SIZE)
+            .map_err(|e| zip_error(&file_info.path(), e))?;
+        if let Some(crc32) = trailer_crc32
+            && crc32 != file_info.crc32
+        {
+            return Err(Error::InvalidCrc32 {
+                path: file_info.path(),
+                actual: crc32,
+                expected: file_info.crc32,
+            });
         }
 
-        progress.flush_blocking()?;
+        let expected_size = file_info.download_size();
+        let actual_size = reader.get_ref().bytes_read;
+
+        if actual_size != expected_size {
+            return Err(Error::InvalidSize {
+                path: file_info.path(),
+                actual: actual_size,
+                expected: expected_size,
+            });
+        }
 
-        check_cancel(cancel_signal)?;
+        check_cancel(cancel_signal).map_err(Error::Cancelled)?;
 
         file.sync_all()
-            .with_context(|| format!("Failed to sync data: {extract_path}"))?;
+            .map_err(|e| Error::WriteFile(extract_path.clone(), e))?;
 
         drop(file);
 
         directory
             .rename(&extract_path, directory, &file_info.name)
-            .with_context(|| {
-                format!("Failed to move file: {extract_path} -> {}", file_info.name)
-            })?;
+            .map_err(|e| Error::MoveFile(extract_path, file_info.name.clone(), e))?;
 
         Ok(())
     }
@@ -656,7 +1970,7 @@ impl Downloader {
 
         if !keep_raw {
             for i in 0..file_info.download_count() {
-                check_cancel(cancel_signal)?;
+                check_cancel(cancel_signal).map_err(Error::Cancelled)?;
 
                 let path = file_info.download_name(i);
 
@@ -667,12 +1981,15 @@ impl Downloader {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn post_process(
         base_directory: Arc<Dir>,
         firmware: Arc<FirmwareInfo>,
         file_index: usize,
         keep_raw: bool,
+        delete_streamed: bool,
         clean_only: bool,
+        split_rx: Option<std_mpsc::Receiver<u32>>,
         progress_tx: mpsc::Sender<ProgressMessage>,
     ) -> Result<()> {
         let cancel_on_drop = CancelOnDrop::new();
@@ -685,13 +2002,23 @@ impl Downloader {
                 base_directory
                     .open_dir(name)
                     .map(Arc::new)
-                    .with_context(|| format!("Failed to open directory: {name}"))?
+                    .map_err(|e| Error::OpenDir(name.clone(), e))?
             } else {
                 base_directory
             };
 
             if file_info.is_split() {
-                if !clean_only {
+                if let Some(split_rx) = split_rx {
+                    Self::extract_streaming(
+                        &directory,
+                        &firmware,
+                        file_index,
+                        split_rx,
+                        delete_streamed,
+                        progress_tx,
+                        &cancel_signal,
+                    )?;
+                } else if !clean_only {
                     Self::extract(
                         &directory,
                         &firmware,
@@ -712,17 +2039,21 @@ impl Downloader {
                 )
             }
         })
-        .await??;
+        .await
+        .map_err(Error::Panic)??;
 
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn post_process_task(
         base_directory: Arc<Dir>,
         firmware: Arc<FirmwareInfo>,
         file_index: usize,
         keep_raw: bool,
+        delete_streamed: bool,
         clean_only: bool,
+        split_rx: Option<std_mpsc::Receiver<u32>>,
         progress_tx: mpsc::Sender<ProgressMessage>,
     ) -> TaskResult {
         let result = Self::post_process(
@@ -730,7 +2061,9 @@ impl Downloader {
             firmware,
             file_index,
             keep_raw,
+            delete_streamed,
             clean_only,
+            split_rx,
             progress_tx,
         )
         .await;
@@ -747,7 +2080,8 @@ impl Downloader {
 
             move || Self::write_version_file(&directory, &car, &firmware)
         })
-        .await??;
+        .await
+        .map_err(Error::Panic)??;
 
         let mut state = {
             let cancel_on_drop = CancelOnDrop::new();
@@ -755,36 +2089,91 @@ impl Downloader {
 
             let base_directory = self.directory.clone();
             let firmware = self.firmware.clone();
+            let stream_extract = self.options.stream_extract;
 
             task::spawn_blocking(move || {
-                Self::compute_initial_state(&base_directory, &firmware, &cancel_signal)
+                Self::compute_initial_state(
+                    Some(&base_directory),
+                    &firmware,
+                    stream_extract,
+                    &cancel_signal,
+                )
             })
-            .await??
+            .await
+            .map_err(Error::Panic)??
         };
 
+        // Make sure the download won't run out of space midway through. This
+        // assumes that subdirectories are on the same filesystem.
+        if self.options.skip_space_check {
+            debug!("Skipping free space check");
+        } else {
+            let estimate = SpaceEstimate::new(&self.firmware, &state, &self.options);
+            let reliable = self.firmware.has_reliable_sizes();
+
+            let directory = self.directory.clone();
+            match task::spawn_blocking(move || filesystem::space(&directory))
+                .await
+                .map_err(Error::Panic)?
+            {
+                Ok(space) => estimate.check(space.available, reliable)?,
+                Err(e) => warn!("Failed to query free space: {e}"),
+            }
+        }
+
+        // For streaming extraction, the extraction tasks are notified whenever
+        // a split finishes downloading. Dropping the sender indicates that
+        // there are no more downloads.
+        let mut split_txs = HashMap::new();
+        let mut split_rxs = HashMap::new();
+
+        for params in &state.pp_tasks {
+            let file_info = &self.firmware.files[params.file_index];
+
+            if self.options.stream_extract && file_info.is_split() && !params.clean_only {
+                let (tx, rx) = std_mpsc::channel();
+
+                if state.dl_remain[params.file_index] > 0 {
+                    split_txs.insert(params.file_index, tx);
+                }
+                split_rxs.insert(params.file_index, rx);
+            }
+        }
+
         // Report initial progress.
         let dl_total = self.firmware.files.iter().map(|f| f.download_size()).sum();
         let pp_total = self.firmware.size;
 
         self.progress_tx
-            .send(ProgressMessage::TotalDownload(dl_total))
-            .await?;
-        self.progress_tx
-            .send(ProgressMessage::TotalPostProcess(pp_total))
-            .await?;
-        self.progress_tx
-            .send(ProgressMessage::Download(state.dl_bytes))
-            .await?;
+            .send(ProgressMessage::TotalDownload { bytes: dl_total })
+            .await
+            .map_err(Error::Progress)?;
         self.progress_tx
-            .send(ProgressMessage::PostProcess(state.pp_bytes))
-            .await?;
+            .send(ProgressMessage::TotalPostProcess { bytes: pp_total })
+            .await
+            .map_err(Error::Progress)?;
+
+        for (file_index, (&download_bytes, &post_process_bytes)) in
+            state.dl_bytes.iter().zip(&state.pp_bytes).enumerate()
+        {
+            if download_bytes > 0 || post_process_bytes > 0 {
+                self.progress_tx
+                    .send(ProgressMessage::Resumed {
+                        file_index,
+                        download_bytes,
+                        post_process_bytes,
+                    })
+                    .await
+                    .map_err(Error::Progress)?;
+            }
+        }
 
         let mut tasks = JoinSet::new();
         let mut dl_running = 0;
         let mut pp_running = 0;
 
         loop {
-            while dl_running < self.concurrency {
+            while dl_running < self.options.concurrency {
                 let Some(params) = state.dl_tasks.pop_front() else {
                     break;
                 };
@@ -793,6 +2182,16 @@ impl Downloader {
                     "[Download#{}:{}] Task starting",
                     params.file_index, params.download_index,
                 );
+                self.progress_tx
+                    .send(ProgressMessage::TaskStarted {
+                        task: Task::Download {
+                            file_index: params.file_index,
+                            download_index: params.download_index,
+                        },
+                        start_offset: params.start_offset,
+                    })
+                    .await
+                    .map_err(Error::Progress)?;
                 dl_running += 1;
                 tasks.spawn(Self::download_task(
                     self.directory.clone(),
@@ -801,24 +2200,38 @@ impl Downloader {
                     params.file_index,
                     params.download_index,
                     params.start_offset,
-                    self.retries,
+                    self.options.retry_policy.clone(),
+                    self.options.timeouts,
+                    self.options.segmentation,
+                    self.options.rate_limiter.clone(),
                     self.progress_tx.clone(),
                 ));
             }
 
-            while pp_running < self.concurrency {
+            while pp_running < self.options.concurrency {
                 let Some(params) = state.pp_tasks.pop_front() else {
                     break;
                 };
 
                 debug!("[PostProcess#{}] Task starting", params.file_index);
+                self.progress_tx
+                    .send(ProgressMessage::TaskStarted {
+                        task: Task::PostProcess {
+                            file_index: params.file_index,
+                        },
+                        start_offset: 0,
+                    })
+                    .await
+                    .map_err(Error::Progress)?;
                 pp_running += 1;
                 tasks.spawn(Self::post_process_task(
                     self.directory.clone(),
                     self.firmware.clone(),
                     params.file_index,
-                    self.keep_raw,
+                    self.options.keep_raw,
+                    self.options.deletes_streamed_splits(),
                     params.clean_only,
+                    split_rxs.remove(&params.file_index),
                     self.progress_tx.clone(),
                 ));
             }
@@ -827,7 +2240,7 @@ impl Downloader {
                 // All tasks exited.
                 None => break,
                 // Task panicked or cancelled.
-                Some(Err(e)) => return Err(e).context("Unexpected panic in task"),
+                Some(Err(e)) => return Err(Error::Panic(e)),
                 // Task completed.
                 Some(Ok(result)) => result,
             };
@@ -838,11 +2251,29 @@ impl Downloader {
                     dl_running -= 1;
                     result?;
 
+                    self.progress_tx
+                        .send(ProgressMessage::TaskCompleted {
+                            task: Task::Download {
+                                file_index: f_i,
+                                download_index: dl_i,
+                            },
+                        })
+                        .await
+                        .map_err(Error::Progress)?;
+
                     state.dl_remain[f_i] -= 1;
 
-                    // Begin post-processing if there's nothing left to download
-                    // for this output file.
-                    if state.dl_remain[f_i] == 0 {
+                    if let Some(tx) = split_txs.get(&f_i) {
+                        // The streaming extraction task is already running.
+                        // The receiver is gone if the task already failed.
+                        let _ = tx.send(dl_i);
+
+                        if state.dl_remain[f_i] == 0 {
+                            split_txs.remove(&f_i);
+                        }
+                    } else if state.dl_remain[f_i] == 0 {
+                        // Begin post-processing if there's nothing left to
+                        // download for this output file.
                         debug!("[Download#{f_i}:{dl_i}] Queuing post-processing task");
                         state.pp_tasks.push_back(PostProcessParams {
                             file_index: f_i,
@@ -854,6 +2285,13 @@ impl Downloader {
                     debug!("[PostProcess#{f_i}] Task completed");
                     pp_running -= 1;
                     result?;
+
+                    self.progress_tx
+                        .send(ProgressMessage::TaskCompleted {
+                            task: Task::PostProcess { file_index: f_i },
+                        })
+                        .await
+                        .map_err(Error::Progress)?;
                 }
             }
         }
@@ -861,3 +2299,739 @@ impl Downloader {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::mock::{
+        Failure, MockCar, MockServer, TempDir, download_options, mock_car, split_zip, test_data,
+    };
+
+    fn options(retries: u8, keep_raw: bool, stream_extract: bool) -> DownloadOptions {
+        DownloadOptions {
+            retry_policy: RetryPolicy {
+                initial_delay: Duration::from_millis(10),
+                ..RetryPolicy::new(retries)
+            },
+            keep_raw,
+            stream_extract,
+            ..download_options()
+        }
+    }
+
+    fn assert_outputs(temp_dir: &TempDir, car: &MockCar) {
+        for file in &car.files {
+            let mut path = temp_dir.path().to_owned();
+            if let Some(d) = &file.directory {
+                path.push(d);
+            }
+            path.push(&file.name);
+
+            assert_eq!(std::fs::read(&path).unwrap(), file.data, "{path:?}");
+        }
+
+        assert!(temp_dir.path().join("CAR1.ver").exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download() {
+        for stream_extract in [false, true] {
+            let car = mock_car();
+            let server = MockServer::start(vec![car.clone()]).await;
+            let temp_dir = TempDir::new();
+
+            server
+                .download(&temp_dir, options(0, false, stream_extract))
+                .await
+                .unwrap();
+
+            assert_outputs(&temp_dir, &car);
+            for name in ["split.z01", "split.z02", "split.zip", "single.zip"] {
+                assert!(!temp_dir.path().join(name).exists(), "{name}");
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_keep_raw() {
+        for stream_extract in [false, true] {
+            let car = mock_car();
+            let server = MockServer::start(vec![car.clone()]).await;
+            let temp_dir = TempDir::new();
+
+            server
+                .download(&temp_dir, options(0, true, stream_extract))
+                .await
+                .unwrap();
+
+            assert_outputs(&temp_dir, &car);
+            for name in ["split.z01", "split.z02", "split.zip", "single.zip"] {
+                assert!(temp_dir.path().join(name).exists(), "{name}");
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_stream_extract_corrupt() {
+        let mut car = mock_car();
+        car.files.truncate(1);
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+
+        // Replace the first split with one from different data.
+        let splits = split_zip("split.tar", &test_data(64 * 1024, 4), 3);
+        std::fs::write(temp_dir.path().join("split.z01"), &splits[0]).unwrap();
+
+        let err = server
+            .download(&temp_dir, options(0, false, true))
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("CRC32"), "{err:#}");
+        assert!(!temp_dir.path().join("split.tar").exists());
+
+        // Splits can be kept until the output is verified, so only the bad
+        // split needs to be replaced.
+        let options = DownloadOptions {
+            keep_splits_until_verified: true,
+            ..options(0, false, true)
+        };
+        let temp_dir = TempDir::new();
+        let good = split_zip("split.tar", &car.files[0].data, 3);
+        let mut bad = good[1].clone();
+        for b in &mut bad[..256] {
+            *b = !*b;
+        }
+        for (name, data) in ["split.z01", "split.z02", "split.zip"]
+            .into_iter()
+            .zip([&good[0], &bad, &good[2]])
+        {
+            std::fs::write(temp_dir.path().join(name), data).unwrap();
+        }
+
+        server
+            .download(&temp_dir, options.clone())
+            .await
+            .unwrap_err();
+        assert!(!temp_dir.path().join("split.tar").exists());
+        for name in ["split.z01", "split.z02", "split.zip"] {
+            assert!(temp_dir.path().join(name).exists(), "{name}");
+        }
+
+        std::fs::write(temp_dir.path().join("split.z02"), &good[1]).unwrap();
+        let requests = server.requests().len();
+
+        server.download(&temp_dir, options).await.unwrap();
+        assert_outputs(&temp_dir, &car);
+        assert!(!temp_dir.path().join("split.z01").exists());
+        assert!(
+            server.requests()[requests..]
+                .iter()
+                .all(|r| !r.contains("split.z")),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_split_stream_reader_delete() {
+        let car = mock_car();
+        let server = MockServer::start(vec![car.clone()]).await;
+        let (_, firmware) = server.firmware().await;
+        let file_info = &firmware.files[0];
+
+        let temp_dir = TempDir::new();
+        let splits = split_zip("split.tar", &car.files[0].data, 3);
+        let names = ["split.z01", "split.z02", "split.zip"];
+        for (name, data) in names.into_iter().zip(&splits) {
+            std::fs::write(temp_dir.path().join(name), data).unwrap();
+        }
+
+        let directory = temp_dir.open();
+        let cancel_signal = AtomicBool::new(false);
+        // All splits are already downloaded.
+        let (_, split_rx) = std_mpsc::channel();
+        let mut reader = SplitStreamReader {
+            directory: &directory,
+            file_info,
+            split_rx,
+            cancel_signal: &cancel_signal,
+            delete: true,
+            index: 0,
+            file: None,
+            bytes_read: 0,
+        };
+
+        // Each split is deleted as soon as the reader moves past it.
+        let mut buf = vec![0u8; splits[0].len() + 1];
+        reader.read_exact(&mut buf).unwrap();
+        assert!(!temp_dir.path().join(names[0]).exists());
+        assert!(temp_dir.path().join(names[1]).exists());
+        assert!(temp_dir.path().join(names[2]).exists());
+
+        let mut rest = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(buf.len() + rest.len(), splits.concat().len());
+        for name in names {
+            assert!(!temp_dir.path().join(name).exists(), "{name}");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_resume() {
+        let car = mock_car();
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+
+        let splits = split_zip("split.tar", &car.files[0].data, 3);
+        std::fs::write(temp_dir.path().join("split.z01"), &splits[0]).unwrap();
+        std::fs::write(
+            temp_dir.path().join(format!("split.z02.{DOWNLOAD_EXT}")),
+            &splits[1][..100],
+        )
+        .unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        std::fs::write(
+            temp_dir
+                .path()
+                .join(format!("sub/unsplit.bin.{DOWNLOAD_EXT}")),
+            &car.files[2].data,
+        )
+        .unwrap();
+
+        let (result, messages) = server
+            .download_with_progress(&temp_dir, options(0, false, false))
+            .await;
+        result.unwrap();
+
+        assert_outputs(&temp_dir, &car);
+
+        // Per-task progress starts from the existing data.
+        let start_offsets = messages
+            .iter()
+            .filter_map(|m| match m {
+                ProgressMessage::TaskStarted {
+                    task: Task::Download { file_index, .. },
+                    start_offset,
+                } if *start_offset > 0 => Some((*file_index, *start_offset)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(start_offsets, [(0, 100), (2, 4096)]);
+
+        let requests = server.requests();
+        assert!(!requests.iter().any(|r| r.contains("split.z01")));
+        assert!(requests.contains(&"GET /firmware/CAR1%2Fsplit.z02 bytes=100-".to_owned()));
+        // The unsplit file was already complete, so the server returns HTTP
+        // 416 and the size is checked with a HEAD request.
+        assert!(requests.contains(&"GET /firmware/CAR1%2Funsplit.bin bytes=4096-".to_owned()));
+        assert!(requests.contains(&"HEAD /firmware/CAR1%2Funsplit.bin".to_owned()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_segmented() {
+        let car = mock_car();
+        let mut options = options(0, false, false);
+        options.segmentation = Segmentation {
+            max_segments: 4,
+            min_segment_size: 1024,
+        };
+        let unsplit_path = "sub/unsplit.bin";
+        let range_requests = |server: &MockServer| {
+            server
+                .requests()
+                .into_iter()
+                .filter(|r| r.starts_with("GET /firmware/CAR1%2Funsplit.bin"))
+                .map(|r| r.rsplit(' ').next().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+
+        server.download(&temp_dir, options.clone()).await.unwrap();
+
+        assert_outputs(&temp_dir, &car);
+        assert!(
+            !temp_dir
+                .path()
+                .join(format!("{unsplit_path}.{SEGMENTS_EXT}"))
+                .exists()
+        );
+
+        let mut requests = range_requests(&server);
+        requests.sort();
+        assert_eq!(
+            requests,
+            [
+                "bytes=0-1023",
+                "bytes=1024-2047",
+                "bytes=2048-3071",
+                "bytes=3072-4095",
+            ],
+        );
+
+        // Resume an interrupted segmented download.
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+        let data = &car.files[2].data;
+
+        let mut partial = data[..2148].to_vec();
+        partial.resize(data.len(), 0);
+        let state = SegmentState {
+            size: data.len() as u64,
+            segments: vec![
+                Segment {
+                    start: 0,
+                    end: 2048,
+                    done: 2048,
+                },
+                Segment {
+                    start: 2048,
+                    end: 4096,
+                    done: 100,
+                },
+            ],
+        };
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        std::fs::write(
+            temp_dir
+                .path()
+                .join(format!("{unsplit_path}.{DOWNLOAD_EXT}")),
+            partial,
+        )
+        .unwrap();
+        std::fs::write(
+            temp_dir
+                .path()
+                .join(format!("{unsplit_path}.{SEGMENTS_EXT}")),
+            serde_json::to_vec(&state).unwrap(),
+        )
+        .unwrap();
+
+        let plan =
+            Downloader::plan(Some(&temp_dir.open()), &server.firmware().await.1, &options).unwrap();
+        assert_eq!(plan.files[2].downloaded_bytes, 2148);
+
+        server.download(&temp_dir, options).await.unwrap();
+
+        assert_outputs(&temp_dir, &car);
+        assert_eq!(range_requests(&server), ["bytes=2148-4095"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_segment_overrun() {
+        let mut car = mock_car();
+        car.files.retain(|f| f.name == "unsplit.bin");
+        let mut options = options(0, false, false);
+        options.segmentation = Segmentation {
+            max_segments: 4,
+            min_segment_size: 1024,
+        };
+
+        // Extra data is discarded instead of overwriting the next segment.
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+        for _ in 0..4 {
+            server.inject_failure("unsplit.bin", Failure::Overrun);
+        }
+
+        server.download(&temp_dir, options.clone()).await.unwrap();
+        assert_outputs(&temp_dir, &car);
+
+        // A different range than what was requested is rejected.
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+        for _ in 0..4 {
+            server.inject_failure("unsplit.bin", Failure::IgnoreRangeEnd);
+        }
+
+        let err = server.download(&temp_dir, options).await.unwrap_err();
+        assert!(format!("{err:#}").contains("Content-Range"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn test_download_plan() {
+        let car = mock_car();
+        let server = MockServer::start(vec![car.clone()]).await;
+        let (_, firmware) = server.firmware().await;
+
+        let plan = Downloader::plan(None, &firmware, &options(0, false, false)).unwrap();
+        assert_eq!(
+            plan.remaining_bytes,
+            firmware
+                .files
+                .iter()
+                .map(|f| f.download_size())
+                .sum::<u64>(),
+        );
+        assert_eq!(plan.files[0].action, PlannedAction::Extract);
+        assert_eq!(plan.files[0].downloads.len(), 3);
+        assert_eq!(
+            plan.files[0].delete,
+            ["split.z01", "split.z02", "split.zip"]
+        );
+        assert_eq!(plan.files[2].action, PlannedAction::Verify);
+        assert_eq!(plan.files[2].downloads[0].path, "sub/unsplit.bin");
+
+        // The plan uses the same options as the download.
+        let kept = Downloader::plan(None, &firmware, &options(0, true, true)).unwrap();
+        assert!(kept.files[0].delete.is_empty());
+        let mut serial = options(0, false, true);
+        serial.concurrency = 1;
+        let serial = Downloader::plan(None, &firmware, &serial).unwrap();
+        assert!(kept.peak_space_bytes > serial.peak_space_bytes);
+
+        let temp_dir = TempDir::new();
+        let splits = split_zip("split.tar", &car.files[0].data, 3);
+        std::fs::write(temp_dir.path().join("split.z01"), &splits[0]).unwrap();
+        std::fs::write(
+            temp_dir.path().join(format!("split.z02.{DOWNLOAD_EXT}")),
+            &splits[1][..100],
+        )
+        .unwrap();
+        std::fs::write(temp_dir.path().join("single.tar"), &car.files[1].data).unwrap();
+        std::fs::write(temp_dir.path().join("single.zip"), b"").unwrap();
+
+        let plan =
+            Downloader::plan(Some(&temp_dir.open()), &firmware, &options(0, false, false)).unwrap();
+        let split = &plan.files[0];
+        assert_eq!(split.downloaded_bytes, splits[0].len() as u64 + 100);
+        assert_eq!(
+            split
+                .downloads
+                .iter()
+                .map(|d| (d.download_index, d.start_offset))
+                .collect::<Vec<_>>(),
+            [(1, 100), (2, 0)],
+        );
+        assert_eq!(plan.files[1].action, PlannedAction::Clean);
+        assert!(plan.files[1].downloads.is_empty());
+        assert_eq!(plan.files[1].delete, ["single.zip"]);
+        assert_eq!(plan.files[1].remaining_bytes, 0);
+
+        // Nothing was written.
+        let names = std::fs::read_dir(temp_dir.path()).unwrap().count();
+        assert_eq!(names, 4);
+    }
+
+    #[tokio::test]
+    async fn test_space_estimate() {
+        let car = mock_car();
+        let server = MockServer::start(vec![car.clone()]).await;
+        let (_, firmware) = server.firmware().await;
+        let split_size = firmware.files[0].download_size();
+        let single_size = firmware.files[1].download_size();
+        let total_size = firmware
+            .files
+            .iter()
+            .map(|f| f.download_size())
+            .sum::<u64>();
+
+        let temp_dir = TempDir::new();
+        std::fs::write(temp_dir.path().join("single.tar"), &car.files[1].data).unwrap();
+        std::fs::write(
+            temp_dir.path().join(format!("split.z01.{DOWNLOAD_EXT}")),
+            [0u8; 100],
+        )
+        .unwrap();
+
+        let state = Downloader::compute_initial_state(
+            Some(&temp_dir.open()),
+            &firmware,
+            false,
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+
+        let estimate = SpaceEstimate::new(&firmware, &state, &download_options());
+        assert_eq!(
+            estimate,
+            SpaceEstimate {
+                download: total_size - single_size - 100,
+                extract: 64 * 1024,
+                release: split_size,
+                overlap: split_size,
+            },
+        );
+        assert_eq!(estimate.peak(), total_size - single_size - 100 + 64 * 1024);
+
+        assert!(estimate.check(estimate.peak(), true).is_ok());
+        let err = estimate.check(estimate.peak() - 1, true).unwrap_err();
+        assert!(
+            err.to_string().contains("Extracted output: 65536 bytes"),
+            "{err}"
+        );
+        assert!(estimate.check(0, false).is_ok());
+
+        // Only the split zips of the file being extracted coexist with the
+        // extracted outputs.
+        let mut firmware = firmware;
+        firmware.files.push(firmware.files[0].clone());
+        let state =
+            Downloader::compute_initial_state(None, &firmware, false, &AtomicBool::new(false))
+                .unwrap();
+        let options = DownloadOptions {
+            concurrency: 1,
+            ..download_options()
+        };
+        let estimate = SpaceEstimate::new(&firmware, &state, &options);
+        assert_eq!(estimate.release, 2 * split_size + single_size);
+        assert_eq!(estimate.overlap, split_size);
+        assert_eq!(
+            estimate.peak(),
+            estimate.download + estimate.extract + split_size - estimate.release,
+        );
+
+        // Streamed splits are deleted as they are read, so only one split
+        // coexists with the extracted output.
+        let streamed = SpaceEstimate::new(
+            &firmware,
+            &state,
+            &DownloadOptions {
+                stream_extract: true,
+                ..options.clone()
+            },
+        );
+        assert_eq!(streamed.release, estimate.release);
+        assert_eq!(streamed.overlap, split_size.div_ceil(3));
+        let verified = SpaceEstimate::new(
+            &firmware,
+            &state,
+            &DownloadOptions {
+                stream_extract: true,
+                keep_splits_until_verified: true,
+                ..options.clone()
+            },
+        );
+        assert_eq!(verified, estimate);
+
+        let kept = SpaceEstimate::new(
+            &firmware,
+            &state,
+            &DownloadOptions {
+                keep_raw: true,
+                ..options
+            },
+        );
+        assert_eq!((kept.release, kept.overlap), (0, 0));
+        assert_eq!(kept.peak(), kept.download + kept.extract);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_retry() {
+        let car = mock_car();
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+
+        server.inject_failure("split.z02", Failure::Truncate(1000));
+        server.inject_failure("unsplit.bin", Failure::Status(StatusCode::BAD_GATEWAY));
+
+        server
+            .download(&temp_dir, options(1, false, true))
+            .await
+            .unwrap();
+
+        assert_outputs(&temp_dir, &car);
+
+        let requests = server.requests();
+        assert!(requests.contains(&"GET /firmware/CAR1%2Fsplit.z02 bytes=1000-".to_owned()));
+        assert_eq!(
+            requests
+                .iter()
+                .filter(|r| r.starts_with("GET /firmware/CAR1%2Funsplit.bin"))
+                .count(),
+            2,
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_stall() {
+        let car = mock_car();
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+
+        server.inject_failure("unsplit.bin", Failure::Stall(1000));
+
+        let mut options = options(1, false, false);
+        options.timeouts.idle = Some(Duration::from_millis(200));
+
+        server.download(&temp_dir, options).await.unwrap();
+
+        assert_outputs(&temp_dir, &car);
+        assert!(
+            server
+                .requests()
+                .contains(&"GET /firmware/CAR1%2Funsplit.bin bytes=1000-".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_stall_monitor() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let timeouts = StreamTimeouts {
+            idle: Some(Duration::from_millis(500)),
+            min_throughput: Some(MinThroughput {
+                bytes_per_sec: 1000,
+                window: Duration::from_secs(1),
+            }),
+        };
+
+        let mut monitor = StallMonitor::new(timeouts, start);
+        assert_eq!(monitor.deadline(), Some(at(500)));
+        monitor.update("a", at(400), 600).unwrap();
+        assert_eq!(monitor.deadline(), Some(at(900)));
+        monitor.update("a", at(800), 600).unwrap();
+        // The window resets after being checked.
+        monitor.update("a", at(1000), 0).unwrap();
+        assert_eq!(monitor.deadline(), Some(at(1300)));
+        assert!(matches!(
+            monitor.update("a", at(1300), 0),
+            Err(Error::IdleTimeout { .. }),
+        ));
+
+        let mut monitor = StallMonitor::new(timeouts, start);
+        monitor.update("a", at(400), 400).unwrap();
+        monitor.update("a", at(800), 400).unwrap();
+        assert!(matches!(
+            monitor.update("a", at(1000), 100),
+            Err(Error::LowThroughput { actual: 900, .. }),
+        ));
+
+        // Waiting for the rate limiter doesn't count.
+        let mut monitor = StallMonitor::new(timeouts, start);
+        monitor.update("a", at(400), 400).unwrap();
+        monitor.exclude(Duration::from_secs(10));
+        monitor.update("a", at(10_800), 400).unwrap();
+        monitor.update("a", at(11_000), 200).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_progress() {
+        let car = mock_car();
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+
+        server.inject_failure("single.zip", Failure::Status(StatusCode::BAD_GATEWAY));
+
+        let (result, messages) = server
+            .download_with_progress(&temp_dir, options(1, false, false))
+            .await;
+        result.unwrap();
+
+        let mut dl_total = None;
+        let mut pp_total = None;
+        let mut dl_bytes = vec![0u64; car.files.len()];
+        let mut pp_bytes = vec![0u64; car.files.len()];
+        let mut started = vec![];
+        let mut completed = vec![];
+        let mut retries = vec![];
+
+        for message in &messages {
+            match message {
+                ProgressMessage::TotalDownload { bytes } => dl_total = Some(*bytes),
+                ProgressMessage::TotalPostProcess { bytes } => pp_total = Some(*bytes),
+                ProgressMessage::Resumed { .. } => panic!("Nothing to resume"),
+                ProgressMessage::Download {
+                    file_index, bytes, ..
+                } => dl_bytes[*file_index] += bytes,
+                ProgressMessage::PostProcess { file_index, bytes } => {
+                    pp_bytes[*file_index] += bytes;
+                }
+                ProgressMessage::TaskStarted { task, .. } => started.push(format!("{task:?}")),
+                ProgressMessage::TaskCompleted { task } => completed.push(format!("{task:?}")),
+                ProgressMessage::TaskRetry { task, attempt, .. } => {
+                    retries.push((format!("{task:?}"), *attempt));
+                }
+            }
+        }
+
+        assert_eq!(dl_total, Some(dl_bytes.iter().sum()));
+        assert_eq!(pp_total, Some(pp_bytes.iter().sum()));
+        assert_eq!(
+            pp_bytes,
+            car.files
+                .iter()
+                .map(|f| f.data.len() as u64)
+                .collect::<Vec<_>>(),
+        );
+
+        // 3 + 1 + 1 + 1 downloads and 4 post-processing tasks.
+        started.sort();
+        completed.sort();
+        assert_eq!(started.len(), 10);
+        assert_eq!(started, completed);
+
+        assert_eq!(
+            retries,
+            [(
+                format!(
+                    "{:?}",
+                    Task::Download {
+                        file_index: 1,
+                        download_index: 0,
+                    }
+                ),
+                1,
+            )],
+        );
+
+        let json = serde_json::to_value(&messages[0]).unwrap();
+        assert_eq!(json["event"], "total_download");
+        assert_eq!(json["bytes"], dl_total.unwrap());
+
+        let json = serde_json::to_value(ProgressMessage::TaskStarted {
+            task: Task::PostProcess { file_index: 2 },
+            start_offset: 0,
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "event": "task_started",
+                "task": { "kind": "post_process", "file_index": 2 },
+                "start_offset": 0,
+            }),
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_retries_exhausted() {
+        let car = mock_car();
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+
+        for _ in 0..2 {
+            server.inject_failure("single.zip", Failure::Status(StatusCode::BAD_GATEWAY));
+        }
+
+        let err = server
+            .download(&temp_dir, options(1, false, false))
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("single.zip"), "{err:#}");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_download_fatal_not_retried() {
+        let car = mock_car();
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+
+        server.inject_failure("single.zip", Failure::Status(StatusCode::NOT_FOUND));
+
+        let err = server
+            .download(&temp_dir, options(3, false, false))
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("HTTP 404"), "{err:#}");
+        assert_eq!(
+            server
+                .requests()
+                .iter()
+                .filter(|r| r.starts_with("GET /firmware/CAR1%2Fsingle.zip"))
+                .count(),
+            1,
+        );
+    }
+}
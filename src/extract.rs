@@ -0,0 +1,419 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Offline extraction of split zips that were kept with `--keep-raw`. The
+//! split zips are found by their file names and the entry inside each set of
+//! splits. The `.ver` file is used as the source of truth for the output CRC32.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    io::{self, BufReader},
+    path::PathBuf,
+    sync::{Arc, atomic::AtomicBool},
+};
+
+use cap_std::fs::Dir;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::{
+    sync::mpsc::{self, error::SendError},
+    task::{self, JoinError, JoinSet},
+};
+use tracing::debug;
+
+use crate::{
+    cancel::CancelOnDrop,
+    client::ZipNamingScheme,
+    download,
+    progress::{THROTTLE_DELAY, ThrottledProgress},
+    verify::{self, Verifier},
+    version::VersionEntry,
+    zipstream::LocalHeader,
+};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Failed to list directory")]
+    ListDir(#[source] io::Error),
+    #[error("Failed to open directory: {0:?}")]
+    OpenDir(String, #[source] io::Error),
+    #[error("Failed to open file: {0:?}")]
+    OpenFile(String, #[source] io::Error),
+    #[error("Failed to delete file: {0:?}")]
+    DeleteFile(String, #[source] io::Error),
+    #[error("Multiple split zips contain {name:?}: {first:?}")]
+    DuplicateEntry { name: String, first: String },
+    #[error("No output file or split zips found: {0:?}")]
+    MissingSplits(String),
+    #[error("Failed to extract: {0:?}")]
+    Extract(String, #[source] download::Error),
+    #[error(transparent)]
+    Verify(#[from] verify::Error),
+    #[error(transparent)]
+    Progress(SendError<ProgressMessage>),
+    #[error(transparent)]
+    Panic(JoinError),
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Progress events. Files are identified by their index in the `.ver` file.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressMessage {
+    /// Total size of all files that need to be extracted.
+    Total {
+        bytes: u64,
+    },
+    Progress {
+        file_index: usize,
+        bytes: u64,
+    },
+    FileStarted {
+        file_index: usize,
+        path: String,
+    },
+    FileCompleted {
+        file_index: usize,
+    },
+}
+
+/// A single output file to extract from a set of splits.
+struct Job {
+    file_index: usize,
+    entry: VersionEntry,
+    splits: Vec<String>,
+}
+
+pub struct Extractor {
+    directory: Arc<Dir>,
+    concurrency: usize,
+    keep_raw: bool,
+    progress_tx: mpsc::Sender<ProgressMessage>,
+}
+
+impl Extractor {
+    pub fn new(
+        directory: Dir,
+        concurrency: usize,
+        keep_raw: bool,
+    ) -> (Self, mpsc::Receiver<ProgressMessage>) {
+        let (progress_tx, progress_rx) = mpsc::channel(2 * concurrency);
+
+        let result = Self {
+            directory: Arc::new(directory),
+            concurrency,
+            keep_raw,
+            progress_tx,
+        };
+
+        (result, progress_rx)
+    }
+
+    /// List all UTF-8 file names in a directory.
+    fn list_names(directory: &Dir) -> Result<BTreeSet<String>> {
+        let mut names = BTreeSet::new();
+
+        for entry in directory.entries().map_err(Error::ListDir)? {
+            let entry = entry.map_err(Error::ListDir)?;
+
+            if let Ok(name) = entry.file_name().into_string() {
+                names.insert(name);
+            }
+        }
+
+        Ok(names)
+    }
+
+    /// Find the splits for every entry in the `.ver` file that has not been
+    /// extracted yet.
+    fn find_jobs(directory: &Dir) -> Result<Vec<Job>> {
+        let info = Verifier::read_version_file(directory)?;
+
+        let mut by_directory = BTreeMap::<_, Vec<_>>::new();
+        for (file_index, entry) in info.entries.into_iter().enumerate() {
+            by_directory
+                .entry(entry.directory.clone())
+                .or_default()
+                .push((file_index, entry));
+        }
+
+        let mut jobs = vec![];
+
+        for (name, entries) in by_directory {
+            let subdirectory = match &name {
+                Some(name) => match directory.open_dir(name) {
+                    Ok(d) => Some(d),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(Error::OpenDir(name.clone(), e)),
+                },
+                None => Some(
+                    directory
+                        .try_clone()
+                        .map_err(|e| Error::OpenDir(".".to_owned(), e))?,
+                ),
+            };
+            let names = match &subdirectory {
+                Some(d) => Self::list_names(d)?,
+                None => BTreeSet::new(),
+            };
+
+            // Map the name of the entry inside each set of splits to the split
+            // file names.
+            let mut sets = BTreeMap::new();
+
+            for (scheme, count) in ZipNamingScheme::detect(&names) {
+                let Some(subdirectory) = &subdirectory else {
+                    break;
+                };
+                let first = scheme.name(0);
+                let file = subdirectory
+                    .open(&first)
+                    .map_err(|e| Error::OpenFile(first.clone(), e))?;
+
+                let header = match LocalHeader::read(&mut BufReader::new(file)) {
+                    Ok(h) => h,
+                    Err(e) => {
+                        debug!("Not the first split of a zip: {first}: {e}");
+                        continue;
+                    }
+                };
+
+                let splits = (0..count).map(|i| scheme.name(i)).collect::<Vec<_>>();
+
+                if let Some(mut existing) = sets.insert(header.name.clone(), splits) {
+                    return Err(Error::DuplicateEntry {
+                        name: header.name,
+                        first: existing.swap_remove(0),
+                    });
+                }
+            }
+
+            for (file_index, entry) in entries {
+                let path = entry.path();
+
+                if names.contains(&entry.filename) {
+                    debug!("Already extracted: {path}");
+                } else if let Some(splits) = sets.remove(&entry.filename) {
+                    debug!("Found splits for {path}: {splits:?}");
+                    jobs.push(Job {
+                        file_index,
+                        entry,
+                        splits,
+                    });
+                } else {
+                    return Err(Error::MissingSplits(path.into_owned()));
+                }
+            }
+        }
+
+        Ok(jobs)
+    }
+
+    fn extract_job(
+        directory: &Arc<Dir>,
+        job: &Job,
+        keep_raw: bool,
+        progress_tx: mpsc::Sender<ProgressMessage>,
+        cancel_signal: &AtomicBool,
+    ) -> Result<()> {
+        let directory = match &job.entry.directory {
+            Some(name) => Arc::new(
+                directory
+                    .open_dir(name)
+                    .map_err(|e| Error::OpenDir(name.clone(), e))?,
+            ),
+            None => directory.clone(),
+        };
+
+        let file_index = job.file_index;
+        let mut progress = ThrottledProgress::new(
+            progress_tx,
+            |bytes| ProgressMessage::Progress { file_index, bytes },
+            THROTTLE_DELAY,
+        );
+
+        download::extract_split_zip(
+            &directory,
+            job.splits.iter().map(PathBuf::from).collect(),
+            &job.entry.filename,
+            job.entry.crc32,
+            None,
+            // The receiver is only dropped when the extraction is cancelled.
+            |n| {
+                progress
+                    .update_blocking(n)
+                    .map_err(|e| download::Error::Cancelled(io::Error::other(e)))
+            },
+            cancel_signal,
+        )
+        .map_err(|e| Error::Extract(job.entry.path().into_owned(), e))?;
+
+        progress.flush_blocking().map_err(Error::Progress)?;
+
+        if !keep_raw {
+            for split in &job.splits {
+                directory
+                    .remove_file(split)
+                    .map_err(|e| Error::DeleteFile(split.clone(), e))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn extract_task(
+        directory: Arc<Dir>,
+        job: Job,
+        keep_raw: bool,
+        progress_tx: mpsc::Sender<ProgressMessage>,
+    ) -> (usize, Result<()>) {
+        let cancel_on_drop = CancelOnDrop::new();
+        let cancel_signal = cancel_on_drop.handle();
+
+        let file_index = job.file_index;
+
+        let result = task::spawn_blocking(move || {
+            Self::extract_job(&directory, &job, keep_raw, progress_tx, &cancel_signal)
+        })
+        .await
+        .map_err(Error::Panic)
+        .flatten();
+
+        (file_index, result)
+    }
+
+    /// Extract all files listed in the `.ver` file that only exist as split
+    /// zips. Returns the number of files that were extracted.
+    pub async fn extract(&self) -> Result<usize> {
+        // Finding the splits only requires reading a few headers, so this is
+        // not cancellable.
+        let mut jobs = task::spawn_blocking({
+            let directory = self.directory.clone();
+            move || Self::find_jobs(&directory)
+        })
+        .await
+        .map_err(Error::Panic)??
+        .into_iter()
+        .collect::<VecDeque<_>>();
+
+        let count = jobs.len();
+        let total_size = jobs.iter().map(|j| j.entry.size).sum();
+
+        self.progress_tx
+            .send(ProgressMessage::Total { bytes: total_size })
+            .await
+            .map_err(Error::Progress)?;
+
+        let mut tasks = JoinSet::new();
+        let mut running = 0;
+
+        loop {
+            while running < self.concurrency {
+                let Some(job) = jobs.pop_front() else {
+                    break;
+                };
+
+                debug!("[Extract#{}] Task starting", job.file_index);
+                self.progress_tx
+                    .send(ProgressMessage::FileStarted {
+                        file_index: job.file_index,
+                        path: job.entry.path().into_owned(),
+                    })
+                    .await
+                    .map_err(Error::Progress)?;
+                running += 1;
+                tasks.spawn(Self::extract_task(
+                    self.directory.clone(),
+                    job,
+                    self.keep_raw,
+                    self.progress_tx.clone(),
+                ));
+            }
+
+            let (file_index, result) = match tasks.join_next().await {
+                // All tasks exited.
+                None => break,
+                // Task panicked or cancelled.
+                Some(Err(e)) => return Err(Error::Panic(e)),
+                // Task completed.
+                Some(Ok(r)) => r,
+            };
+
+            debug!("[Extract#{file_index}] Task completed");
+            running -= 1;
+            result?;
+
+            self.progress_tx
+                .send(ProgressMessage::FileCompleted { file_index })
+                .await
+                .map_err(Error::Progress)?;
+        }
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        download::DownloadOptions,
+        mock::{
+            MockCar, MockFile, MockServer, TempDir, download_options, drain_progress, mock_car,
+            test_data,
+        },
+    };
+
+    async fn extract(temp_dir: &TempDir) -> Result<usize> {
+        let (extractor, progress_rx) = Extractor::new(temp_dir.open(), 2, false);
+        let progress = drain_progress(progress_rx);
+
+        let result = extractor.extract().await;
+        drop(extractor);
+        progress.await.unwrap();
+
+        result
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_extract() {
+        let car = MockCar {
+            files: vec![
+                MockFile::new("split.tar", test_data(8192, 1), 3),
+                MockFile::new("single.tar", test_data(1024, 2), 1).with_directory("sub"),
+                MockFile::new("unsplit.bin", test_data(1024, 3), 0),
+            ],
+            ..mock_car()
+        };
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+
+        let options = DownloadOptions {
+            keep_raw: true,
+            ..download_options()
+        };
+
+        server.download(&temp_dir, options).await.unwrap();
+        assert_eq!(extract(&temp_dir).await.unwrap(), 0);
+
+        std::fs::remove_file(temp_dir.path().join("split.tar")).unwrap();
+        std::fs::remove_file(temp_dir.path().join("sub/single.tar")).unwrap();
+
+        assert_eq!(extract(&temp_dir).await.unwrap(), 2);
+
+        for (file, path) in car.files.iter().zip(["split.tar", "sub/single.tar"]) {
+            let data = std::fs::read(temp_dir.path().join(path)).unwrap();
+            assert_eq!(data, file.data);
+        }
+
+        // The splits were deleted, so the output can no longer be recreated.
+        std::fs::remove_file(temp_dir.path().join("split.tar")).unwrap();
+
+        assert!(matches!(
+            extract(&temp_dir).await,
+            Err(Error::MissingSplits(p)) if p == "split.tar",
+        ));
+    }
+}
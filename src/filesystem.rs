@@ -0,0 +1,297 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Queries about the filesystem containing a directory.
+
+use std::{fmt, io};
+
+use cap_std::fs::{Dir, File};
+
+/// Type of a filesystem, as far as removable media is concerned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilesystemType {
+    /// FAT12, FAT16, or FAT32.
+    Fat,
+    Exfat,
+    Ntfs,
+    Other(String),
+}
+
+impl FilesystemType {
+    #[cfg_attr(any(target_os = "linux", target_os = "android"), allow(dead_code))]
+    fn from_name(name: &str) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "fat" | "fat12" | "fat16" | "fat32" | "msdos" | "msdosfs" | "vfat" => Self::Fat,
+            "exfat" => Self::Exfat,
+            "ntfs" => Self::Ntfs,
+            _ => Self::Other(name.to_owned()),
+        }
+    }
+
+    /// Largest file size that the filesystem supports, if limited.
+    pub fn max_file_size(&self) -> Option<u64> {
+        match self {
+            Self::Fat => Some(u32::MAX.into()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FilesystemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fat => f.write_str("FAT"),
+            Self::Exfat => f.write_str("exFAT"),
+            Self::Ntfs => f.write_str("NTFS"),
+            Self::Other(name) => f.write_str(name),
+        }
+    }
+}
+
+/// Size of a filesystem in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpaceInfo {
+    /// Total capacity.
+    pub total: u64,
+    /// Space available to the current user. This excludes space that is
+    /// reserved for privileged users.
+    pub available: u64,
+}
+
+/// Get the capacity and free space of the filesystem containing `directory`.
+#[cfg(unix)]
+pub fn space(directory: &Dir) -> io::Result<SpaceInfo> {
+    let stat = rustix::fs::fstatvfs(directory)?;
+
+    Ok(SpaceInfo {
+        total: stat.f_blocks.saturating_mul(stat.f_frsize),
+        available: stat.f_bavail.saturating_mul(stat.f_frsize),
+    })
+}
+
+/// Get the capacity and free space of the filesystem containing `directory`.
+#[cfg(windows)]
+pub fn space(directory: &Dir) -> io::Result<SpaceInfo> {
+    use std::{
+        os::windows::io::{AsHandle, AsRawHandle},
+        ptr,
+    };
+
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_NAME_NORMALIZED, GetDiskFreeSpaceExW, GetFinalPathNameByHandleW,
+    };
+
+    // GetDiskFreeSpaceExW() only accepts paths.
+    let handle = directory.as_handle().as_raw_handle();
+    let mut path = vec![0u16; 32768];
+
+    let len = unsafe {
+        GetFinalPathNameByHandleW(
+            handle,
+            path.as_mut_ptr(),
+            path.len() as u32,
+            FILE_NAME_NORMALIZED,
+        )
+    };
+    if len == 0 {
+        return Err(io::Error::last_os_error());
+    } else if len as usize >= path.len() {
+        return Err(io::Error::other("Directory path is too long"));
+    }
+
+    path.truncate(len as usize);
+    path.push(0);
+
+    let mut available = 0u64;
+    let mut total = 0u64;
+
+    let ret =
+        unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut available, &mut total, ptr::null_mut()) };
+    if ret == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(SpaceInfo { total, available })
+}
+
+/// Get the type of the filesystem containing `directory`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn filesystem_type(directory: &Dir) -> io::Result<FilesystemType> {
+    const MSDOS_SUPER_MAGIC: u32 = 0x4d44;
+    const EXFAT_SUPER_MAGIC: u32 = 0x2011bab0;
+    const NTFS_SB_MAGIC: u32 = 0x5346544e;
+    const NTFS3_SUPER_MAGIC: u32 = 0x7366746e;
+
+    let stat = rustix::fs::fstatfs(directory)?;
+
+    // The type varies between architectures, but the magic values all fit in
+    // 32 bits.
+    let result = match stat.f_type as u32 {
+        MSDOS_SUPER_MAGIC => FilesystemType::Fat,
+        EXFAT_SUPER_MAGIC => FilesystemType::Exfat,
+        NTFS_SB_MAGIC | NTFS3_SUPER_MAGIC => FilesystemType::Ntfs,
+        magic => FilesystemType::Other(format!("{magic:#x}")),
+    };
+
+    Ok(result)
+}
+
+/// Get the type of the filesystem containing `directory`.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "dragonfly",
+))]
+pub fn filesystem_type(directory: &Dir) -> io::Result<FilesystemType> {
+    let stat = rustix::fs::fstatfs(directory)?;
+    let name = stat
+        .f_fstypename
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as u8)
+        .collect::<Vec<_>>();
+
+    Ok(FilesystemType::from_name(&String::from_utf8_lossy(&name)))
+}
+
+/// Get the type of the filesystem containing `directory`.
+#[cfg(windows)]
+pub fn filesystem_type(directory: &Dir) -> io::Result<FilesystemType> {
+    use std::{
+        os::windows::io::{AsHandle, AsRawHandle},
+        ptr,
+    };
+
+    use windows_sys::Win32::Storage::FileSystem::GetVolumeInformationByHandleW;
+
+    let handle = directory.as_handle().as_raw_handle();
+    // MAX_PATH + 1, as required by the documentation.
+    let mut name = [0u16; 261];
+
+    let ret = unsafe {
+        GetVolumeInformationByHandleW(
+            handle,
+            ptr::null_mut(),
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            name.as_mut_ptr(),
+            name.len() as u32,
+        )
+    };
+    if ret == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+
+    Ok(FilesystemType::from_name(&String::from_utf16_lossy(
+        &name[..len],
+    )))
+}
+
+/// Get the type of the filesystem containing `directory`.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "dragonfly",
+    windows,
+)))]
+pub fn filesystem_type(_directory: &Dir) -> io::Result<FilesystemType> {
+    Ok(FilesystemType::Other("unknown".to_owned()))
+}
+
+/// Flush a directory's entries to disk so that renames are durable.
+#[cfg(unix)]
+pub fn sync_dir(directory: &Dir) -> io::Result<()> {
+    // cap-std may open directories with O_PATH, which cannot be synced.
+    let file = directory.open(".")?;
+    rustix::fs::fsync(&file)?;
+
+    Ok(())
+}
+
+/// Flush a directory's entries to disk so that renames are durable. Windows
+/// does not support syncing directories, so this does nothing.
+#[cfg(windows)]
+pub fn sync_dir(_directory: &Dir) -> io::Result<()> {
+    Ok(())
+}
+
+/// Reserve disk space for the first `len` bytes of a file, extending it if
+/// needed. If the filesystem does not support preallocation, the file is only
+/// extended, which may leave it sparse. Existing data is never truncated.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn preallocate(file: &File, len: u64) -> io::Result<()> {
+    use rustix::{fs::FallocateFlags, io::Errno};
+
+    match rustix::fs::fallocate(file, FallocateFlags::empty(), 0, len) {
+        Err(Errno::OPNOTSUPP) => extend(file, len),
+        r => Ok(r?),
+    }
+}
+
+/// Reserve disk space for the first `len` bytes of a file, extending it if
+/// needed. On this platform, the file is only extended, which may leave it
+/// sparse. Existing data is never truncated.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn preallocate(file: &File, len: u64) -> io::Result<()> {
+    extend(file, len)
+}
+
+fn extend(file: &File, len: u64) -> io::Result<()> {
+    if file.metadata()?.len() < len {
+        file.set_len(len)?;
+    }
+
+    Ok(())
+}
+
+/// Drop a file's cached pages so that subsequent reads come from the
+/// underlying device. The file must have already been synced. This is only a
+/// hint and does nothing on platforms that don't support it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn drop_cache(file: &File) -> io::Result<()> {
+    rustix::fs::fadvise(file, 0, None, rustix::fs::Advice::DontNeed)?;
+
+    Ok(())
+}
+
+/// Drop a file's cached pages so that subsequent reads come from the
+/// underlying device. The file must have already been synced. This is only a
+/// hint and does nothing on platforms that don't support it.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn drop_cache(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::TempDir;
+
+    #[test]
+    fn test_filesystem() {
+        let temp_dir = TempDir::new();
+        let directory = temp_dir.open();
+
+        let space = space(&directory).unwrap();
+        assert!(space.total > 0);
+        assert!(space.available <= space.total);
+
+        filesystem_type(&directory).unwrap();
+        sync_dir(&directory).unwrap();
+
+        assert_eq!(FilesystemType::from_name("vfat"), FilesystemType::Fat);
+        assert_eq!(FilesystemType::from_name("exFAT"), FilesystemType::Exfat);
+        assert_eq!(FilesystemType::Fat.max_file_size(), Some(u32::MAX.into()));
+        assert_eq!(FilesystemType::Exfat.max_file_size(), None);
+    }
+}
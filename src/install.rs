@@ -0,0 +1,579 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Copying downloaded firmware to removable media. The head unit expects the
+//! `.ver` file at the root of the drive and the firmware files at the paths
+//! listed in it. Any existing `.ver` file is deleted first and the new one is
+//! copied last so that an interrupted copy is never mistaken for a complete
+//! one.
+
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::AtomicBool},
+};
+
+use cap_std::fs::{Dir, OpenOptions};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::{
+    sync::mpsc::{self, error::SendError},
+    task::{self, JoinError},
+};
+use tracing::{debug, warn};
+
+use crate::{
+    cancel::{CancelOnDrop, check_cancel},
+    filesystem::{self, FilesystemType},
+    progress::{THROTTLE_DELAY, ThrottledProgress},
+    verify::{self, Verifier},
+    version::{VersionEntry, VersionInfo},
+};
+
+const INSTALL_EXT: &str = concat!(env!("CARGO_PKG_NAME"), "_install");
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Expected .ver file to be named: {0:?}")]
+    VerFileName(String),
+    #[error("Expected size {expected}, but have {actual}: {path:?}. Run `verify` first.")]
+    InvalidSize {
+        path: String,
+        actual: u64,
+        expected: u64,
+    },
+    #[error("File not found: {0:?}. Run `download` or `extract` first.")]
+    MissingFile(String),
+    #[error("Failed to list target directory")]
+    ListDir(#[source] io::Error),
+    #[error("Target already contains firmware for another vehicle: {0:?}")]
+    OtherVehicle(String),
+    #[error("Failed to query target filesystem type")]
+    FilesystemType(#[source] io::Error),
+    #[error("Failed to query target free space")]
+    Space(#[source] io::Error),
+    #[error(
+        "Files exceed the {fs_type} limit of {max_size} bytes. \
+        The drive must be formatted as exFAT instead:{}",
+        .files.iter().map(|(p, s)| format!("\n  {p} ({s} bytes)")).collect::<String>(),
+    )]
+    FilesTooLarge {
+        fs_type: FilesystemType,
+        max_size: u64,
+        files: Vec<(String, u64)>,
+    },
+    #[error(
+        "Firmware requires {required} bytes, but the target only has a capacity of {capacity} bytes"
+    )]
+    InsufficientCapacity { required: u64, capacity: u64 },
+    #[error(
+        "Not enough free space: {needed} bytes needed, but only {available} bytes available\n\
+        \x20 Firmware files: {required} bytes\n\
+        \x20 Existing firmware files to be replaced: {existing} bytes"
+    )]
+    InsufficientSpace {
+        needed: u64,
+        available: u64,
+        required: u64,
+        existing: u64,
+    },
+    #[error("Invalid path: {0:?}")]
+    InvalidPath(PathBuf),
+    #[error("Failed to stat: {0:?}")]
+    Stat(PathBuf, #[source] io::Error),
+    #[error("Failed to create directory: {0:?}")]
+    CreateDir(PathBuf, #[source] io::Error),
+    #[error("Failed to open directory: {0:?}")]
+    OpenDir(PathBuf, #[source] io::Error),
+    #[error("Failed to open file: {0:?}")]
+    OpenFile(PathBuf, #[source] io::Error),
+    #[error("Failed to create file: {0:?}")]
+    CreateFile(PathBuf, #[source] io::Error),
+    #[error("Failed to read file: {0:?}")]
+    ReadFile(PathBuf, #[source] io::Error),
+    #[error("Failed to write file: {0:?}")]
+    WriteFile(PathBuf, #[source] io::Error),
+    #[error("Failed to sync file: {0:?}")]
+    SyncFile(PathBuf, #[source] io::Error),
+    #[error("Failed to delete file: {0:?}")]
+    DeleteFile(PathBuf, #[source] io::Error),
+    #[error("Failed to move file: {0:?} -> {1:?}")]
+    MoveFile(PathBuf, PathBuf, #[source] io::Error),
+    #[error("Failed to sync directory of: {0:?}")]
+    SyncDir(PathBuf, #[source] io::Error),
+    #[error(transparent)]
+    Verify(#[from] verify::Error),
+    #[error(transparent)]
+    Progress(SendError<ProgressMessage>),
+    #[error(transparent)]
+    Cancelled(io::Error),
+    #[error(transparent)]
+    Panic(JoinError),
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Progress events. Files are identified by their index in the `.ver` file.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressMessage {
+    /// Total size of all files that need to be copied.
+    Total {
+        bytes: u64,
+    },
+    Progress {
+        file_index: usize,
+        bytes: u64,
+    },
+    FileStarted {
+        file_index: usize,
+        path: String,
+    },
+    FileCompleted {
+        file_index: usize,
+    },
+}
+
+/// Firmware that was checked and is ready to be copied.
+struct Prepared {
+    ver_name: String,
+    info: VersionInfo,
+}
+
+/// Fail if any file is too large for the filesystem.
+fn check_file_sizes(fs_type: &FilesystemType, entries: &[VersionEntry]) -> Result<()> {
+    let Some(max_size) = fs_type.max_file_size() else {
+        return Ok(());
+    };
+
+    let too_large = entries
+        .iter()
+        .filter(|e| e.size > max_size)
+        .map(|e| (e.path().into_owned(), e.size))
+        .collect::<Vec<_>>();
+
+    if !too_large.is_empty() {
+        return Err(Error::FilesTooLarge {
+            fs_type: fs_type.clone(),
+            max_size,
+            files: too_large,
+        });
+    }
+
+    Ok(())
+}
+
+/// Get the peak additional space needed to copy files in order. `sizes`
+/// contains the new size and the existing size of each file. Each file is
+/// written to a temporary path before it replaces the existing file, so both
+/// take up space at the same time.
+fn peak_usage(sizes: impl IntoIterator<Item = (u64, u64)>) -> u64 {
+    let mut usage = 0i128;
+    let mut peak = 0i128;
+
+    for (new, existing) in sizes {
+        peak = peak.max(usage + i128::from(new));
+        usage += i128::from(new) - i128::from(existing);
+    }
+
+    peak.max(0) as u64
+}
+
+/// Get the size of a file, or [`None`] if it doesn't exist.
+fn file_size(directory: &Dir, path: &str) -> Result<Option<u64>> {
+    match directory.metadata(path) {
+        Ok(m) => Ok(Some(m.len())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::Stat(path.into(), e)),
+    }
+}
+
+pub struct Installer {
+    source: Arc<Dir>,
+    target: Arc<Dir>,
+    progress_tx: mpsc::Sender<ProgressMessage>,
+}
+
+impl Installer {
+    pub fn new(source: Dir, target: Dir) -> (Self, mpsc::Receiver<ProgressMessage>) {
+        let (progress_tx, progress_rx) = mpsc::channel(2);
+
+        let result = Self {
+            source: Arc::new(source),
+            target: Arc::new(target),
+            progress_tx,
+        };
+
+        (result, progress_rx)
+    }
+
+    /// Make sure that the source firmware is complete and that the target
+    /// filesystem can hold it.
+    fn prepare(source: &Dir, target: &Dir) -> Result<Prepared> {
+        let info = Verifier::read_version_file(source)?;
+        let ver_name = format!("{}.ver", info.header.id);
+
+        let Some(ver_size) = file_size(source, &ver_name)? else {
+            return Err(Error::VerFileName(ver_name));
+        };
+
+        let mut required = 0;
+
+        for entry in &info.entries {
+            let path = entry.path();
+
+            match file_size(source, &path)? {
+                Some(size) if size == entry.size => required += size,
+                Some(size) => {
+                    return Err(Error::InvalidSize {
+                        path: path.into_owned(),
+                        actual: size,
+                        expected: entry.size,
+                    });
+                }
+                None => return Err(Error::MissingFile(path.into_owned())),
+            }
+        }
+
+        // The head unit only looks for one .ver file at the root.
+        for entry in target.entries().map_err(Error::ListDir)? {
+            let entry = entry.map_err(Error::ListDir)?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+
+            if name.ends_with(".ver") && name != ver_name {
+                return Err(Error::OtherVehicle(name));
+            }
+        }
+
+        let fs_type = filesystem::filesystem_type(target).map_err(Error::FilesystemType)?;
+        debug!("Target filesystem type: {fs_type}");
+
+        match fs_type {
+            FilesystemType::Fat | FilesystemType::Exfat => {}
+            _ => warn!("Head units may not support the target filesystem: {fs_type}"),
+        }
+
+        check_file_sizes(&fs_type, &info.entries)?;
+
+        // Existing files are overwritten, so their space can be reused once
+        // they are replaced. The existing .ver file is deleted first and the
+        // new one is copied last.
+        let existing_ver = file_size(target, &ver_name)?.unwrap_or_default();
+        let mut sizes = vec![(0, existing_ver)];
+        for entry in &info.entries {
+            sizes.push((
+                entry.size,
+                file_size(target, &entry.path())?.unwrap_or_default(),
+            ));
+        }
+        sizes.push((ver_size, 0));
+
+        let existing = sizes.iter().map(|(_, e)| e).sum::<u64>();
+        let peak = peak_usage(sizes);
+
+        let space = filesystem::space(target).map_err(Error::Space)?;
+        debug!("Target space: {space:?}, existing: {existing}, required: {required}, peak: {peak}",);
+
+        if space.total < required {
+            return Err(Error::InsufficientCapacity {
+                required,
+                capacity: space.total,
+            });
+        } else if space.available < peak {
+            return Err(Error::InsufficientSpace {
+                needed: peak,
+                available: space.available,
+                required,
+                existing,
+            });
+        }
+
+        Ok(Prepared { ver_name, info })
+    }
+
+    /// Copy a file to a temporary path, sync it, and then rename it into
+    /// place.
+    fn copy_file(
+        source: &Dir,
+        target: &Dir,
+        path: &str,
+        mut progress: impl FnMut(u64) -> Result<()>,
+        cancel_signal: &AtomicBool,
+    ) -> Result<()> {
+        let path = Path::new(path);
+        let name = path
+            .file_name()
+            .ok_or_else(|| Error::InvalidPath(path.to_owned()))?;
+
+        let owned_directory;
+        let directory = match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            Some(parent) => {
+                target
+                    .create_dir_all(parent)
+                    .map_err(|e| Error::CreateDir(parent.to_owned(), e))?;
+                owned_directory = target
+                    .open_dir(parent)
+                    .map_err(|e| Error::OpenDir(parent.to_owned(), e))?;
+                &owned_directory
+            }
+            None => target,
+        };
+
+        let mut temp_name = name.to_owned();
+        temp_name.push(format!(".{INSTALL_EXT}"));
+
+        let mut input = source
+            .open(path)
+            .map_err(|e| Error::OpenFile(path.to_owned(), e))?;
+        let mut output = directory
+            .open_with(
+                &temp_name,
+                OpenOptions::new().write(true).create(true).truncate(true),
+            )
+            .map_err(|e| Error::CreateFile(temp_name.clone().into(), e))?;
+
+        let mut buf = vec![0u8; 1024 * 1024];
+
+        loop {
+            check_cancel(cancel_signal).map_err(Error::Cancelled)?;
+
+            let n = input
+                .read(&mut buf)
+                .map_err(|e| Error::ReadFile(path.to_owned(), e))?;
+            if n == 0 {
+                break;
+            }
+
+            output
+                .write_all(&buf[..n])
+                .map_err(|e| Error::WriteFile(temp_name.clone().into(), e))?;
+
+            progress(n as u64)?;
+        }
+
+        output
+            .sync_all()
+            .map_err(|e| Error::SyncFile(temp_name.clone().into(), e))?;
+
+        // Make sure the verification reads the data back from the drive.
+        if let Err(e) = filesystem::drop_cache(&output) {
+            debug!("Failed to drop cache: {temp_name:?}: {e}");
+        }
+
+        drop(output);
+
+        directory
+            .rename(&temp_name, directory, name)
+            .map_err(|e| Error::MoveFile(temp_name.into(), name.into(), e))?;
+        filesystem::sync_dir(directory).map_err(|e| Error::SyncDir(path.to_owned(), e))?;
+
+        Ok(())
+    }
+
+    /// Copy all firmware files and then the `.ver` file. Returns the number
+    /// of files copied, excluding the `.ver` file.
+    ///
+    /// The copied data is not checked here. Callers must verify the target
+    /// afterwards with [`Verifier`] to detect data that was corrupted while
+    /// being written to the drive.
+    pub async fn install(&self) -> Result<usize> {
+        // Checking only requires reading metadata, so this is not
+        // cancellable.
+        let prepared = task::spawn_blocking({
+            let source = self.source.clone();
+            let target = self.target.clone();
+            move || Self::prepare(&source, &target)
+        })
+        .await
+        .map_err(Error::Panic)??;
+
+        // Otherwise, if this is interrupted, an existing .ver file from an
+        // earlier installation would make the mix of old and new files look
+        // complete.
+        task::spawn_blocking({
+            let target = self.target.clone();
+            let ver_name = prepared.ver_name.clone();
+
+            move || {
+                match target.remove_file(&ver_name) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+                    Err(e) => return Err(Error::DeleteFile(ver_name.into(), e)),
+                }
+
+                filesystem::sync_dir(&target).map_err(|e| Error::SyncDir(ver_name.into(), e))
+            }
+        })
+        .await
+        .map_err(Error::Panic)??;
+
+        let total_size = prepared.info.entries.iter().map(|e| e.size).sum();
+
+        self.progress_tx
+            .send(ProgressMessage::Total { bytes: total_size })
+            .await
+            .map_err(Error::Progress)?;
+
+        // Removable media is usually slow with concurrent writes, so the files
+        // are copied one at a time.
+        for (file_index, entry) in prepared.info.entries.iter().enumerate() {
+            let path = entry.path().into_owned();
+
+            self.progress_tx
+                .send(ProgressMessage::FileStarted {
+                    file_index,
+                    path: path.clone(),
+                })
+                .await
+                .map_err(Error::Progress)?;
+
+            let cancel_on_drop = CancelOnDrop::new();
+            let cancel_signal = cancel_on_drop.handle();
+            let source = self.source.clone();
+            let target = self.target.clone();
+            let progress_tx = self.progress_tx.clone();
+
+            task::spawn_blocking(move || {
+                let mut progress = ThrottledProgress::new(
+                    progress_tx,
+                    |bytes| ProgressMessage::Progress { file_index, bytes },
+                    THROTTLE_DELAY,
+                );
+
+                Self::copy_file(
+                    &source,
+                    &target,
+                    &path,
+                    |n| progress.update_blocking(n).map_err(Error::Progress),
+                    &cancel_signal,
+                )?;
+
+                progress.flush_blocking().map_err(Error::Progress)
+            })
+            .await
+            .map_err(Error::Panic)??;
+
+            self.progress_tx
+                .send(ProgressMessage::FileCompleted { file_index })
+                .await
+                .map_err(Error::Progress)?;
+        }
+
+        let cancel_on_drop = CancelOnDrop::new();
+
+        task::spawn_blocking({
+            let cancel_signal = cancel_on_drop.handle();
+            let source = self.source.clone();
+            let target = self.target.clone();
+            let ver_name = prepared.ver_name.clone();
+
+            move || Self::copy_file(&source, &target, &ver_name, |_| Ok(()), &cancel_signal)
+        })
+        .await
+        .map_err(Error::Panic)??;
+
+        Ok(prepared.info.entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        download::DownloadOptions,
+        mock::{
+            MockCar, MockFile, MockServer, TempDir, download_options, drain_progress, mock_car,
+            test_data,
+        },
+    };
+
+    async fn install(source: &TempDir, target: &TempDir) -> Result<usize> {
+        let (installer, progress_rx) = Installer::new(source.open(), target.open());
+        let progress = drain_progress(progress_rx);
+
+        let result = installer.install().await;
+        drop(installer);
+        progress.await.unwrap();
+
+        result
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_install() {
+        let car = MockCar {
+            files: vec![
+                MockFile::new("split.tar", test_data(8192, 1), 3),
+                MockFile::new("unsplit.bin", test_data(1024, 2), 0).with_directory("sub"),
+            ],
+            ..mock_car()
+        };
+        let server = MockServer::start(vec![car.clone()]).await;
+        let source = TempDir::new();
+        let target = TempDir::new();
+
+        let options = DownloadOptions {
+            keep_raw: true,
+            ..download_options()
+        };
+
+        server.download(&source, options).await.unwrap();
+
+        // Overwriting an existing installation is allowed.
+        for _ in 0..2 {
+            assert_eq!(install(&source, &target).await.unwrap(), 2);
+        }
+
+        for (file, path) in car.files.iter().zip(["split.tar", "sub/unsplit.bin"]) {
+            let data = std::fs::read(target.path().join(path)).unwrap();
+            assert_eq!(data, file.data);
+        }
+        assert_eq!(
+            std::fs::read(target.path().join("CAR1.ver")).unwrap(),
+            std::fs::read(source.path().join("CAR1.ver")).unwrap(),
+        );
+        // Raw splits are not copied.
+        assert!(!target.path().join("split.zip").exists());
+
+        // An interrupted reinstallation must not leave the old .ver file
+        // behind.
+        let (installer, progress_rx) = Installer::new(source.open(), target.open());
+        drop(progress_rx);
+        installer.install().await.unwrap_err();
+        assert!(!target.path().join("CAR1.ver").exists());
+
+        std::fs::write(target.path().join("CAR2.ver"), "").unwrap();
+        let err = install(&source, &target).await.unwrap_err();
+        assert!(matches!(err, Error::OtherVehicle(n) if n == "CAR2.ver"));
+    }
+
+    #[test]
+    fn test_peak_usage() {
+        assert_eq!(peak_usage([(10, 0), (20, 0)]), 30);
+        // The temp file and the file it replaces exist at the same time.
+        assert_eq!(peak_usage([(10, 10), (20, 20)]), 20);
+        assert_eq!(peak_usage([(0, 5), (10, 10), (1, 0)]), 5);
+    }
+
+    #[test]
+    fn test_check_file_sizes() {
+        let entries = [VersionEntry {
+            id: "CAR1".to_owned(),
+            directory: None,
+            filename: "large.bin".to_owned(),
+            version: "1".to_owned(),
+            crc32: 0,
+            size: 4 * 1024 * 1024 * 1024,
+        }];
+
+        assert!(check_file_sizes(&FilesystemType::Exfat, &entries).is_ok());
+
+        let err = check_file_sizes(&FilesystemType::Fat, &entries).unwrap_err();
+        assert!(
+            err.to_string().contains("large.bin (4294967296 bytes)"),
+            "{err}"
+        );
+    }
+}
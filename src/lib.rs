@@ -0,0 +1,46 @@
+// SPDX-FileCopyrightText: 2024-2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Library for querying and downloading HMG infotainment firmware. This is
+//! the same functionality that the `nudl` command line tool is built on.
+//!
+//! A typical flow is to build a [`NuClient`], look up the cars for a brand and
+//! region, pick one with [`select_car`], fetch its [`FirmwareInfo`], and then
+//! hand everything to a [`Downloader`]. Existing downloads can be checked with
+//! a [`Verifier`].
+//!
+//! Every module reports failures with its own error enum. The enums are
+//! `#[non_exhaustive]` so that new failure cases can be added without breaking
+//! callers.
+
+pub mod batch;
+mod cancel;
+pub mod client;
+pub mod compare;
+mod constants;
+mod crypto;
+pub mod download;
+pub mod extract;
+pub mod filesystem;
+pub mod install;
+#[cfg(test)]
+mod mock;
+pub mod model;
+mod progress;
+pub mod ratelimit;
+pub mod recording;
+pub mod retry;
+pub mod selector;
+pub mod snapshot;
+pub mod tls;
+pub mod verify;
+pub mod version;
+mod zipstream;
+
+pub use crate::{
+    client::{Brand, CarInfo, FileInfo, FirmwareInfo, NuClient, NuClientBuilder},
+    download::Downloader,
+    selector::{Selector, select_car},
+    verify::Verifier,
+    version::VersionInfo,
+};
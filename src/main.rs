@@ -1,20 +1,15 @@
 // SPDX-FileCopyrightText: 2024-2026 Andrew Gunnerson
 // SPDX-License-Identifier: GPL-3.0-only
 
-mod cancel;
 mod cli;
-mod client;
-mod constants;
-mod crypto;
-mod download;
-mod model;
-mod progress;
-mod verify;
-mod version;
+mod terminal;
 
 use std::{
-    fmt::{self, Display, Write as _},
+    collections::HashMap,
+    fmt::{self, Display},
     io::{self, IsTerminal, Write},
+    path::Path,
+    sync::Arc,
     time::Duration,
 };
 
@@ -22,17 +17,36 @@ use anyhow::{Context, Result, anyhow, bail};
 use cap_std::{ambient_authority, fs::Dir};
 use clap::Parser;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-
-use tokio::signal::ctrl_c;
-use tracing::debug;
+use serde::Serialize;
+use tokio::{signal::ctrl_c, task::JoinSet};
+use tracing::{debug, error, info, warn};
 use unicode_width::UnicodeWidthStr;
 
+use nudl::{
+    batch::{BatchReport, Manifest, ManifestEntry},
+    client::{
+        Brand, BrandInfo, CANDIDATE_REGIONS, CarInfo, CarList, FileInfo, FirmwareInfo, NuClient,
+        NuClientBuilder,
+    },
+    compare::{Comparison, RegionFirmware, RegionResult},
+    download::{self, DownloadOptions, DownloadPlan, Downloader, PlannedAction, Task},
+    extract::{self, Extractor},
+    install::{self, Installer},
+    selector::select_car,
+    snapshot::{self, Change, Snapshot, SnapshotStore},
+    verify::{self, Verifier},
+};
+
 use crate::{
-    cli::{Brand, Cli, Command, DownloadCli, ListCli, OutputFormat, VerifyCli},
-    client::{CarInfo, NuClient, NuClientBuilder},
-    download::Downloader,
-    progress::{Osc94, Osc94Printer, ProgressSuspendingStderr, SpeedTracker, progress_percentage},
-    verify::Verifier,
+    cli::{
+        BatchCli, ChangesCli, Cli, Command, CompareCli, DownloadCli, ExtractCli, InfoCli,
+        InstallMediaCli, ListCli, OutputFormat, ProgressFormat, ProgressGroup, RegionsCli,
+        ReportFormat, SnapshotCli, VerifyCli,
+    },
+    terminal::{
+        JsonlWriter, Osc94, Osc94Printer, ProgressSuspendingStderr, SpeedTracker,
+        progress_percentage,
+    },
 };
 
 const PROGRESS_SPEED_WINDOW: Duration = Duration::from_secs(1);
@@ -46,14 +60,184 @@ fn progress_style() -> ProgressStyle {
     .progress_chars("█▉▊▋▌▍▎▏  ")
 }
 
+/// Style for individual tasks. The size is not known ahead of time for split
+/// downloads, so there is no bar.
+fn task_progress_style(has_length: bool) -> ProgressStyle {
+    if has_length {
+        ProgressStyle::with_template(
+            "  {spinner:.green} {prefix:.dim}▕{wide_bar:.cyan/blue}▏{bytes}/{total_bytes} ({speed})",
+        )
+    } else {
+        ProgressStyle::with_template("  {spinner:.green} {prefix:.dim} {bytes} ({speed})")
+    }
+    .unwrap()
+    .with_key("speed", SpeedTracker::new(PROGRESS_SPEED_WINDOW))
+    .progress_chars("█▉▊▋▌▍▎▏  ")
+}
+
+/// Machine-readable events that are not directly derived from
+/// [`download::ProgressMessage`] or [`verify::ProgressMessage`].
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    /// Information about the firmware about to be downloaded.
+    Firmware {
+        region: &'a str,
+        car: &'a CarInfo,
+        size: u64,
+        files: Vec<FileEvent>,
+    },
+    /// Work that would be done by a download. Only emitted for dry runs.
+    Plan {
+        #[serde(flatten)]
+        plan: &'a DownloadPlan,
+    },
+    BatchEntryStarted {
+        index: usize,
+        total: usize,
+        entry: String,
+        output: &'a Path,
+    },
+    BatchEntryCompleted {
+        index: usize,
+        error: Option<String>,
+    },
+    /// Final status of the command.
+    Finished {
+        error: Option<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct FileEvent {
+    path: String,
+    crc32: u32,
+    size: u64,
+    download_count: u32,
+    download_size: u64,
+}
+
+impl From<&FileInfo> for FileEvent {
+    fn from(file: &FileInfo) -> Self {
+        Self {
+            path: file.path(),
+            crc32: file.crc32,
+            size: file.size,
+            download_count: file.download_count(),
+            download_size: file.download_size(),
+        }
+    }
+}
+
+/// Destination for progress and human-readable output.
+struct Reporter {
+    bars: MultiProgress,
+    /// Whether to show progress bars. They are replaced by the JSON lines
+    /// output if the progress format is `jsonl`.
+    show_bars: bool,
+    /// Whether to show progress bars for individual tasks.
+    detailed: bool,
+    jsonl: Option<JsonlWriter>,
+    /// Whether JSON lines are written to stdout. If so, human-readable output
+    /// is written to stderr instead so that stdout remains parseable.
+    jsonl_stdout: bool,
+}
+
+impl Reporter {
+    fn new(bars: MultiProgress, group: Option<&ProgressGroup>) -> Result<Self> {
+        // A progress file is written regardless of the format so that the
+        // events can be logged while showing progress bars.
+        let (jsonl, jsonl_stdout) = match group {
+            Some(g) if g.progress == ProgressFormat::Jsonl || g.progress_file.is_some() => {
+                let writer = JsonlWriter::open(g.progress_file.as_deref()).with_context(|| {
+                    format!("Failed to open progress output: {:?}", g.progress_file)
+                })?;
+
+                (Some(writer), g.progress_file.is_none())
+            }
+            _ => (None, false),
+        };
+
+        let show_bars = group.is_none_or(|g| g.progress != ProgressFormat::Jsonl);
+        let detailed = group.is_some_and(|g| g.progress == ProgressFormat::Detailed);
+
+        Ok(Self {
+            bars,
+            show_bars,
+            detailed,
+            jsonl,
+            jsonl_stdout,
+        })
+    }
+
+    /// Get a writer for human-readable output.
+    fn text(&self) -> Box<dyn Write> {
+        if self.jsonl_stdout {
+            Box::new(io::stderr().lock())
+        } else {
+            Box::new(io::stdout().lock())
+        }
+    }
+
+    /// Create a new progress bar. It is never shown if the progress format is
+    /// `jsonl`.
+    fn add_bar(&self, prefix: &'static str) -> ProgressBar {
+        let bar = if self.show_bars {
+            self.bars.add(ProgressBar::hidden())
+        } else {
+            ProgressBar::hidden()
+        };
+
+        bar.set_prefix(prefix);
+        bar.set_style(progress_style());
+
+        bar
+    }
+
+    /// Create a new progress bar for an individual task if detailed progress
+    /// is enabled.
+    fn add_task_bar(&self, prefix: String, length: Option<u64>) -> Option<ProgressBar> {
+        if !self.detailed {
+            return None;
+        }
+
+        let bar = self.bars.add(ProgressBar::no_length());
+        bar.set_style(task_progress_style(length.is_some()));
+        bar.set_prefix(prefix);
+        if let Some(l) = length {
+            bar.set_length(l);
+        }
+
+        Some(bar)
+    }
+
+    fn remove_task_bar(&self, bar: ProgressBar) {
+        bar.finish_and_clear();
+        self.bars.remove(&bar);
+    }
+
+    fn clear(&self) {
+        let _ = self.bars.clear();
+    }
+
+    /// Write an event if the JSON lines output is enabled.
+    fn event(&self, event: &impl Serialize) -> Result<()> {
+        if let Some(jsonl) = &self.jsonl {
+            jsonl
+                .write(event)
+                .context("Failed to write progress event")?;
+        }
+
+        Ok(())
+    }
+}
+
 async fn prepare_client(
     brand: Brand,
     region: Option<&str>,
-    ignore_tls_validation: bool,
+    builder: NuClientBuilder,
 ) -> Result<(NuClient, String, String)> {
-    let client = NuClientBuilder::new()
-        .ignore_tls_validation(ignore_tls_validation)
-        .build()?;
+    let client = builder.build()?;
 
     let (autodetected, region) = match region {
         Some(r) => (false, r.to_owned()),
@@ -89,48 +273,12 @@ fn join(into_iter: impl IntoIterator<Item = impl Display>, sep: &str) -> String
     result
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub enum Selector {
-    Model(String),
-    Name(String),
-    Version(String),
-}
-
-impl Selector {
-    fn all_for_car(car: &CarInfo) -> Vec<Self> {
-        let mut result = vec![Self::Model(car.id.clone()), Self::Name(car.name.clone())];
-
-        result.extend(car.versions.iter().cloned().map(Self::Version));
-
-        result
-    }
-
-    fn matches_car(&self, car: &CarInfo) -> bool {
-        match self {
-            Self::Model(m) => car.id == *m,
-            Self::Name(n) => car.name == *n,
-            Self::Version(v) => car.versions.contains(v),
-        }
-    }
-}
-
-impl fmt::Display for Selector {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Model(m) => write!(f, "-m {m}"),
-            Self::Name(n) => write!(f, "-n \"{n}\""),
-            Self::Version(v) => write!(f, "-v {v}"),
-        }
-    }
-}
-
 async fn list_subcommand(cli: &ListCli) -> Result<()> {
-    let (client, region, guid) = prepare_client(
-        cli.family.brand,
-        cli.family.region.as_deref(),
-        cli.network.ignore_tls_validation,
-    )
-    .await?;
+    let credentials = cli.auth.to_credentials()?;
+    let authenticated = credentials.is_some();
+    let builder = cli.network.to_client_builder().credentials(credentials);
+    let (client, region, guid) =
+        prepare_client(cli.family.brand, cli.family.region.as_deref(), builder).await?;
     let brand = cli.family.brand.as_code_str();
     let selectors = cli.selector.to_selectors();
 
@@ -140,7 +288,10 @@ async fn list_subcommand(cli: &ListCli) -> Result<()> {
             const HEADING_NAME: &str = "NAME";
             const HEADING_VERSION: &str = "VERSION";
 
-            let mut cars = client.get_cars(&region, &guid, brand).await?;
+            let CarList {
+                user_auth,
+                mut cars,
+            } = client.get_car_list(&region, &guid, brand).await?;
             if !selectors.is_empty() {
                 cars.retain(|c| selectors.iter().all(|s| s.matches_car(c)));
             }
@@ -160,6 +311,11 @@ async fn list_subcommand(cli: &ListCli) -> Result<()> {
 
             let mut stdout = io::stdout().lock();
 
+            if authenticated {
+                writeln!(stdout, "User authorization: {user_auth}")?;
+                writeln!(stdout)?;
+            }
+
             writeln!(
                 stdout,
                 "{HEADING_MODEL:model_width$} {HEADING_NAME:name_width$} {HEADING_VERSION}",
@@ -182,14 +338,15 @@ async fn list_subcommand(cli: &ListCli) -> Result<()> {
             }
         }
         OutputFormat::Json => {
-            let mut cars = client.get_cars(&region, &guid, brand).await?;
+            let mut list = client.get_car_list(&region, &guid, brand).await?;
             if !selectors.is_empty() {
-                cars.retain(|c| selectors.iter().all(|s| s.matches_car(c)));
+                list.cars
+                    .retain(|c| selectors.iter().all(|s| s.matches_car(c)));
             }
 
             let mut stdout = io::stdout().lock();
 
-            serde_json::to_writer_pretty(&mut stdout, &cars)?;
+            serde_json::to_writer_pretty(&mut stdout, &list)?;
             writeln!(stdout)?;
         }
         OutputFormat::JsonRaw => {
@@ -211,85 +368,562 @@ async fn list_subcommand(cli: &ListCli) -> Result<()> {
     Ok(())
 }
 
-async fn download_subcommand(cli: &DownloadCli, bars: MultiProgress) -> Result<()> {
-    let (client, region, guid) = prepare_client(
-        cli.family.brand,
-        cli.family.region.as_deref(),
-        cli.network.ignore_tls_validation,
-    )
-    .await?;
+/// A single download of a firmware file.
+#[derive(Serialize)]
+struct InfoDownload {
+    name: String,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct InfoFile {
+    path: String,
+    crc32: u32,
+    size: u64,
+    server_path: String,
+    version: String,
+    zip_naming: &'static str,
+    download_count: u32,
+    download_size: u64,
+    downloads: Vec<InfoDownload>,
+}
+
+impl InfoFile {
+    fn new(firmware: &FirmwareInfo, file: &FileInfo) -> Self {
+        Self {
+            path: file.path(),
+            crc32: file.crc32,
+            size: file.size,
+            server_path: file.server_path.clone(),
+            version: file.version.clone(),
+            zip_naming: file.zip_naming().kind(),
+            download_count: file.download_count(),
+            download_size: file.download_size(),
+            downloads: (0..file.download_count())
+                .map(|i| InfoDownload {
+                    name: file.download_name(i),
+                    url: firmware.download_url(file, i),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Firmware manifest for the `info` subcommand.
+#[derive(Serialize)]
+struct InfoReport<'a> {
+    region: &'a str,
+    car: &'a CarInfo,
+    update_version: &'a str,
+    size: u64,
+    base_url: &'a str,
+    files: Vec<InfoFile>,
+}
+
+impl fmt::Display for InfoReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "ID: {}", self.car.id)?;
+        writeln!(f, "Region: {}", self.region)?;
+        writeln!(f, "Brand: {}", self.car.brand.as_code_str())?;
+        writeln!(f, "Model: {}", self.car.name)?;
+        writeln!(f, "Version: {}", join(&self.car.versions, ", "))?;
+        writeln!(f, "Media: {}", self.car.media_type)?;
+        writeln!(f, "Update version: {}", self.update_version)?;
+        writeln!(f, "Size: {} bytes", self.size)?;
+        writeln!(f, "Base URL: {}", self.base_url)?;
+        write!(f, "Files:")?;
+
+        for file in &self.files {
+            writeln!(f)?;
+            writeln!(f, "  {}", file.path)?;
+            writeln!(f, "    CRC32: {:08X}", file.crc32)?;
+            writeln!(f, "    Size: {} bytes", file.size)?;
+            writeln!(f, "    Version: {}", file.version)?;
+            writeln!(f, "    Server path: {}", file.server_path)?;
+            writeln!(f, "    Naming scheme: {}", file.zip_naming)?;
+            writeln!(
+                f,
+                "    Downloads: {} ({} bytes)",
+                file.download_count, file.download_size,
+            )?;
+
+            for (i, download) in file.downloads.iter().enumerate() {
+                if i > 0 {
+                    writeln!(f)?;
+                }
+                write!(f, "      {}: {}", download.name, download.url)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn info_subcommand(cli: &InfoCli) -> Result<()> {
+    let builder = cli
+        .network
+        .to_client_builder()
+        .credentials(cli.auth.to_credentials()?);
+    let (client, region, guid) =
+        prepare_client(cli.family.brand, cli.family.region.as_deref(), builder).await?;
 
     let cars = client
         .get_cars(&region, &guid, cli.family.brand.as_code_str())
         .await?;
     let selectors = cli.selector.to_selectors();
-    let candidates: Vec<_> = cars
+    let car = select_car(&cars, &selectors)?;
+
+    let mut stdout = io::stdout().lock();
+
+    match cli.output {
+        OutputFormat::Text | OutputFormat::Json => {
+            let firmware = client.get_firmware_info(&region, car).await?;
+            let report = InfoReport {
+                region: &region,
+                car,
+                update_version: &firmware.update_version,
+                size: firmware.size,
+                base_url: &firmware.base_url,
+                files: firmware
+                    .files
+                    .iter()
+                    .map(|f| InfoFile::new(&firmware, f))
+                    .collect(),
+            };
+
+            if let OutputFormat::Text = cli.output {
+                writeln!(stdout, "{report}")?;
+            } else {
+                serde_json::to_writer_pretty(&mut stdout, &report)?;
+                writeln!(stdout)?;
+            }
+        }
+        OutputFormat::JsonRaw => {
+            let raw_data = client.get_firmware_info_raw(&region, &car.code).await?;
+
+            serde_json::to_writer_pretty(&mut stdout, &raw_data)?;
+            writeln!(stdout)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Result of probing a single region.
+#[derive(Serialize)]
+struct RegionProbe {
+    region: String,
+    api_url: String,
+    /// Whether the region has data for the brand.
+    available: bool,
+    /// Number of cars listed in the region.
+    cars: Option<usize>,
+    error: Option<String>,
+}
+
+async fn regions_subcommand(cli: &RegionsCli) -> Result<()> {
+    let client = Arc::new(cli.network.to_client_builder().build()?);
+    let brand = cli.brand.as_code_str();
+
+    let mut regions = CANDIDATE_REGIONS
         .iter()
-        .filter(|c| selectors.iter().all(|s| s.matches_car(c)))
-        .collect();
-    let car = if candidates.is_empty() {
-        let mut msg = format!(
-            "No firmware versions found matching selector: {}",
-            join(selectors.iter(), " "),
-        );
+        .map(|r| (*r).to_owned())
+        .chain(cli.regions.iter().map(|r| r.to_ascii_uppercase()))
+        .collect::<Vec<_>>();
+    regions.sort();
+    regions.dedup();
+
+    let mut pending = regions.into_iter();
+    let mut tasks = JoinSet::new();
+    let mut results = vec![];
+
+    loop {
+        while tasks.len() < usize::from(cli.concurrency.0) {
+            let Some(region) = pending.next() else {
+                break;
+            };
+
+            let client = client.clone();
+            tasks.spawn(async move {
+                let result = client.probe_region(brand, &region).await;
+                (region, result)
+            });
+        }
+
+        let Some(task) = tasks.join_next().await else {
+            break;
+        };
+        let (region, result) = task?;
 
-        for selector in &selectors {
-            let available: Vec<_> = cars.iter().filter(|c| selector.matches_car(c)).collect();
-            if !available.is_empty() {
-                writeln!(&mut msg, "\n\nAvailable options for just: {selector}")?;
+        if let Err(e) = &result {
+            warn!("Failed to probe region {region}: {e}");
+        }
+
+        results.push(RegionProbe {
+            api_url: client.base_url(&region).to_owned(),
+            available: result.as_ref().is_ok_and(|c| c.is_some()),
+            cars: result.as_ref().ok().copied().flatten(),
+            error: result.err().map(|e| e.to_string()),
+            region,
+        });
+    }
+
+    results.sort_by(|a, b| a.region.cmp(&b.region));
+
+    let mut stdout = io::stdout().lock();
+
+    match cli.output {
+        ReportFormat::Text => {
+            const HEADING_REGION: &str = "REGION";
+            const HEADING_API_URL: &str = "API URL";
+            const HEADING_CARS: &str = "CARS";
+
+            let available = results.iter().filter(|r| r.available).collect::<Vec<_>>();
+
+            let api_url_max_width = available
+                .iter()
+                .map(|r| r.api_url.width())
+                .max()
+                .unwrap_or_default()
+                .max(HEADING_API_URL.width());
+
+            writeln!(
+                stdout,
+                "{HEADING_REGION:6} {HEADING_API_URL:api_url_width$} {HEADING_CARS}",
+                api_url_width = api_url_max_width,
+            )?;
+
+            for result in available {
+                writeln!(
+                    stdout,
+                    "{:6} {:api_url_width$} {}",
+                    result.region,
+                    result.api_url,
+                    result.cars.unwrap_or_default(),
+                    api_url_width = api_url_max_width,
+                )?;
             }
+        }
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut stdout, &results)?;
+            writeln!(stdout)?;
+        }
+    }
 
-            for car in available {
-                let disambiguation = Selector::all_for_car(car)
-                    .into_iter()
-                    .filter(|s| s != selector);
+    if results.iter().all(|r| r.error.is_some()) {
+        bail!("Failed to probe all regions");
+    }
+
+    Ok(())
+}
 
-                write!(&mut msg, "\n  {}", join(disambiguation, " "))?;
+/// Get the firmware for a model in a region.
+async fn region_firmware(
+    client: &NuClient,
+    brand: Brand,
+    region: &str,
+    model: &str,
+) -> Result<RegionFirmware> {
+    client.validate_region(brand.as_code_str(), region).await?;
+
+    let guid = client.get_guid(region).await?;
+    let cars = client.get_cars(region, &guid, brand.as_code_str()).await?;
+    let Some(car) = cars.iter().find(|c| c.id == model) else {
+        bail!("Model not found: {model}");
+    };
+
+    let firmware = client.get_firmware_info(region, car).await?;
+
+    Ok(RegionFirmware::new(car, &firmware))
+}
+
+async fn compare_subcommand(cli: &CompareCli) -> Result<()> {
+    if cli.regions.len() < 2 {
+        bail!("At least two regions are required");
+    }
+
+    let client = cli
+        .network
+        .to_client_builder()
+        .credentials(cli.auth.to_credentials()?)
+        .build()?;
+    let mut regions = vec![];
+
+    for region in &cli.regions {
+        let result = region_firmware(&client, cli.brand, region, &cli.model).await;
+
+        if let Err(e) = &result {
+            warn!("Region {region}: {e:#}");
+        }
+
+        regions.push(RegionResult {
+            region: region.clone(),
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+            firmware: result.ok(),
+        });
+    }
+
+    let comparison = Comparison::new(cli.model.clone(), regions);
+    let mut stdout = io::stdout().lock();
+
+    match cli.output {
+        ReportFormat::Text => writeln!(stdout, "{comparison}")?,
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut stdout, &comparison)?;
+            writeln!(stdout)?;
+        }
+    }
+
+    if comparison.regions.iter().all(|r| r.firmware.is_none()) {
+        bail!("No region has firmware for {}", cli.model);
+    } else if cli.fail_on_diff && !comparison.identical {
+        let missing = comparison.missing_regions().collect::<Vec<_>>();
+
+        if missing.is_empty() {
+            bail!("Firmware differs between regions");
+        } else {
+            bail!("Regions have no firmware: {}", missing.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+async fn snapshot_subcommand(cli: &SnapshotCli) -> Result<()> {
+    let builder = cli
+        .network
+        .to_client_builder()
+        .credentials(cli.auth.to_credentials()?);
+    let (client, region, _) =
+        prepare_client(cli.family.brand, cli.family.region.as_deref(), builder).await?;
+    let store = SnapshotStore::new(cli.store.clone());
+
+    let snapshot = Snapshot::capture(&client, cli.family.brand, &region).await?;
+    let path = store.save(&snapshot)?;
+
+    writeln!(io::stdout().lock(), "Saved snapshot: {}", path.display())?;
+
+    Ok(())
+}
+
+fn print_changes(changes: &[Change], format: ReportFormat) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+
+    for change in changes {
+        match format {
+            ReportFormat::Text => writeln!(stdout, "{change}")?,
+            ReportFormat::Json => {
+                serde_json::to_writer(&mut stdout, change)?;
+                writeln!(stdout)?;
             }
         }
+    }
 
-        bail!(msg);
-    } else if candidates.len() > 1 {
-        let mut msg = format!(
-            "Multiple firmware versions found matching selector: {}\n",
-            join(selectors.iter(), " "),
-        );
-        msg.push_str("To disambiguate, rerun with one of the following:\n");
+    stdout.flush()?;
 
-        for car in candidates {
-            let disambiguation = Selector::all_for_car(car)
-                .into_iter()
-                .filter(|s| !selectors.contains(s));
+    Ok(())
+}
+
+/// Compare the two most recent snapshots in the store.
+fn changes_offline(cli: &ChangesCli, store: &SnapshotStore) -> Result<()> {
+    let brand = cli.family.brand.as_code_str();
 
-            write!(&mut msg, "\n  {}", join(disambiguation, " "))?;
+    let region = match &cli.family.region {
+        Some(r) => r.clone(),
+        None => {
+            let mut regions = store.regions(brand)?;
+
+            match regions.len() {
+                0 => bail!("No snapshots found for brand: {brand}"),
+                1 => regions.remove(0),
+                _ => bail!(
+                    "Snapshots exist for multiple regions. Please specify a region: {}",
+                    regions.join(", "),
+                ),
+            }
         }
+    };
 
-        bail!(msg);
-    } else {
-        candidates[0]
+    let paths = store.list(brand, &region)?;
+    let [.., old, new] = paths.as_slice() else {
+        bail!("At least two snapshots are required for {brand} in {region}");
     };
 
-    let firmware = client.get_firmware_info(&region, car).await?;
+    debug!("Comparing snapshots: {old:?} -> {new:?}");
+
+    let changes = snapshot::diff(SnapshotStore::load(old)?, SnapshotStore::load(new)?)?;
 
-    println!("ID: {}", car.id);
-    println!("Region: {region}");
-    println!("Brand: {}", car.brand.as_code_str());
-    println!("Model: {}", car.name);
-    println!("Version: {}", join(&car.versions, ", "));
-    println!("Size: {} bytes", firmware.size);
-    println!("Files:");
+    print_changes(&changes, cli.output)
+}
 
-    for file in &firmware.files {
-        println!("  {}", file.path());
-        println!("    CRC32: {:08X}", file.crc32);
-        println!("    Size: {} bytes", file.size);
+/// Take a new snapshot and compare it against the most recent one. The new
+/// snapshot is only saved if there are changes.
+async fn changes_poll(
+    client: &NuClient,
+    brand: Brand,
+    region: &str,
+    store: &SnapshotStore,
+    format: ReportFormat,
+) -> Result<()> {
+    let snapshot = Snapshot::capture(client, brand, region).await?;
+
+    let Some(previous) = store.list(brand.as_code_str(), region)?.pop() else {
+        let path = store.save(&snapshot)?;
+        info!("Saved initial snapshot: {path:?}");
+        return Ok(());
+    };
+
+    let changes = snapshot::diff(SnapshotStore::load(&previous)?, snapshot.clone())?;
+    if changes.is_empty() {
+        debug!("No changes since: {previous:?}");
+        return Ok(());
     }
 
+    let path = store.save(&snapshot)?;
+    debug!("Saved snapshot: {path:?}");
+
+    print_changes(&changes, format)
+}
+
+async fn changes_subcommand(cli: &ChangesCli) -> Result<()> {
+    let store = SnapshotStore::new(cli.store.clone());
+
+    let Some(interval) = cli.watch else {
+        return changes_offline(cli, &store);
+    };
+
+    let builder = cli
+        .network
+        .to_client_builder()
+        .credentials(cli.auth.to_credentials()?);
+    let (client, region, _) =
+        prepare_client(cli.family.brand, cli.family.region.as_deref(), builder).await?;
+
+    loop {
+        // Transient errors should not stop the watch.
+        if let Err(e) = changes_poll(&client, cli.family.brand, &region, &store, cli.output).await {
+            warn!("Failed to check for changes: {e:#}");
+        }
+
+        tokio::select! {
+            c = ctrl_c() => {
+                c?;
+                return Ok(());
+            }
+            _ = tokio::time::sleep(interval.0) => {}
+        }
+    }
+}
+
+fn print_firmware(
+    region: &str,
+    car: &CarInfo,
+    firmware: &FirmwareInfo,
+    reporter: &Reporter,
+) -> Result<()> {
+    {
+        let mut out = reporter.text();
+
+        writeln!(out, "ID: {}", car.id)?;
+        writeln!(out, "Region: {region}")?;
+        writeln!(out, "Brand: {}", car.brand.as_code_str())?;
+        writeln!(out, "Model: {}", car.name)?;
+        writeln!(out, "Version: {}", join(&car.versions, ", "))?;
+        writeln!(out, "Media: {}", car.media_type)?;
+        writeln!(out, "Size: {} bytes", firmware.size)?;
+        writeln!(out, "Files:")?;
+
+        for file in &firmware.files {
+            writeln!(out, "  {}", file.path())?;
+            writeln!(out, "    CRC32: {:08X}", file.crc32)?;
+            writeln!(out, "    Size: {} bytes", file.size)?;
+        }
+    }
+
+    reporter.event(&Event::Firmware {
+        region,
+        car,
+        size: firmware.size,
+        files: firmware.files.iter().map(FileEvent::from).collect(),
+    })
+}
+
+/// Show what [`download_firmware`] would do without writing anything.
+fn plan_firmware(
+    region: &str,
+    car: &CarInfo,
+    firmware: &FirmwareInfo,
+    output: &Path,
+    options: &DownloadOptions,
+    reporter: &Reporter,
+) -> Result<()> {
+    print_firmware(region, car, firmware, reporter)?;
+
+    let directory = match Dir::open_ambient_dir(output, ambient_authority()) {
+        Ok(d) => Some(d),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("Failed to open directory: {output:?}")),
+    };
+
+    let plan = Downloader::plan(directory.as_ref(), firmware, options)?;
+
+    {
+        let mut out = reporter.text();
+
+        writeln!(out, "Plan:")?;
+
+        for file in &plan.files {
+            writeln!(out, "  {}", file.path)?;
+
+            for download in &file.downloads {
+                if download.start_offset == 0 {
+                    writeln!(out, "    Download: {}", download.path)?;
+                } else {
+                    writeln!(
+                        out,
+                        "    Resume: {} (from byte {})",
+                        download.path, download.start_offset,
+                    )?;
+                }
+            }
+
+            match file.action {
+                PlannedAction::None => writeln!(out, "    Already complete")?,
+                PlannedAction::Clean => writeln!(out, "    Already extracted")?,
+                PlannedAction::Verify => writeln!(out, "    Verify")?,
+                PlannedAction::Extract => writeln!(out, "    Extract")?,
+            }
+
+            for path in &file.delete {
+                writeln!(out, "    Delete: {path}")?;
+            }
+
+            writeln!(out, "    Remaining: {} bytes", file.remaining_bytes)?;
+        }
+
+        writeln!(out, "Total remaining: {} bytes", plan.remaining_bytes)?;
+        writeln!(out, "Peak disk usage: {} bytes", plan.peak_space_bytes)?;
+    }
+
+    reporter.event(&Event::Plan { plan: &plan })
+}
+
+async fn download_firmware(
+    client: NuClient,
+    region: &str,
+    car: &CarInfo,
+    firmware: FirmwareInfo,
+    output: &Path,
+    options: DownloadOptions,
+    reporter: &Reporter,
+) -> Result<()> {
+    print_firmware(region, car, &firmware, reporter)?;
+
     let authority = ambient_authority();
-    Dir::create_ambient_dir_all(&cli.output, authority)
-        .with_context(|| format!("Failed to create directory: {:?}", cli.output))?;
-    let directory = Dir::open_ambient_dir(&cli.output, authority)
-        .with_context(|| format!("Failed to open directory: {:?}", cli.output))?;
+    Dir::create_ambient_dir_all(output, authority)
+        .with_context(|| format!("Failed to create directory: {output:?}"))?;
+    let directory = Dir::open_ambient_dir(output, authority)
+        .with_context(|| format!("Failed to open directory: {output:?}"))?;
 
     // The progress will be misreported if files are modified by external
     // processes. Solving this requires sending HEAD requests for each split and
@@ -300,23 +934,13 @@ async fn download_subcommand(cli: &DownloadCli, bars: MultiProgress) -> Result<(
     let mut osc94 = Osc94Printer::new();
     osc94.update(Osc94::Indeterminate);
 
-    let p_dl = bars.add(ProgressBar::hidden());
-    p_dl.set_prefix("Download");
-    p_dl.set_style(progress_style());
+    let p_dl = reporter.add_bar("Download");
+    let p_pp = reporter.add_bar("Post-process");
 
-    let p_pp = bars.add(ProgressBar::hidden());
-    p_pp.set_prefix("Post-process");
-    p_pp.set_style(progress_style());
+    let files = firmware.files.clone();
+    let mut task_bars = HashMap::<Task, (String, ProgressBar)>::new();
 
-    let (downloader, mut p_rx) = Downloader::new(
-        directory,
-        client,
-        car.clone(),
-        firmware,
-        cli.concurrency.0.into(),
-        cli.retries,
-        cli.keep_raw,
-    );
+    let (downloader, mut p_rx) = Downloader::new(directory, client, car.clone(), firmware, options);
     let handle = downloader.download();
     tokio::pin!(handle);
 
@@ -325,33 +949,94 @@ async fn download_subcommand(cli: &DownloadCli, bars: MultiProgress) -> Result<(
             biased;
 
             c = ctrl_c() => {
-                let _ = bars.clear();
+                reporter.clear();
                 c?;
 
                 bail!("Download was interrupted. To resume, rerun the current command.");
             }
             r = &mut handle => {
-                let _ = bars.clear();
+                reporter.clear();
                 r?;
                 break;
             }
             p = p_rx.recv() => {
                 if let Some(msg) = p {
-                    match msg {
-                        download::ProgressMessage::TotalDownload(bytes) => {
-                            p_dl.set_length(bytes);
+                    match &msg {
+                        download::ProgressMessage::TotalDownload { bytes } => {
+                            p_dl.set_length(*bytes);
+                        }
+                        download::ProgressMessage::TotalPostProcess { bytes } => {
+                            p_pp.set_length(*bytes);
                         }
-                        download::ProgressMessage::TotalPostProcess(bytes) => {
-                            p_pp.set_length(bytes);
+                        download::ProgressMessage::Resumed {
+                            download_bytes,
+                            post_process_bytes,
+                            ..
+                        } => {
+                            p_dl.inc(*download_bytes);
+                            p_pp.inc(*post_process_bytes);
                         }
-                        download::ProgressMessage::Download(bytes) => {
-                            p_dl.inc(bytes);
+                        download::ProgressMessage::Download {
+                            file_index,
+                            download_index,
+                            bytes,
+                        } => {
+                            p_dl.inc(*bytes);
+
+                            let task = Task::Download {
+                                file_index: *file_index,
+                                download_index: *download_index,
+                            };
+                            if let Some((_, bar)) = task_bars.get(&task) {
+                                bar.inc(*bytes);
+                            }
                         }
-                        download::ProgressMessage::PostProcess(bytes) => {
-                            p_pp.inc(bytes);
+                        download::ProgressMessage::PostProcess { file_index, bytes } => {
+                            p_pp.inc(*bytes);
+
+                            let task = Task::PostProcess {
+                                file_index: *file_index,
+                            };
+                            if let Some((_, bar)) = task_bars.get(&task) {
+                                bar.inc(*bytes);
+                            }
+                        }
+                        download::ProgressMessage::TaskStarted { task, start_offset } => {
+                            let (prefix, length) = match *task {
+                                Task::Download { file_index, download_index } => {
+                                    let file = &files[file_index];
+                                    let length = (!file.is_split()).then_some(file.size);
+
+                                    (file.download_path(download_index), length)
+                                }
+                                Task::PostProcess { file_index } => {
+                                    let file = &files[file_index];
+
+                                    (format!("{} (post-process)", file.path()), Some(file.size))
+                                }
+                            };
+
+                            if let Some(bar) = reporter.add_task_bar(prefix.clone(), length) {
+                                // Resumed downloads continue where the previous
+                                // run left off.
+                                bar.set_position(*start_offset);
+                                task_bars.insert(*task, (prefix, bar));
+                            }
+                        }
+                        download::ProgressMessage::TaskCompleted { task } => {
+                            if let Some((_, bar)) = task_bars.remove(task) {
+                                reporter.remove_task_bar(bar);
+                            }
+                        }
+                        download::ProgressMessage::TaskRetry { task, attempt, .. } => {
+                            if let Some((prefix, bar)) = task_bars.get(task) {
+                                bar.set_prefix(format!("{prefix} (retry #{attempt})"));
+                            }
                         }
                     }
 
+                    reporter.event(&msg)?;
+
                     let osc94_bars: &[&ProgressBar] = if p_pp.length() == Some(0) {
                         // If the server won't tell us the total extracted size,
                         // then don't include post-processing progress since
@@ -371,19 +1056,162 @@ async fn download_subcommand(cli: &DownloadCli, bars: MultiProgress) -> Result<(
     Ok(())
 }
 
-async fn verify_subcommand(cli: &VerifyCli, bars: MultiProgress) -> Result<()> {
-    let authority = ambient_authority();
-    let directory = Dir::open_ambient_dir(&cli.directory, authority)
-        .with_context(|| format!("Failed to open directory: {:?}", cli.directory))?;
+async fn download_subcommand(cli: &DownloadCli, reporter: &Reporter) -> Result<()> {
+    let builder = cli
+        .network
+        .to_client_builder()
+        .credentials(cli.auth.to_credentials()?);
+    let (client, region, guid) =
+        prepare_client(cli.family.brand, cli.family.region.as_deref(), builder).await?;
+
+    let cars = client
+        .get_cars(&region, &guid, cli.family.brand.as_code_str())
+        .await?;
+    let selectors = cli.selector.to_selectors();
+    let car = select_car(&cars, &selectors)?;
+
+    let options = DownloadOptions {
+        concurrency: cli.concurrency.0.into(),
+        retry_policy: cli.network.retry_policy(),
+        keep_raw: cli.keep_raw,
+        stream_extract: cli.stream_extract,
+        keep_splits_until_verified: cli.keep_splits_until_verified,
+        rate_limiter: cli.rate_limit.to_rate_limiter(),
+        timeouts: cli.network.stream_timeouts(),
+        segmentation: cli.segments.to_segmentation(),
+        skip_space_check: cli.skip_space_check,
+    };
+
+    let firmware = client.get_firmware_info(&region, car).await?;
+
+    if cli.dry_run {
+        return plan_firmware(&region, car, &firmware, &cli.output, &options, reporter);
+    }
+
+    download_firmware(
+        client,
+        &region,
+        car,
+        firmware,
+        &cli.output,
+        options,
+        reporter,
+    )
+    .await
+}
+
+async fn batch_entry(
+    entry: &ManifestEntry,
+    builder: NuClientBuilder,
+    options: DownloadOptions,
+    reporter: &Reporter,
+) -> Result<()> {
+    let (client, region, guid) =
+        prepare_client(entry.brand, entry.region.as_deref(), builder).await?;
+
+    let cars = client
+        .get_cars(&region, &guid, entry.brand.as_code_str())
+        .await?;
+    let car = select_car(&cars, &entry.to_selectors())?;
+
+    let firmware = client.get_firmware_info(&region, car).await?;
+
+    download_firmware(
+        client,
+        &region,
+        car,
+        firmware,
+        &entry.output,
+        options,
+        reporter,
+    )
+    .await
+}
+
+async fn batch_subcommand(cli: &BatchCli, reporter: &Reporter) -> Result<()> {
+    let manifest = Manifest::load(&cli.manifest)?;
+    let credentials = cli.auth.to_credentials()?;
+    let options = DownloadOptions {
+        concurrency: cli.concurrency.0.into(),
+        retry_policy: cli.network.retry_policy(),
+        keep_raw: cli.keep_raw,
+        stream_extract: cli.stream_extract,
+        keep_splits_until_verified: cli.keep_splits_until_verified,
+        rate_limiter: cli.rate_limit.to_rate_limiter(),
+        timeouts: cli.network.stream_timeouts(),
+        segmentation: cli.segments.to_segmentation(),
+        skip_space_check: cli.skip_space_check,
+    };
+
+    let total = manifest.entries.len();
+    let mut report = BatchReport::default();
+
+    for (i, entry) in manifest.entries.into_iter().enumerate() {
+        writeln!(reporter.text(), "[{}/{total}] {entry}", i + 1)?;
+        reporter.event(&Event::BatchEntryStarted {
+            index: i,
+            total,
+            entry: entry.to_string(),
+            output: &entry.output,
+        })?;
+
+        let builder = cli
+            .network
+            .to_client_builder()
+            .credentials(credentials.clone());
+
+        // The inner download also handles Ctrl-C, but that would only fail the
+        // current entry instead of the whole batch.
+        let result = tokio::select! {
+            biased;
+
+            c = ctrl_c() => {
+                reporter.clear();
+                c?;
+
+                bail!("Batch download was interrupted. To resume, rerun the current command.");
+            }
+            r = batch_entry(&entry, builder, options.clone(), reporter) => r,
+        };
+
+        if let Err(e) = &result {
+            error!("Failed to download {entry}: {e:#}");
+        }
+
+        reporter.event(&Event::BatchEntryCompleted {
+            index: i,
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+        })?;
+
+        let failed = result.is_err();
+        report.push(entry, result);
+
+        if failed && cli.fail_fast {
+            break;
+        }
+    }
 
+    write!(reporter.text(), "{report}")?;
+
+    if report.failed() > 0 {
+        bail!("{} of {total} batch entries failed", report.failed());
+    }
+
+    Ok(())
+}
+
+fn open_firmware_dir(path: &Path) -> Result<Dir> {
+    Dir::open_ambient_dir(path, ambient_authority())
+        .with_context(|| format!("Failed to open directory: {path:?}"))
+}
+
+async fn verify_firmware(directory: Dir, concurrency: usize, reporter: &Reporter) -> Result<()> {
     let mut osc94 = Osc94Printer::new();
     osc94.update(Osc94::Indeterminate);
 
-    let p_verify = bars.add(ProgressBar::hidden());
-    p_verify.set_prefix("Verify");
-    p_verify.set_style(progress_style());
+    let p_verify = reporter.add_bar("Verify");
 
-    let (verifier, mut p_rx) = Verifier::new(directory, cli.concurrency.0.into());
+    let (verifier, mut p_rx) = Verifier::new(directory, concurrency);
     let handle = verifier.verify();
     tokio::pin!(handle);
 
@@ -392,27 +1220,31 @@ async fn verify_subcommand(cli: &VerifyCli, bars: MultiProgress) -> Result<()> {
             biased;
 
             c = ctrl_c() => {
-                let _ = bars.clear();
+                reporter.clear();
                 c?;
 
                 bail!("Verification was interrupted");
             }
             r = &mut handle => {
-                let _ = bars.clear();
+                reporter.clear();
                 r?;
                 break;
             }
             p = p_rx.recv() => {
                 if let Some(msg) = p {
-                    match msg {
-                        verify::ProgressMessage::Total(bytes) => {
-                            p_verify.set_length(bytes);
+                    match &msg {
+                        verify::ProgressMessage::Total { bytes } => {
+                            p_verify.set_length(*bytes);
                         }
-                        verify::ProgressMessage::Progress(bytes) => {
-                            p_verify.inc(bytes);
+                        verify::ProgressMessage::Progress { bytes, .. } => {
+                            p_verify.inc(*bytes);
                         }
+                        verify::ProgressMessage::FileStarted { .. }
+                        | verify::ProgressMessage::FileCompleted { .. } => {}
                     }
 
+                    reporter.event(&msg)?;
+
                     osc94.update(Osc94::Determinate(progress_percentage(&[&p_verify])));
                 }
             }
@@ -422,6 +1254,203 @@ async fn verify_subcommand(cli: &VerifyCli, bars: MultiProgress) -> Result<()> {
     Ok(())
 }
 
+/// Re-download the specified files from a firmware directory. The car and
+/// firmware are found based on the information in the `.ver` file.
+async fn repair_firmware(cli: &VerifyCli, paths: &[String], reporter: &Reporter) -> Result<()> {
+    let directory = open_firmware_dir(&cli.directory)?;
+    let info = Verifier::read_version_file(&directory)?;
+
+    let BrandInfo::Known(brand) = info.header.brand else {
+        bail!(
+            "Cannot repair firmware for unknown brand: {}",
+            info.header.brand.as_code_str(),
+        );
+    };
+
+    let builder = cli
+        .network
+        .to_client_builder()
+        .credentials(cli.auth.to_credentials()?);
+    let (client, region, guid) = prepare_client(brand, cli.region.as_deref(), builder).await?;
+
+    let cars = client.get_cars(&region, &guid, brand.as_code_str()).await?;
+    let Some(car) = Verifier::find_car(&info, &cars) else {
+        bail!(
+            "Firmware {} for {} is no longer available in region {region}",
+            info.header.firmware_version,
+            info.header.id,
+        );
+    };
+
+    let firmware = client.get_firmware_info(&region, car).await?;
+
+    Verifier::prepare_repair(&directory, &info, car, &firmware, paths)?;
+
+    for path in paths {
+        writeln!(reporter.text(), "Repairing: {path}")?;
+    }
+
+    let options = DownloadOptions {
+        concurrency: cli.concurrency.0.into(),
+        retry_policy: cli.network.retry_policy(),
+        keep_raw: false,
+        stream_extract: false,
+        keep_splits_until_verified: false,
+        rate_limiter: cli.rate_limit.to_rate_limiter(),
+        timeouts: cli.network.stream_timeouts(),
+        segmentation: cli.segments.to_segmentation(),
+        skip_space_check: cli.skip_space_check,
+    };
+
+    // All files that were not deleted are skipped by the downloader and the
+    // newly downloaded files have their checksums validated.
+    download_firmware(
+        client,
+        &region,
+        car,
+        firmware,
+        &cli.directory,
+        options,
+        reporter,
+    )
+    .await
+}
+
+async fn verify_subcommand(cli: &VerifyCli, reporter: &Reporter) -> Result<()> {
+    let directory = open_firmware_dir(&cli.directory)?;
+    let result = verify_firmware(directory, cli.concurrency.0.into(), reporter).await;
+
+    if !cli.repair {
+        return result;
+    }
+
+    let paths = match &result {
+        Ok(()) => return Ok(()),
+        Err(e) => match e.downcast_ref::<verify::Error>() {
+            Some(verify::Error::Failed(paths)) => paths.clone(),
+            _ => return result,
+        },
+    };
+
+    repair_firmware(cli, &paths, reporter).await
+}
+
+async fn extract_subcommand(cli: &ExtractCli, reporter: &Reporter) -> Result<()> {
+    let directory = open_firmware_dir(&cli.directory)?;
+
+    let mut osc94 = Osc94Printer::new();
+    osc94.update(Osc94::Indeterminate);
+
+    let p_extract = reporter.add_bar("Extract");
+
+    let (extractor, mut p_rx) = Extractor::new(directory, cli.concurrency.0.into(), cli.keep_raw);
+    let handle = extractor.extract();
+    tokio::pin!(handle);
+
+    let count = loop {
+        tokio::select! {
+            biased;
+
+            c = ctrl_c() => {
+                reporter.clear();
+                c?;
+
+                bail!("Extraction was interrupted");
+            }
+            r = &mut handle => {
+                reporter.clear();
+                break r?;
+            }
+            p = p_rx.recv() => {
+                if let Some(msg) = p {
+                    match &msg {
+                        extract::ProgressMessage::Total { bytes } => {
+                            p_extract.set_length(*bytes);
+                        }
+                        extract::ProgressMessage::Progress { bytes, .. } => {
+                            p_extract.inc(*bytes);
+                        }
+                        extract::ProgressMessage::FileStarted { .. }
+                        | extract::ProgressMessage::FileCompleted { .. } => {}
+                    }
+
+                    reporter.event(&msg)?;
+
+                    osc94.update(Osc94::Determinate(progress_percentage(&[&p_extract])));
+                }
+            }
+        }
+    };
+
+    writeln!(reporter.text(), "Extracted {count} files")?;
+
+    Ok(())
+}
+
+async fn install_media_subcommand(cli: &InstallMediaCli, reporter: &Reporter) -> Result<()> {
+    let source = open_firmware_dir(&cli.directory)?;
+    let target = open_firmware_dir(&cli.mountpoint)?;
+
+    {
+        let mut osc94 = Osc94Printer::new();
+        osc94.update(Osc94::Indeterminate);
+
+        let p_install = reporter.add_bar("Install");
+
+        let (installer, mut p_rx) = Installer::new(source, target);
+        let handle = installer.install();
+        tokio::pin!(handle);
+
+        let count = loop {
+            tokio::select! {
+                biased;
+
+                c = ctrl_c() => {
+                    reporter.clear();
+                    c?;
+
+                    bail!("Installation was interrupted");
+                }
+                r = &mut handle => {
+                    reporter.clear();
+                    break r?;
+                }
+                p = p_rx.recv() => {
+                    if let Some(msg) = p {
+                        match &msg {
+                            install::ProgressMessage::Total { bytes } => {
+                                p_install.set_length(*bytes);
+                            }
+                            install::ProgressMessage::Progress { bytes, .. } => {
+                                p_install.inc(*bytes);
+                            }
+                            install::ProgressMessage::FileStarted { .. }
+                            | install::ProgressMessage::FileCompleted { .. } => {}
+                        }
+
+                        reporter.event(&msg)?;
+
+                        osc94.update(Osc94::Determinate(progress_percentage(&[&p_install])));
+                    }
+                }
+            }
+        };
+
+        writeln!(reporter.text(), "Copied {count} files")?;
+    }
+
+    // Reopen the drive since the installer took ownership.
+    let target = open_firmware_dir(&cli.mountpoint)?;
+    verify_firmware(target, cli.concurrency.0.into(), reporter).await?;
+
+    writeln!(
+        reporter.text(),
+        "Verified copy. Safely eject the drive before removing it.",
+    )?;
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -440,9 +1469,38 @@ async fn main() -> Result<()> {
         .install_default()
         .map_err(|_| anyhow!("Failed to set up ring as rustls crypto provider"))?;
 
-    match &cli.command {
+    let progress = match &cli.command {
+        Command::List(_)
+        | Command::Info(_)
+        | Command::Regions(_)
+        | Command::Compare(_)
+        | Command::Snapshot(_)
+        | Command::Changes(_) => None,
+        Command::Download(c) => Some(&c.progress),
+        Command::Batch(c) => Some(&c.progress),
+        Command::Verify(c) => Some(&c.progress),
+        Command::Extract(c) => Some(&c.progress),
+        Command::InstallMedia(c) => Some(&c.progress),
+    };
+    let reporter = Reporter::new(bars, progress)?;
+
+    let result = match &cli.command {
         Command::List(c) => list_subcommand(c).await,
-        Command::Download(c) => download_subcommand(c, bars).await,
-        Command::Verify(c) => verify_subcommand(c, bars).await,
-    }
+        Command::Info(c) => info_subcommand(c).await,
+        Command::Regions(c) => regions_subcommand(c).await,
+        Command::Compare(c) => compare_subcommand(c).await,
+        Command::Snapshot(c) => snapshot_subcommand(c).await,
+        Command::Changes(c) => changes_subcommand(c).await,
+        Command::Download(c) => download_subcommand(c, &reporter).await,
+        Command::Batch(c) => batch_subcommand(c, &reporter).await,
+        Command::Verify(c) => verify_subcommand(c, &reporter).await,
+        Command::Extract(c) => extract_subcommand(c, &reporter).await,
+        Command::InstallMedia(c) => install_media_subcommand(c, &reporter).await,
+    };
+
+    reporter.event(&Event::Finished {
+        error: result.as_ref().err().map(|e| format!("{e:#}")),
+    })?;
+
+    result
 }
@@ -0,0 +1,928 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Fake NU server for end-to-end tests. This serves the same API endpoints as
+//! the real server, using the schemas from [`crate::model`], along with the
+//! firmware files themselves.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use cap_std::{ambient_authority, fs::Dir};
+use flate2::{Compression, write::DeflateEncoder};
+use reqwest::StatusCode;
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+use crate::{
+    client::{Brand, CarInfo, FirmwareInfo, NuClient, NuClientBuilder},
+    crypto,
+    download::{DownloadOptions, Downloader, ProgressMessage, Segmentation, StreamTimeouts},
+    model::{
+        Car, CarDownloadData, CarListData, CarListRequest, Environment, File, GuidData, Platform,
+        RegionStatusData, ResponseData, UnknownFields,
+    },
+    retry::RetryPolicy,
+};
+
+const API_PATH: &str = "/api/v3";
+const FIRMWARE_PATH: &str = "/firmware";
+const PLATFORM_CODE: &str = "MOCK";
+const UPDATE_VERSION: &str = "26Q1";
+const GUID: &str = "20260101000000000";
+
+/// Firmware file served by [`MockServer`].
+#[derive(Clone, Debug)]
+pub struct MockFile {
+    /// Directory of the output file.
+    pub directory: Option<String>,
+    /// Filename of the output file.
+    pub name: String,
+    /// Contents of the output file.
+    pub data: Vec<u8>,
+    /// Number of split zips to serve. If `0`, the file is served as-is.
+    pub splits: u32,
+}
+
+impl MockFile {
+    pub fn new(name: &str, data: Vec<u8>, splits: u32) -> Self {
+        Self {
+            directory: None,
+            name: name.to_owned(),
+            data,
+            splits,
+        }
+    }
+
+    pub fn with_directory(mut self, directory: &str) -> Self {
+        self.directory = Some(directory.to_owned());
+        self
+    }
+
+    /// Name of the split zips without the extension.
+    fn zip_stem(&self) -> &str {
+        self.name
+            .rsplit_once('.')
+            .map(|(stem, _)| stem)
+            .unwrap_or(&self.name)
+    }
+
+    /// Names and contents of each download, in order.
+    fn downloads(&self) -> Vec<(String, Vec<u8>)> {
+        let stem = self.zip_stem();
+
+        match self.splits {
+            0 => vec![(self.name.clone(), self.data.clone())],
+            1 => vec![(
+                format!("{stem}.zip"),
+                split_zip(&self.name, &self.data, 1).remove(0),
+            )],
+            n => split_zip(&self.name, &self.data, n)
+                .into_iter()
+                .enumerate()
+                .map(|(i, data)| {
+                    let name = if i as u32 == n - 1 {
+                        format!("{stem}.zip")
+                    } else {
+                        format!("{stem}.z{:02}", i + 1)
+                    };
+
+                    (name, data)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Car served by [`MockServer`].
+#[derive(Clone, Debug)]
+pub struct MockCar {
+    pub brand: Brand,
+    pub id: String,
+    pub name: String,
+    pub versions: Vec<String>,
+    pub files: Vec<MockFile>,
+}
+
+impl MockCar {
+    pub fn new(brand: Brand, id: &str, name: &str, version: &str, files: Vec<MockFile>) -> Self {
+        Self {
+            brand,
+            id: id.to_owned(),
+            name: name.to_owned(),
+            versions: vec![version.to_owned()],
+            files,
+        }
+    }
+
+    fn download_code(&self) -> String {
+        format!("{}-{}", self.brand.as_code_str(), self.id)
+    }
+}
+
+/// Failure to inject into a firmware download response.
+#[derive(Clone, Copy, Debug)]
+pub enum Failure {
+    /// Respond with the specified HTTP status code and no body.
+    Status(StatusCode),
+    /// Send the full response headers, but close the connection after the
+    /// specified number of body bytes.
+    Truncate(usize),
+    /// Send the full response headers and the specified number of body bytes,
+    /// but then stop sending data without closing the connection.
+    Stall(usize),
+    /// Send data until the end of the file, but report the requested range in
+    /// the `Content-Range` header.
+    Overrun,
+    /// Ignore the end of the requested range and report the range through the
+    /// end of the file.
+    IgnoreRangeEnd,
+}
+
+struct State {
+    /// Region reported by the `/region/status` endpoint.
+    region: String,
+    /// Regions that have data.
+    regions: Vec<String>,
+    cars: Vec<MockCar>,
+    /// Firmware download contents by server path.
+    blobs: HashMap<String, Vec<u8>>,
+    /// Pending failures by filename. Each failure is used exactly once.
+    failures: Vec<(String, Failure)>,
+    /// Log of all received requests.
+    requests: Vec<String>,
+    /// Known user IDs and passwords.
+    accounts: Vec<(String, String)>,
+    /// Fields that are added to every car in `/car/list`.
+    unknown_car_fields: UnknownFields,
+}
+
+struct Request {
+    method: String,
+    /// Request target as sent by the client. This is an absolute URL if the
+    /// client is using the server as a proxy.
+    target: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: StatusCode,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+    truncate: Option<usize>,
+    stall: bool,
+}
+
+impl Response {
+    fn empty(status: StatusCode) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: vec![],
+            truncate: None,
+            stall: false,
+        }
+    }
+
+    fn json<T: Serialize>(data: T) -> Self {
+        let data = ResponseData {
+            data,
+            resp_code: "0000".to_owned(),
+            resp_msg: "Success".to_owned(),
+            unknown: UnknownFields::new(),
+        };
+
+        Self {
+            status: StatusCode::OK,
+            headers: vec![("Content-Type", "application/json".to_owned())],
+            body: serde_json::to_vec(&data).unwrap(),
+            truncate: None,
+            stall: false,
+        }
+    }
+}
+
+/// Fake NU server listening on a random localhost port.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Start a server that has data for the `US` region, which is also the
+    /// autodetected region.
+    pub async fn start(cars: Vec<MockCar>) -> Self {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut blobs = HashMap::new();
+
+        for car in &cars {
+            for file in &car.files {
+                for (name, data) in file.downloads() {
+                    blobs.insert(format!("{}/{name}", car.id), data);
+                }
+            }
+        }
+
+        let state = Arc::new(Mutex::new(State {
+            region: "US".to_owned(),
+            regions: vec!["US".to_owned()],
+            cars,
+            blobs,
+            failures: vec![],
+            requests: vec![],
+            accounts: vec![],
+            unknown_car_fields: UnknownFields::new(),
+        }));
+
+        let task = tokio::spawn({
+            let state = state.clone();
+
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(handle_connection(stream, addr, state.clone()));
+                }
+            }
+        });
+
+        Self { addr, state, task }
+    }
+
+    /// Base URL of the server. This is also usable as an HTTP proxy URL.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Base URL of the API endpoints.
+    pub fn api_url(&self) -> String {
+        format!("{}{API_PATH}", self.url())
+    }
+
+    /// Build a client that sends all API requests to this server.
+    pub fn client(&self) -> NuClient {
+        NuClientBuilder::new()
+            .api_url(self.api_url())
+            .api_url_eu(self.api_url())
+            .build()
+            .unwrap()
+    }
+
+    /// Fail the next request for the download with the specified filename.
+    /// Multiple failures for the same file are used in order.
+    pub fn inject_failure(&self, name: &str, failure: Failure) {
+        let mut state = self.state.lock().unwrap();
+        state.failures.push((name.to_owned(), failure));
+    }
+
+    /// Add a region that has data, in addition to `US`.
+    pub fn add_region(&self, region: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.regions.push(region.to_owned());
+    }
+
+    /// Add an account that is reported as authorized by `/car/list`.
+    pub fn add_account(&self, user_id: &str, password: &str) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .accounts
+            .push((user_id.to_owned(), password.to_owned()));
+    }
+
+    /// Add a field that is not part of the known schema to every car returned
+    /// by `/car/list`.
+    pub fn add_unknown_car_field(&self, key: &str, value: serde_json::Value) {
+        let mut state = self.state.lock().unwrap();
+        state.unknown_car_fields.insert(key.to_owned(), value);
+    }
+
+    /// Download the firmware for the first car to `directory` via the
+    /// [`Downloader`].
+    pub async fn download(
+        &self,
+        directory: &TempDir,
+        options: DownloadOptions,
+    ) -> anyhow::Result<()> {
+        self.download_with_progress(directory, options).await.0
+    }
+
+    /// Get the car and firmware info for the first car.
+    pub async fn firmware(&self) -> (CarInfo, FirmwareInfo) {
+        let brand = self.state.lock().unwrap().cars[0].brand;
+        let client = self.client();
+        let car = client
+            .get_cars("US", GUID, brand.as_code_str())
+            .await
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        let firmware = client.get_firmware_info("US", &car).await.unwrap();
+
+        (car, firmware)
+    }
+
+    /// Same as [`Self::download`], but also return all progress messages.
+    pub async fn download_with_progress(
+        &self,
+        directory: &TempDir,
+        options: DownloadOptions,
+    ) -> (anyhow::Result<()>, Vec<ProgressMessage>) {
+        let brand = self.state.lock().unwrap().cars[0].brand;
+        let client = self.client();
+        let car = match client.get_cars("US", GUID, brand.as_code_str()).await {
+            Ok(cars) => cars.into_iter().next().unwrap(),
+            Err(e) => return (Err(e.into()), vec![]),
+        };
+        let firmware = match client.get_firmware_info("US", &car).await {
+            Ok(f) => f,
+            Err(e) => return (Err(e.into()), vec![]),
+        };
+
+        let (downloader, progress_rx) =
+            Downloader::new(directory.open(), client, car, firmware, options);
+        let progress = drain_progress(progress_rx);
+
+        let result = downloader.download().await.map_err(Into::into);
+        drop(downloader);
+
+        (result, progress.await.unwrap())
+    }
+
+    /// Get the log of all requests received so far. Each entry is in the form
+    /// `<method> <path>`, followed by ` <range>` if a Range header was sent.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle_connection(stream: TcpStream, addr: SocketAddr, state: Arc<Mutex<State>>) {
+    let mut stream = BufReader::new(stream);
+
+    let Some(request) = read_request(&mut stream).await else {
+        return;
+    };
+
+    let head = request.method == "HEAD";
+    let response = {
+        let mut state = state.lock().unwrap();
+
+        let mut entry = format!("{} {}", request.method, request.target);
+        if let Some(range) = request.headers.get("range") {
+            entry.push(' ');
+            entry.push_str(range);
+        }
+        state.requests.push(entry);
+
+        route(&mut state, addr, &request)
+    };
+
+    let _ = write_response(stream.get_mut(), &response, head).await;
+}
+
+async fn read_request(stream: &mut BufReader<TcpStream>) -> Option<Request> {
+    let mut line = String::new();
+    stream.read_line(&mut line).await.ok()?;
+
+    let mut iter = line.split_whitespace();
+    let method = iter.next()?.to_owned();
+    let target = iter.next()?.to_owned();
+    let path = match target.strip_prefix("http://") {
+        Some(rest) => rest.find('/').map_or("/", |i| &rest[i..]).to_owned(),
+        None => target.clone(),
+    };
+
+    let mut headers = HashMap::new();
+
+    loop {
+        line.clear();
+        stream.read_line(&mut line).await.ok()?;
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        let (name, value) = line.split_once(':')?;
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_owned());
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or_default();
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body).await.ok()?;
+
+    Some(Request {
+        method,
+        target,
+        path,
+        headers,
+        body,
+    })
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    response: &Response,
+    head: bool,
+) -> std::io::Result<()> {
+    let mut data = format!(
+        "HTTP/1.1 {} {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        response.status.as_u16(),
+        response.status.canonical_reason().unwrap_or_default(),
+        response.body.len(),
+    );
+
+    for (name, value) in &response.headers {
+        data.push_str(&format!("{name}: {value}\r\n"));
+    }
+
+    data.push_str("\r\n");
+
+    let mut data = data.into_bytes();
+
+    if !head {
+        let n = response.truncate.unwrap_or(response.body.len());
+        data.extend_from_slice(&response.body[..n.min(response.body.len())]);
+    }
+
+    stream.write_all(&data).await?;
+
+    if response.stall {
+        // The connection stays open until the runtime shuts down.
+        std::future::pending::<()>().await;
+    }
+
+    stream.shutdown().await
+}
+
+fn parse_json<T: DeserializeOwned>(request: &Request) -> Option<T> {
+    serde_json::from_slice(&request.body).ok()
+}
+
+fn decrypt_field(value: &str) -> Option<String> {
+    let data = STANDARD.decode(value).ok()?;
+    let data = crypto::decrypt(&data).ok()?;
+    String::from_utf8(data).ok()
+}
+
+fn is_authenticated(state: &State, request: &CarListRequest) -> bool {
+    let (Some(user_id), Some(password)) = (
+        decrypt_field(&request.user_id),
+        decrypt_field(&request.user_pw),
+    ) else {
+        return false;
+    };
+
+    state
+        .accounts
+        .iter()
+        .any(|(u, p)| *u == user_id && *p == password)
+}
+
+fn route(state: &mut State, addr: SocketAddr, request: &Request) -> Response {
+    if let Some(path) = request.path.strip_prefix(FIRMWARE_PATH) {
+        return route_firmware(state, request, path.trim_start_matches('/'));
+    }
+
+    let Some(path) = request.path.strip_prefix(API_PATH) else {
+        return Response::empty(StatusCode::NOT_FOUND);
+    };
+    let components = path.split('/').skip(1).collect::<Vec<_>>();
+    let authorized = request.headers.contains_key("authorization");
+
+    match (request.method.as_str(), components.as_slice()) {
+        ("GET", ["region", "status", _]) => Response::json(RegionStatusData {
+            region: state.region.clone(),
+            service_yn: "Y".to_owned(),
+            unknown: UnknownFields::new(),
+        }),
+        ("GET", ["guid", _]) => Response::json(GuidData {
+            guid: GUID.to_owned(),
+            unknown: UnknownFields::new(),
+        }),
+        ("GET", ["car", "platform", brand, region]) => {
+            let mut platforms = vec![];
+
+            if state.regions.iter().any(|r| r == region)
+                && state.cars.iter().any(|c| c.brand.as_code_str() == *brand)
+            {
+                platforms.push(mock_platform());
+            }
+
+            Response::json(platforms)
+        }
+        ("POST", ["car", "list"]) if authorized => {
+            let Some(list_request) = parse_json::<CarListRequest>(request) else {
+                return Response::empty(StatusCode::BAD_REQUEST);
+            };
+
+            let cars = if state.regions.contains(&list_request.region) {
+                state
+                    .cars
+                    .iter()
+                    .filter(|c| c.brand.as_code_str() == list_request.brand)
+                    .map(|c| car_data(c, &state.unknown_car_fields))
+                    .collect()
+            } else {
+                vec![]
+            };
+
+            Response::json(CarListData {
+                cars,
+                map_file_download_path: format!("http://{addr}/images"),
+                navi_file_download_path: format!("http://{addr}/images"),
+                platforms: vec![mock_platform()],
+                user_auth: if is_authenticated(state, &list_request) {
+                    // Placeholder. The value that the real server returns for
+                    // accepted credentials has not been observed.
+                    "M".to_owned()
+                } else {
+                    "N".to_owned()
+                },
+                unknown: UnknownFields::new(),
+            })
+        }
+        ("GET", ["car", "download", code]) if authorized => {
+            match state.cars.iter().find(|c| c.download_code() == *code) {
+                Some(car) => Response::json(mock_car_download(car, addr)),
+                None => Response::empty(StatusCode::NOT_FOUND),
+            }
+        }
+        ("POST", ["car", "list"]) | ("GET", ["car", "download", _]) => {
+            Response::empty(StatusCode::UNAUTHORIZED)
+        }
+        _ => Response::empty(StatusCode::NOT_FOUND),
+    }
+}
+
+fn route_firmware(state: &mut State, request: &Request, path: &str) -> Response {
+    let path = urlencoding::decode(path).unwrap().into_owned();
+    let Some(blob) = state.blobs.get(&path) else {
+        return Response::empty(StatusCode::NOT_FOUND);
+    };
+    let len = blob.len() as u64;
+
+    if request.method == "HEAD" {
+        let mut response = Response::empty(StatusCode::OK);
+        response.body = blob.clone();
+        return response;
+    } else if request.method != "GET" {
+        return Response::empty(StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    let failure = state
+        .failures
+        .iter()
+        .position(|(name, _)| path.rsplit('/').next() == Some(name))
+        .map(|i| state.failures.remove(i).1);
+    if let Some(Failure::Status(status)) = failure {
+        return Response::empty(status);
+    }
+
+    let range = request
+        .headers
+        .get("range")
+        .and_then(|r| r.strip_prefix("bytes="))
+        .and_then(|r| r.split_once('-'))
+        .and_then(|(start, end)| {
+            let start = start.parse::<u64>().ok()?;
+            let end = if end.is_empty() {
+                len.checked_sub(1)?
+            } else {
+                end.parse::<u64>().ok()?.min(len.checked_sub(1)?)
+            };
+
+            Some((start, end))
+        });
+
+    let mut response = match range {
+        // Like the real server, empty files cannot be represented with ranges.
+        _ if len == 0 => Response::empty(StatusCode::OK),
+        Some((start, _)) if start >= len => {
+            let mut response = Response::empty(StatusCode::RANGE_NOT_SATISFIABLE);
+            response
+                .headers
+                .push(("Content-Range", format!("bytes */{len}")));
+            response
+        }
+        Some((start, end)) if start <= end => Response {
+            status: StatusCode::PARTIAL_CONTENT,
+            headers: vec![("Content-Range", format!("bytes {start}-{end}/{len}"))],
+            body: blob[start as usize..=end as usize].to_vec(),
+            truncate: None,
+            stall: false,
+        },
+        _ => Response {
+            status: StatusCode::OK,
+            headers: vec![],
+            body: blob.clone(),
+            truncate: None,
+            stall: false,
+        },
+    };
+
+    match failure {
+        Some(Failure::Truncate(n)) => response.truncate = Some(n),
+        Some(Failure::Stall(n)) => {
+            response.truncate = Some(n);
+            response.stall = true;
+        }
+        Some(failure @ (Failure::Overrun | Failure::IgnoreRangeEnd))
+            if response.status == StatusCode::PARTIAL_CONTENT =>
+        {
+            let (start, _) = range.unwrap();
+            response.body = blob[start as usize..].to_vec();
+
+            if matches!(failure, Failure::IgnoreRangeEnd) {
+                response.headers =
+                    vec![("Content-Range", format!("bytes {start}-{}/{len}", len - 1))];
+            }
+        }
+        _ => {}
+    }
+
+    response
+}
+
+fn mock_platform() -> Platform {
+    Platform {
+        map_img_name: "map.png".to_owned(),
+        platform_code: PLATFORM_CODE.to_owned(),
+        platform_img_name: "platform.png".to_owned(),
+        unknown: UnknownFields::new(),
+    }
+}
+
+fn car_data(car: &MockCar, unknown: &UnknownFields) -> Car {
+    Car {
+        brand: car.brand.as_code_str().to_owned(),
+        dest_path: car.id.clone(),
+        download_code: car.download_code(),
+        dvc_name: car.name.clone(),
+        eng_dvc_name: String::new(),
+        eng_vcl_name: String::new(),
+        mcode: "1".to_owned(),
+        media_type: "USB".to_owned(),
+        model_names: vec![car.id.clone()],
+        platform_code: PLATFORM_CODE.to_owned(),
+        priority: "1".to_owned(),
+        regist_yn: "Y".to_owned(),
+        service_yn: "Y".to_owned(),
+        sw_ver_idxs: vec!["*".to_owned()],
+        sw_vers: car.versions.clone(),
+        vcl_name: car.name.clone(),
+        release_date: None,
+        unknown: unknown.clone(),
+    }
+}
+
+fn mock_file(car: &MockCar, file: &MockFile) -> File {
+    let downloads = file.downloads();
+    let (first, last, zip_size) = match file.splits {
+        0 => (String::new(), String::new(), 0),
+        n => (
+            // The server reports the last file as "first" and the second last
+            // file as "last".
+            downloads[n as usize - 1].0.clone(),
+            downloads[(n as usize).saturating_sub(2)].0.clone(),
+            downloads.iter().map(|(_, d)| d.len()).sum(),
+        ),
+    };
+
+    File {
+        dest_path: file.directory.clone().unwrap_or_default(),
+        error: "0".to_owned(),
+        file_crc: crc32fast::hash(&file.data).cast_signed().to_string(),
+        file_name: file.name.clone(),
+        file_path: format!("/{}/", car.id),
+        file_size: file.data.len().to_string(),
+        region: String::new(),
+        sub_region: String::new(),
+        version: "1".to_owned(),
+        zip_file_cnt: file.splits.to_string(),
+        zip_file_first_name: first,
+        zip_file_last_name: last,
+        zip_file_size: zip_size.to_string(),
+        unknown: UnknownFields::new(),
+    }
+}
+
+fn mock_car_download(car: &MockCar, addr: SocketAddr) -> CarDownloadData {
+    CarDownloadData {
+        environment: Environment {
+            ag_zip_name_ext: String::new(),
+            common_prefix: String::new(),
+            dest_root_path: String::new(),
+            download_file_cnt: car.files.len().to_string(),
+            download_file_size: car
+                .files
+                .iter()
+                .map(|f| f.data.len())
+                .sum::<usize>()
+                .to_string(),
+            download_prefix: String::new(),
+            download_root_path: format!("http://{addr}{FIRMWARE_PATH}"),
+            is_online_navi: String::new(),
+            model_prefix: PLATFORM_CODE.to_owned(),
+            sums: String::new(),
+            update_version: UPDATE_VERSION.to_owned(),
+            unknown: UnknownFields::new(),
+        },
+        files: car.files.iter().map(|f| mock_file(car, f)).collect(),
+        unknown: UnknownFields::new(),
+    }
+}
+
+/// Create a split zip containing a single deflate-compressed entry, using the
+/// same layout as Info-ZIP's `zip -s`. Offsets in the local header, central
+/// directory, and end of central directory records are relative to the split
+/// that contains them.
+pub fn split_zip(name: &str, data: &[u8], splits: u32) -> Vec<Vec<u8>> {
+    assert!(splits > 0, "Must have at least one split");
+
+    let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+    encoder.write_all(data).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let crc32 = crc32fast::hash(data);
+    let last_disk = (splits - 1) as u16;
+
+    // Spanning signature, local header, and data.
+    let mut stream = vec![];
+    if splits > 1 {
+        stream.extend_from_slice(&0x08074b50u32.to_le_bytes());
+    }
+    let local_header_offset = stream.len() as u32;
+    stream.extend_from_slice(&0x04034b50u32.to_le_bytes());
+    stream.extend_from_slice(&20u16.to_le_bytes());
+    stream.extend_from_slice(&0u16.to_le_bytes());
+    stream.extend_from_slice(&8u16.to_le_bytes());
+    stream.extend_from_slice(&[0; 4]);
+    stream.extend_from_slice(&crc32.to_le_bytes());
+    stream.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+    stream.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    stream.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    stream.extend_from_slice(&0u16.to_le_bytes());
+    stream.extend_from_slice(name.as_bytes());
+    stream.extend_from_slice(&compressed);
+
+    // Everything except for the last split is evenly sized.
+    let piece_size = stream.len().div_ceil(splits as usize);
+    let mut pieces = stream
+        .chunks(piece_size)
+        .map(|c| c.to_vec())
+        .collect::<Vec<_>>();
+    assert_eq!(pieces.len(), splits as usize, "Data is too small to split");
+    let last = pieces.last_mut().unwrap();
+
+    let cd_offset = last.len() as u32;
+    last.extend_from_slice(&0x02014b50u32.to_le_bytes());
+    last.extend_from_slice(&20u16.to_le_bytes());
+    last.extend_from_slice(&20u16.to_le_bytes());
+    last.extend_from_slice(&0u16.to_le_bytes());
+    last.extend_from_slice(&8u16.to_le_bytes());
+    last.extend_from_slice(&[0; 4]);
+    last.extend_from_slice(&crc32.to_le_bytes());
+    last.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+    last.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    last.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    last.extend_from_slice(&[0; 4]);
+    last.extend_from_slice(&0u16.to_le_bytes());
+    last.extend_from_slice(&[0; 6]);
+    last.extend_from_slice(&local_header_offset.to_le_bytes());
+    last.extend_from_slice(name.as_bytes());
+    let cd_size = last.len() as u32 - cd_offset;
+
+    last.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    last.extend_from_slice(&last_disk.to_le_bytes());
+    last.extend_from_slice(&last_disk.to_le_bytes());
+    last.extend_from_slice(&1u16.to_le_bytes());
+    last.extend_from_slice(&1u16.to_le_bytes());
+    last.extend_from_slice(&cd_size.to_le_bytes());
+    last.extend_from_slice(&cd_offset.to_le_bytes());
+    last.extend_from_slice(&0u16.to_le_bytes());
+
+    pieces
+}
+
+/// Car with a split file, a file with a single split, an unsplit file in a
+/// subdirectory, and an empty file. Tests that need different files or car
+/// details can override fields with struct update syntax.
+pub fn mock_car() -> MockCar {
+    MockCar::new(
+        Brand::Kia,
+        "CAR1",
+        "2026 Car",
+        "CAR1.USA.S5W_L.001.001.260101",
+        vec![
+            MockFile::new("split.tar", test_data(64 * 1024, 1), 3),
+            MockFile::new("single.tar", test_data(1024, 2), 1),
+            MockFile::new("unsplit.bin", test_data(4096, 3), 0).with_directory("sub"),
+            MockFile::new("empty.bin", vec![], 0),
+        ],
+    )
+}
+
+/// Download options with no retries, no limits, and all optional behavior
+/// disabled.
+pub fn download_options() -> DownloadOptions {
+    DownloadOptions {
+        concurrency: 2,
+        retry_policy: RetryPolicy::none(),
+        keep_raw: false,
+        stream_extract: false,
+        keep_splits_until_verified: false,
+        rate_limiter: None,
+        timeouts: StreamTimeouts::default(),
+        segmentation: Segmentation::none(),
+        skip_space_check: false,
+    }
+}
+
+/// Generate deterministic test data that is somewhat compressible.
+pub fn test_data(len: usize, seed: u8) -> Vec<u8> {
+    let mut state = u32::from(seed) | 1;
+
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state % 16) as u8 + b'a'
+        })
+        .collect()
+}
+
+/// Temporary directory that is deleted when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "{}-test-{}-{}",
+            env!("CARGO_PKG_NAME"),
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst),
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn open(&self) -> Dir {
+        Dir::open_ambient_dir(&self.0, ambient_authority()).unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Receive all progress messages until the sender is dropped.
+pub fn drain_progress<T: Send + 'static>(mut rx: mpsc::Receiver<T>) -> JoinHandle<Vec<T>> {
+    tokio::spawn(async move {
+        let mut messages = vec![];
+
+        while let Some(msg) = rx.recv().await {
+            messages.push(msg);
+        }
+
+        messages
+    })
+}
@@ -1,41 +1,114 @@
-// SPDX-FileCopyrightText: 2024-2025 Andrew Gunnerson
+// SPDX-FileCopyrightText: 2024-2026 Andrew Gunnerson
 // SPDX-License-Identifier: GPL-3.0-only
 
-use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize, de::IgnoredAny};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum Error {
     #[error("Request failed: NU {0}: {1}")]
     BadResponse(String, String),
+    #[error("Response has unknown fields: {}", .0.join(", "))]
+    UnknownFields(Vec<String>),
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// Fields in a response object that are not part of the known schema.
+pub type UnknownFields = BTreeMap<String, serde_json::Value>;
+
+/// How to handle response fields that are not part of the known schema.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SchemaMode {
+    /// Keep the unknown fields and log a warning.
+    #[default]
+    Lenient,
+    /// Fail with [`Error::UnknownFields`]. This is useful for noticing when
+    /// the API changes.
+    Strict,
+}
+
+/// A response type that captures unknown fields during deserialization.
+pub trait Schema {
+    /// Add the path of every unknown field to `fields`. `prefix` is the path
+    /// of `self` within the response.
+    fn unknown_fields(&self, prefix: &str, fields: &mut BTreeSet<String>);
+}
+
+fn add_unknown_fields(unknown: &UnknownFields, prefix: &str, fields: &mut BTreeSet<String>) {
+    for key in unknown.keys() {
+        fields.insert(join_path(prefix, key));
+    }
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn unknown_fields(&self, prefix: &str, fields: &mut BTreeSet<String>) {
+        let prefix = format!("{prefix}[]");
+
+        for item in self {
+            item.unknown_fields(&prefix, fields);
+        }
+    }
+}
+
+impl Schema for IgnoredAny {
+    fn unknown_fields(&self, _prefix: &str, _fields: &mut BTreeSet<String>) {}
+}
+
+/// Implement [`Schema`] for a type whose only nested objects are the listed
+/// fields.
+macro_rules! impl_schema {
+    ($type:ty $(, $field:ident)*) => {
+        impl Schema for $type {
+            fn unknown_fields(&self, prefix: &str, fields: &mut BTreeSet<String>) {
+                add_unknown_fields(&self.unknown, prefix, fields);
+                $(self.$field.unknown_fields(&join_path(prefix, stringify!($field)), fields);)*
+            }
+        }
+    };
+}
+
 /// Response data for `/region/status/<country code>` endpoint.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[allow(dead_code)]
-#[serde(deny_unknown_fields, rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
 pub struct RegionStatusData {
     /// Two character ISO country code.
     pub region: String,
     /// Unknown "Y"/"N" boolean value.
     pub service_yn: String,
+    /// Fields that are not part of the known schema.
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
 }
 
 /// Response data for `/guid` endpoint.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[allow(dead_code)]
-#[serde(deny_unknown_fields, rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
 pub struct GuidData {
     /// GUID value. This is not a UUID, but rather a human-readable timestamp.
     pub guid: String,
+    /// Fields that are not part of the known schema.
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
 }
 
 /// Car object in response data for `/car/list` endpoint.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(dead_code)]
-#[serde(deny_unknown_fields, rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
 pub struct Car {
     /// Two character brand code.
     pub brand: String,
@@ -75,12 +148,15 @@ pub struct Car {
     /// Unknown format. As of 2025-03-07, some region + brand combinations
     /// return empty strings, while the rest just omit the field entirely.
     pub release_date: Option<String>,
+    /// Fields that are not part of the known schema.
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
 }
 
 /// Platform object in response data for `/car/list` endpoint.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(dead_code)]
-#[serde(deny_unknown_fields, rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
 pub struct Platform {
     /// Screenshot filename (without base URL).
     pub map_img_name: String,
@@ -88,12 +164,15 @@ pub struct Platform {
     pub platform_code: String,
     /// Screenshot filename (without base URL).
     pub platform_img_name: String,
+    /// Fields that are not part of the known schema.
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
 }
 
 /// Response data for `/car/list` endpoint.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(dead_code)]
-#[serde(deny_unknown_fields, rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
 pub struct CarListData {
     /// List of [`Car`] instances.
     pub cars: Vec<Car>,
@@ -105,12 +184,15 @@ pub struct CarListData {
     pub platforms: Vec<Platform>,
     /// Can be `M` or `N`. Meaning is unknown.
     pub user_auth: String,
+    /// Fields that are not part of the known schema.
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
 }
 
 /// Environment object in response data for `/car/download/<code>` endpoint.
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(dead_code)]
-#[serde(deny_unknown_fields, rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
 pub struct Environment {
     /// Empty string.
     pub ag_zip_name_ext: String,
@@ -141,12 +223,15 @@ pub struct Environment {
     /// querter (eg. 23Q2), but does not match the date in the actual firmware
     /// version number.
     pub update_version: String,
+    /// Fields that are not part of the known schema.
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
 }
 
 /// File object in response data for `/car/download/<code>` endpoint.
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(dead_code)]
-#[serde(deny_unknown_fields, rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
 pub struct File {
     /// Empty string.
     pub dest_path: String,
@@ -176,21 +261,27 @@ pub struct File {
     pub zip_file_last_name: String,
     /// Total byte size of all split zip files.
     pub zip_file_size: String,
+    /// Fields that are not part of the known schema.
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
 }
 
 /// Response data for `/car/download/<code>` endpoint.
-#[derive(Debug, Deserialize)]
-#[serde(deny_unknown_fields, rename_all = "camelCase")]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CarDownloadData {
     /// List of [`Environment`] instances.
     pub environment: Environment,
     /// List of [`File`] instances.
     pub files: Vec<File>,
+    /// Fields that are not part of the known schema.
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
 }
 
 /// Raw response data for all API responses.
-#[derive(Debug, Deserialize)]
-#[serde(deny_unknown_fields, rename_all = "camelCase")]
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ResponseData<D> {
     /// Request-specific data.
     pub data: D,
@@ -200,6 +291,25 @@ pub struct ResponseData<D> {
     pub resp_code: String,
     /// Status message. Also present for successful requests.
     pub resp_msg: String,
+    /// Fields that are not part of the known schema.
+    #[serde(flatten)]
+    pub unknown: UnknownFields,
+}
+
+impl_schema!(RegionStatusData);
+impl_schema!(GuidData);
+impl_schema!(Car);
+impl_schema!(Platform);
+impl_schema!(CarListData, cars, platforms);
+impl_schema!(Environment);
+impl_schema!(File);
+impl_schema!(CarDownloadData, environment, files);
+
+impl<D: Schema> Schema for ResponseData<D> {
+    fn unknown_fields(&self, prefix: &str, fields: &mut BTreeSet<String>) {
+        add_unknown_fields(&self.unknown, prefix, fields);
+        self.data.unknown_fields(&join_path(prefix, "data"), fields);
+    }
 }
 
 impl<D> ResponseData<D> {
@@ -220,19 +330,19 @@ impl<D> ResponseData<D> {
 }
 
 /// Request data for `/car/list` endpoint.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CarListRequest {
-    /// Two character car brand code. Must be one of [`crate::cli::Brand`].
+    /// Two character car brand code. Must be one of [`crate::client::Brand`].
     pub brand: String,
     /// GUID value from [`GuidData`].
     pub guid: String,
     /// Two character ISO country code.
     pub region: String,
-    /// Username encrypted with [`crate::crypto::encrypt`]. Not needed for
+    /// Username encrypted with `crypto::encrypt()`. Not needed for
     /// anonymous downloads.
     pub user_id: String,
-    /// Password encrypted with [`crate::crypto::encrypt`]. Not needed for
+    /// Password encrypted with `crypto::encrypt()`. Not needed for
     /// anonymous downloads.
     pub user_pw: String,
     /// Always `U` regardless if the user is anonymous. Unknown whether there
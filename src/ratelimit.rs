@@ -0,0 +1,211 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{fmt, str::FromStr, sync::Mutex, time::Duration};
+
+use jiff::{Zoned, civil::Time};
+use thiserror::Error;
+use tokio::time::{self, Instant};
+
+/// How often to check if the current time is within the limiter's window.
+const WINDOW_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Expected <START>-<END>: {0:?}")]
+    InvalidWindow(String),
+    #[error("Invalid time: {0:?}")]
+    InvalidTime(String, #[source] jiff::Error),
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Daily time window in local time. If the end is before the start, then the
+/// window wraps around midnight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeWindow {
+    pub start: Time,
+    pub end: Time,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: Time) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.strftime("%H:%M"),
+            self.end.strftime("%H:%M"),
+        )
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| Error::InvalidWindow(s.to_owned()))?;
+        let parse = |t: &str| {
+            t.parse::<Time>()
+                .map_err(|e| Error::InvalidTime(t.to_owned(), e))
+        };
+
+        Ok(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    /// Available bytes. This becomes negative when a caller needs to wait.
+    tokens: f64,
+    last_refill: Instant,
+    active: bool,
+    last_window_check: Option<Instant>,
+}
+
+/// Token bucket rate limiter that is shared by all download tasks.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Bytes per second.
+    rate: u64,
+    /// Only limit the rate during this time window.
+    window: Option<TimeWindow>,
+    state: Mutex<State>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64, window: Option<TimeWindow>) -> Self {
+        assert!(rate > 0, "Rate must be non-zero");
+
+        Self {
+            rate,
+            window,
+            state: Mutex::new(State {
+                tokens: 0.0,
+                last_refill: Instant::now(),
+                active: true,
+                last_window_check: None,
+            }),
+        }
+    }
+
+    /// Compute how long the caller must wait before `bytes` may be consumed.
+    fn reserve(&self, bytes: u64) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        if let Some(window) = &self.window
+            && state
+                .last_window_check
+                .is_none_or(|t| now - t >= WINDOW_CHECK_INTERVAL)
+        {
+            state.active = window.contains(Zoned::now().time());
+            state.last_window_check = Some(now);
+        }
+
+        // Allow bursts of up to one second's worth of data.
+        let rate = self.rate as f64;
+        let elapsed = now - state.last_refill;
+        state.tokens = (state.tokens + elapsed.as_secs_f64() * rate).min(rate);
+        state.last_refill = now;
+
+        if !state.active {
+            return Duration::ZERO;
+        }
+
+        state.tokens -= bytes as f64;
+
+        if state.tokens < 0.0 {
+            Duration::from_secs_f64(-state.tokens / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Wait until `bytes` may be consumed without exceeding the rate limit.
+    pub async fn acquire(&self, bytes: u64) {
+        let delay = self.reserve(bytes);
+
+        if !delay.is_zero() {
+            time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jiff::civil::time;
+
+    use super::*;
+
+    #[test]
+    fn test_time_window() {
+        let window = "08:00-18:30".parse::<TimeWindow>().unwrap();
+        assert_eq!(window.start, time(8, 0, 0, 0));
+        assert_eq!(window.end, time(18, 30, 0, 0));
+        assert_eq!(window.to_string(), "08:00-18:30");
+        assert!(window.contains(time(12, 0, 0, 0)));
+        assert!(!window.contains(time(18, 30, 0, 0)));
+        assert!(!window.contains(time(7, 59, 0, 0)));
+
+        let window = "22:00-06:00".parse::<TimeWindow>().unwrap();
+        assert!(window.contains(time(23, 0, 0, 0)));
+        assert!(window.contains(time(1, 0, 0, 0)));
+        assert!(!window.contains(time(12, 0, 0, 0)));
+
+        assert!(matches!(
+            "08:00".parse::<TimeWindow>(),
+            Err(Error::InvalidWindow(_)),
+        ));
+        assert!(matches!(
+            "08:00-25:00".parse::<TimeWindow>(),
+            Err(Error::InvalidTime(_, _)),
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(1024 * 1024, None);
+        let start = Instant::now();
+
+        // The clock only advances while sleeping, so there are no bursts.
+        for _ in 0..8 {
+            limiter.acquire(64 * 1024).await;
+        }
+
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed.abs_diff(Duration::from_millis(500)) < Duration::from_millis(1),
+            "{elapsed:?}",
+        );
+
+        // Idle time is credited, but only up to one second's worth of data.
+        time::advance(Duration::from_secs(5)).await;
+        let start = Instant::now();
+
+        limiter.acquire(1024 * 1024).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        limiter.acquire(256 * 1024).await;
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed.abs_diff(Duration::from_millis(250)) < Duration::from_millis(1),
+            "{elapsed:?}",
+        );
+    }
+}
@@ -0,0 +1,340 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Recording of HTTP exchanges to a directory and replaying them later. This
+//! allows flows that only query the API, like listing cars or a dry-run
+//! download, to be rerun deterministically for bug reports and tests.
+//!
+//! Each request/response pair is stored as a separate JSON file. Requests are
+//! matched by their method, URL, `Range` header, and JSON body. Other request
+//! headers, like `Authorization`, depend on the current time and are not
+//! recorded. Credentials in request bodies are redacted. Firmware response
+//! bodies are never recorded, so firmware downloads cannot be replayed.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use reqwest::{Request, StatusCode, header::HeaderMap};
+use ring::digest::{SHA256, digest};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use tracing::debug;
+
+const EXCHANGE_EXT: &str = "json";
+
+/// Request body fields that contain credentials.
+const REDACTED_FIELDS: &[&str] = &["userId", "userPw"];
+const REDACTED: &str = "<redacted>";
+
+/// Maximum length of the URL path portion of a filename.
+const MAX_PATH_LEN: usize = 64;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Failed to create directory: {0:?}")]
+    CreateDir(PathBuf, #[source] io::Error),
+    #[error("Failed to read exchange: {0:?}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("Failed to write exchange: {0:?}")]
+    Write(PathBuf, #[source] io::Error),
+    #[error("Failed to parse exchange: {0:?}")]
+    Parse(PathBuf, #[source] serde_json::Error),
+    #[error("Failed to serialize exchange")]
+    Serialize(#[source] serde_json::Error),
+    #[error("Invalid HTTP status in exchange: {0}")]
+    InvalidStatus(u16),
+    #[error("No recorded response for request: {0} {1}")]
+    NotRecorded(String, String),
+    #[error("Firmware downloads cannot be replayed: {0}")]
+    FirmwareReplay(String),
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The parts of a request that are used for matching during replay.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    /// Value of the `Range` header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<String>,
+    /// Body with credentials redacted. Only JSON bodies are recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+}
+
+impl RecordedRequest {
+    pub fn new(request: &Request) -> Self {
+        let range = request
+            .headers()
+            .get(reqwest::header::RANGE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_owned());
+        let mut body = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .and_then(|b| serde_json::from_slice::<Value>(b).ok());
+
+        if let Some(Value::Object(map)) = &mut body {
+            for field in REDACTED_FIELDS {
+                if let Some(value) = map.get_mut(*field)
+                    && value.as_str().is_some_and(|s| !s.is_empty())
+                {
+                    *value = Value::String(REDACTED.to_owned());
+                }
+            }
+        }
+
+        Self {
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            range,
+            body,
+        }
+    }
+
+    /// Filename for the exchange. This contains the method and URL path for
+    /// readability and a hash of the whole request for uniqueness.
+    fn filename(&self) -> String {
+        let path = reqwest::Url::parse(&self.url)
+            .map(|u| u.path().to_owned())
+            .unwrap_or_default();
+        let path = path
+            .trim_matches('/')
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let path = &path[path.len().saturating_sub(MAX_PATH_LEN)..];
+
+        // Serializing a struct and serde_json's sorted maps is deterministic.
+        let data = serde_json::to_vec(self).expect("Request is always serializable");
+        let hash = digest(&SHA256, &data);
+
+        let mut name = format!("{}_{path}_", self.method);
+        for b in &hash.as_ref()[..8] {
+            write!(name, "{b:02x}").unwrap();
+        }
+        name.push('.');
+        name.push_str(EXCHANGE_EXT);
+
+        name
+    }
+}
+
+/// A response as it was received from the server.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    /// Body if it is valid JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+    /// Body if it is not valid JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_text: Option<String>,
+}
+
+impl RecordedResponse {
+    /// Capture a response. If `body` is [`None`], only the headers are
+    /// recorded.
+    pub fn new(status: StatusCode, headers: &HeaderMap, body: Option<&[u8]>) -> Self {
+        let headers = headers
+            .iter()
+            .map(|(k, v)| {
+                (
+                    k.as_str().to_owned(),
+                    String::from_utf8_lossy(v.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        let (body, body_text) = match body {
+            Some(b) => match serde_json::from_slice(b) {
+                Ok(v) => (Some(v), None),
+                Err(_) => (None, Some(String::from_utf8_lossy(b).into_owned())),
+            },
+            None => (None, None),
+        };
+
+        Self {
+            status: status.as_u16(),
+            headers,
+            body,
+            body_text,
+        }
+    }
+
+    pub fn status(&self) -> Result<StatusCode> {
+        StatusCode::from_u16(self.status).map_err(|_| Error::InvalidStatus(self.status))
+    }
+
+    /// Get the headers. Headers that are no longer valid after being edited by
+    /// hand are skipped.
+    pub fn header_map(&self) -> HeaderMap {
+        self.headers
+            .iter()
+            .filter_map(|(k, v)| Some((k.parse().ok()?, v.parse().ok()?)))
+            .collect()
+    }
+
+    /// Get the raw body. JSON bodies are re-serialized, so whitespace and
+    /// field order may differ from what the server originally sent.
+    pub fn body_bytes(&self) -> Vec<u8> {
+        if let Some(body) = &self.body {
+            serde_json::to_vec(body).expect("JSON value is always serializable")
+        } else if let Some(text) = &self.body_text {
+            text.as_bytes().to_vec()
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// A request/response pair.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Exchange {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Send requests normally and save every exchange.
+    Record,
+    /// Serve responses from previously saved exchanges without sending any
+    /// requests.
+    Replay,
+}
+
+/// Directory of recorded exchanges.
+#[derive(Clone, Debug)]
+pub struct Recording {
+    directory: PathBuf,
+    mode: Mode,
+}
+
+impl Recording {
+    pub fn new(directory: PathBuf, mode: Mode) -> Self {
+        Self { directory, mode }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Save an exchange, replacing any existing exchange for the same request.
+    pub fn save(&self, exchange: &Exchange) -> Result<PathBuf> {
+        fs::create_dir_all(&self.directory)
+            .map_err(|e| Error::CreateDir(self.directory.clone(), e))?;
+
+        let path = self.directory.join(exchange.request.filename());
+        let data = serde_json::to_vec_pretty(exchange).map_err(Error::Serialize)?;
+
+        fs::write(&path, data).map_err(|e| Error::Write(path.clone(), e))?;
+        debug!(
+            "Recorded {} {} to {path:?}",
+            exchange.request.method, exchange.request.url,
+        );
+
+        Ok(path)
+    }
+
+    /// Load the response for a previously saved request.
+    pub fn load(&self, request: &RecordedRequest) -> Result<RecordedResponse> {
+        let path = self.directory.join(request.filename());
+
+        let data = match fs::read(&path) {
+            Ok(d) => d,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::NotRecorded(
+                    request.method.clone(),
+                    request.url.clone(),
+                ));
+            }
+            Err(e) => return Err(Error::Read(path, e)),
+        };
+        let exchange: Exchange =
+            serde_json::from_slice(&data).map_err(|e| Error::Parse(path.clone(), e))?;
+        debug!("Replaying {} {} from {path:?}", request.method, request.url);
+
+        Ok(exchange.response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Client, header};
+
+    use super::*;
+    use crate::mock::TempDir;
+
+    #[test]
+    fn test_round_trip() {
+        let temp_dir = TempDir::new();
+        let recording = Recording::new(temp_dir.path().join("rec"), Mode::Record);
+        let client = Client::new();
+
+        let build = |user_pw: &str| {
+            let request = client
+                .post("http://localhost/api/v3/car/list")
+                .header(header::AUTHORIZATION, user_pw)
+                .json(&serde_json::json!({"guid": "g", "userId": "", "userPw": user_pw}))
+                .build()
+                .unwrap();
+
+            RecordedRequest::new(&request)
+        };
+
+        let request = build("secret");
+        assert_eq!(request.body.as_ref().unwrap()["userPw"], REDACTED);
+        assert_eq!(request.body.as_ref().unwrap()["userId"], "");
+        // Credentials and headers do not affect matching.
+        assert_eq!(request, build("other"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        let response =
+            RecordedResponse::new(StatusCode::OK, &headers, Some(br#"{"data": [1, 2]}"#));
+
+        let path = recording
+            .save(&Exchange {
+                request: request.clone(),
+                response: response.clone(),
+            })
+            .unwrap();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("POST_api_v3_car_list_"), "{name}");
+
+        let replay = Recording::new(recording.directory().to_owned(), Mode::Replay);
+        let loaded = replay.load(&request).unwrap();
+        assert_eq!(loaded, response);
+        assert_eq!(loaded.status().unwrap(), StatusCode::OK);
+        assert_eq!(loaded.body_bytes(), br#"{"data":[1,2]}"#);
+
+        let text = RecordedResponse::new(StatusCode::BAD_GATEWAY, &headers, Some(b"<html>"));
+        assert_eq!(text.body_bytes(), b"<html>");
+
+        let mut missing = request;
+        missing.url.push_str("?x");
+        assert!(matches!(
+            replay.load(&missing),
+            Err(Error::NotRecorded(m, _)) if m == "POST",
+        ));
+    }
+}
@@ -0,0 +1,228 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Retry policy shared by API requests and firmware downloads. Only failures
+//! that are likely to be transient are retried. The delay between attempts
+//! grows exponentially and is randomized so that concurrent downloads don't
+//! retry in lockstep.
+
+use std::time::Duration;
+
+use jiff::{Timestamp, fmt::rfc2822::DateTimeParser};
+use reqwest::{
+    StatusCode,
+    header::{self, HeaderMap},
+};
+use ring::rand::{SecureRandom, SystemRandom};
+
+pub const DEFAULT_RETRIES: u8 = 3;
+pub const DEFAULT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+pub const DEFAULT_JITTER: f64 = 0.5;
+
+/// Whether a failed operation should be attempted again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Retryability {
+    /// The failure is likely transient, like a timeout, a connection reset, or
+    /// a server error.
+    Retryable {
+        /// Delay requested by the server via the `Retry-After` header.
+        retry_after: Option<Duration>,
+    },
+    /// Retrying would fail the same way, like for HTTP 404 or a CRC32
+    /// mismatch.
+    Fatal,
+}
+
+impl Retryability {
+    /// Classify an HTTP error status. Server errors, HTTP 408, and HTTP 429 are
+    /// retryable. All other statuses are fatal.
+    pub fn from_status(status: StatusCode, retry_after: Option<Duration>) -> Self {
+        if status.is_server_error()
+            || status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::TOO_MANY_REQUESTS
+        {
+            Self::Retryable { retry_after }
+        } else {
+            Self::Fatal
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Retryable { .. })
+    }
+}
+
+/// Errors that can be classified as retryable or fatal.
+pub trait Classify {
+    fn retryability(&self) -> Retryability;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries after the initial attempt.
+    pub max_retries: u8,
+    /// Delay before the first retry. The delay doubles for each subsequent
+    /// retry.
+    pub initial_delay: Duration,
+    /// Upper bound for the delay between attempts. This also applies to
+    /// delays requested by the server.
+    pub max_delay: Duration,
+    /// Fraction of each delay, from 0 to 1, that is randomly subtracted.
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Create a policy with the specified number of retries and the default
+    /// delays.
+    pub fn new(max_retries: u8) -> Self {
+        Self {
+            max_retries,
+            ..Default::default()
+        }
+    }
+
+    /// Create a policy that never retries.
+    pub fn none() -> Self {
+        Self::new(0)
+    }
+
+    /// Get the delay after the specified 0-based attempt failed. If the server
+    /// requested a delay, it is used as-is instead, aside from being limited
+    /// to [`Self::max_delay`].
+    pub fn delay(&self, attempt: u8, retry_after: Option<Duration>) -> Duration {
+        if let Some(delay) = retry_after {
+            return delay.min(self.max_delay);
+        }
+
+        let factor = 1u32.checked_shl(attempt.into()).unwrap_or(u32::MAX);
+        let delay = self
+            .initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0) * random_fraction();
+
+        delay.mul_f64(1.0 - jitter)
+    }
+
+    /// Get the delay before the next attempt if the specified 0-based attempt
+    /// failed with `error` and should be retried.
+    pub fn retry_delay(&self, attempt: u8, error: &impl Classify) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+
+        match error.retryability() {
+            Retryability::Retryable { retry_after } => Some(self.delay(attempt, retry_after)),
+            Retryability::Fatal => None,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_RETRIES,
+            initial_delay: DEFAULT_INITIAL_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            jitter: DEFAULT_JITTER,
+        }
+    }
+}
+
+/// Get a random number in the range [0, 1).
+fn random_fraction() -> f64 {
+    let mut buf = [0u8; 4];
+
+    // Failing to get randomness only means that the delay isn't jittered.
+    if SystemRandom::new().fill(&mut buf).is_err() {
+        return 0.0;
+    }
+
+    f64::from(u32::from_le_bytes(buf)) / (f64::from(u32::MAX) + 1.0)
+}
+
+/// Parse the `Retry-After` header, which contains either a number of seconds
+/// or an HTTP date.
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let timestamp = DateTimeParser::new().parse_timestamp(value).ok()?;
+    let duration = timestamp.duration_since(Timestamp::now());
+
+    // Dates in the past mean that the request can be retried immediately.
+    Some(duration.try_into().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestError(Retryability);
+
+    impl Classify for TestError {
+        fn retryability(&self) -> Retryability {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+            jitter: 0.0,
+        };
+        let retryable = TestError(Retryability::Retryable { retry_after: None });
+
+        assert_eq!(
+            (0..4)
+                .map(|a| policy.retry_delay(a, &retryable).unwrap().as_secs())
+                .collect::<Vec<_>>(),
+            [1, 2, 4, 5],
+        );
+        assert_eq!(policy.delay(255, None), Duration::from_secs(5));
+        assert_eq!(policy.retry_delay(10, &retryable), None);
+        assert_eq!(policy.retry_delay(0, &TestError(Retryability::Fatal)), None);
+        assert_eq!(RetryPolicy::none().retry_delay(0, &retryable), None);
+
+        let throttled = TestError(Retryability::Retryable {
+            retry_after: Some(Duration::from_secs(3)),
+        });
+        assert_eq!(
+            policy.retry_delay(3, &throttled),
+            Some(Duration::from_secs(3)),
+        );
+
+        let jittered = RetryPolicy {
+            jitter: 0.5,
+            ..policy
+        };
+        for _ in 0..16 {
+            let delay = jittered.delay(1, None);
+            assert!(
+                delay > Duration::from_secs(1) && delay <= Duration::from_secs(2),
+                "{delay:?}",
+            );
+        }
+
+        assert!(Retryability::from_status(StatusCode::BAD_GATEWAY, None).is_retryable());
+        assert!(Retryability::from_status(StatusCode::TOO_MANY_REQUESTS, None).is_retryable());
+        assert!(!Retryability::from_status(StatusCode::NOT_FOUND, None).is_retryable());
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+        headers.insert(header::RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(120)));
+        headers.insert(
+            header::RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(parse_retry_after(&headers), Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,219 @@
+// SPDX-FileCopyrightText: 2024-2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Selection of a single car from the list returned by the server. Multiple
+//! cars may share a model ID, name, or version, so selectors can be combined.
+
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::client::CarInfo;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("{}", no_match_message(.selectors, .alternatives))]
+    NoMatch {
+        selectors: Vec<Selector>,
+        /// For each selector that matches cars on its own, the remaining
+        /// selectors for each of those cars.
+        alternatives: Vec<(Selector, Vec<Vec<Selector>>)>,
+    },
+    #[error("{}", ambiguous_message(.selectors, .candidates))]
+    Ambiguous {
+        selectors: Vec<Selector>,
+        /// Additional selectors that would narrow the match down to each of
+        /// the candidates.
+        candidates: Vec<Vec<Selector>>,
+    },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+fn join(selectors: &[Selector]) -> String {
+    selectors
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn no_match_message(
+    selectors: &[Selector],
+    alternatives: &[(Selector, Vec<Vec<Selector>>)],
+) -> String {
+    let mut msg = format!(
+        "No firmware versions found matching selector: {}",
+        join(selectors),
+    );
+
+    for (selector, cars) in alternatives {
+        msg.push_str(&format!("\n\nAvailable options for just: {selector}"));
+
+        for car in cars {
+            msg.push_str(&format!("\n  {}", join(car)));
+        }
+    }
+
+    msg
+}
+
+fn ambiguous_message(selectors: &[Selector], candidates: &[Vec<Selector>]) -> String {
+    let mut msg = format!(
+        "Multiple firmware versions found matching selector: {}\n",
+        join(selectors),
+    );
+    msg.push_str("To disambiguate, rerun with one of the following:\n");
+
+    for car in candidates {
+        msg.push_str(&format!("\n  {}", join(car)));
+    }
+
+    msg
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Selector {
+    Model(String),
+    Name(String),
+    Version(String),
+}
+
+impl Selector {
+    /// Get every selector that matches the car.
+    pub fn all_for_car(car: &CarInfo) -> Vec<Self> {
+        let mut result = vec![Self::Model(car.id.clone()), Self::Name(car.name.clone())];
+
+        result.extend(car.versions.iter().cloned().map(Self::Version));
+
+        result
+    }
+
+    pub fn matches_car(&self, car: &CarInfo) -> bool {
+        match self {
+            Self::Model(m) => car.id == *m,
+            Self::Name(n) => car.name == *n,
+            Self::Version(v) => car.versions.contains(v),
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Model(m) => write!(f, "-m {m}"),
+            Self::Name(n) => write!(f, "-n \"{n}\""),
+            Self::Version(v) => write!(f, "-v {v}"),
+        }
+    }
+}
+
+/// Find the single car that matches all of the selectors.
+pub fn select_car<'a>(cars: &'a [CarInfo], selectors: &[Selector]) -> Result<&'a CarInfo> {
+    let candidates: Vec<_> = cars
+        .iter()
+        .filter(|c| selectors.iter().all(|s| s.matches_car(c)))
+        .collect();
+
+    match candidates.as_slice() {
+        [] => {
+            let alternatives = selectors
+                .iter()
+                .map(|selector| {
+                    let cars = cars
+                        .iter()
+                        .filter(|c| selector.matches_car(c))
+                        .map(|c| {
+                            Selector::all_for_car(c)
+                                .into_iter()
+                                .filter(|s| s != selector)
+                                .collect()
+                        })
+                        .collect::<Vec<_>>();
+
+                    (selector.clone(), cars)
+                })
+                .filter(|(_, cars)| !cars.is_empty())
+                .collect();
+
+            Err(Error::NoMatch {
+                selectors: selectors.to_vec(),
+                alternatives,
+            })
+        }
+        [car] => Ok(car),
+        _ => {
+            let candidates = candidates
+                .into_iter()
+                .map(|c| {
+                    Selector::all_for_car(c)
+                        .into_iter()
+                        .filter(|s| !selectors.contains(s))
+                        .collect()
+                })
+                .collect();
+
+            Err(Error::Ambiguous {
+                selectors: selectors.to_vec(),
+                candidates,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{Brand, BrandInfo};
+
+    fn car(id: &str, name: &str, versions: &[&str]) -> CarInfo {
+        CarInfo {
+            brand: BrandInfo::Known(Brand::Kia),
+            id: id.to_owned(),
+            code: String::new(),
+            model: name.to_owned(),
+            name: name.to_owned(),
+            versions: versions.iter().map(|v| (*v).to_owned()).collect(),
+            media_type: "USB".to_owned(),
+            mcode: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_select_car() {
+        let cars = [
+            car("CAR1", "2026 Car", &["V1"]),
+            car("CAR2", "2026 Car", &["V2", "V3"]),
+        ];
+        let model = |m: &str| Selector::Model(m.to_owned());
+        let name = |n: &str| Selector::Name(n.to_owned());
+        let version = |v: &str| Selector::Version(v.to_owned());
+
+        assert_eq!(select_car(&cars, &[model("CAR2")]).unwrap().id, "CAR2");
+        assert_eq!(select_car(&cars, &[version("V1")]).unwrap().id, "CAR1");
+
+        let err = select_car(&cars, &[name("2026 Car")]).unwrap_err();
+        let Error::Ambiguous { candidates, .. } = &err else {
+            panic!("Unexpected error: {err:?}");
+        };
+        assert_eq!(candidates[0], [model("CAR1"), version("V1")]);
+        assert!(err.to_string().contains("\n  -m CAR2 -v V2 -v V3"), "{err}");
+
+        let err = select_car(&cars, &[model("CAR1"), version("V2")]).unwrap_err();
+        let Error::NoMatch { alternatives, .. } = &err else {
+            panic!("Unexpected error: {err:?}");
+        };
+        assert_eq!(alternatives.len(), 2);
+        assert_eq!(alternatives[1].0, version("V2"));
+        assert_eq!(
+            alternatives[1].1,
+            [vec![model("CAR2"), name("2026 Car"), version("V3")]],
+        );
+        assert!(
+            err.to_string()
+                .contains("Available options for just: -m CAR1\n  -n \"2026 Car\" -v V1"),
+            "{err}",
+        );
+    }
+}
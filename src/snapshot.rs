@@ -0,0 +1,483 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Local store of catalog snapshots. The server only provides the latest
+//! firmware, so snapshots are the only way to find out what changed. Snapshots
+//! are stored as `<store>/<brand>/<region>/<timestamp>.json`.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    client::{self, Brand, CarInfo, FirmwareInfo, NuClient},
+    model::{CarDownloadData, CarListData},
+};
+
+const SNAPSHOT_EXT: &str = "json";
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Failed to create directory: {0:?}")]
+    CreateDir(PathBuf, #[source] io::Error),
+    #[error("Failed to list directory: {0:?}")]
+    ListDir(PathBuf, #[source] io::Error),
+    #[error("Failed to read snapshot: {0:?}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("Failed to write snapshot: {0:?}")]
+    Write(PathBuf, #[source] io::Error),
+    #[error("Failed to parse snapshot: {0:?}")]
+    Parse(PathBuf, #[source] serde_json::Error),
+    #[error("Failed to serialize snapshot")]
+    Serialize(#[source] serde_json::Error),
+    #[error("Snapshot already exists: {0:?}")]
+    Exists(PathBuf),
+    #[error("Invalid snapshot timestamp: {0:?}")]
+    InvalidTimestamp(String, #[source] jiff::Error),
+    #[error(transparent)]
+    Client(#[from] client::Error),
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Raw server data for every car of a brand in a region at a point in time.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    /// RFC 3339 timestamp of when the snapshot was captured.
+    pub timestamp: String,
+    /// Two character brand code.
+    pub brand: String,
+    pub region: String,
+    /// Data from the `/car/list` endpoint.
+    pub cars: CarListData,
+    /// Data from the `/car/download/<code>` endpoint, keyed by the download
+    /// code.
+    pub downloads: BTreeMap<String, CarDownloadData>,
+}
+
+impl Snapshot {
+    /// Capture the current data for every car from the server.
+    pub async fn capture(client: &NuClient, brand: Brand, region: &str) -> Result<Self> {
+        let timestamp = Timestamp::now();
+        let guid = client.get_guid(region).await?;
+        let cars = client
+            .get_cars_raw(region, &guid, brand.as_code_str())
+            .await?;
+        let mut downloads = BTreeMap::new();
+
+        for car in &cars.cars {
+            let data = client
+                .get_firmware_info_raw(region, &car.download_code)
+                .await?;
+
+            downloads.insert(car.download_code.clone(), data);
+        }
+
+        Ok(Self {
+            timestamp: timestamp.to_string(),
+            brand: brand.as_code_str().to_owned(),
+            region: region.to_owned(),
+            cars,
+            downloads,
+        })
+    }
+}
+
+/// Directory containing snapshots for all brands and regions.
+pub struct SnapshotStore {
+    root: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn dir(&self, brand: &str, region: &str) -> PathBuf {
+        self.root.join(brand).join(region)
+    }
+
+    /// Save a snapshot and return its path.
+    pub fn save(&self, snapshot: &Snapshot) -> Result<PathBuf> {
+        let dir = self.dir(&snapshot.brand, &snapshot.region);
+        fs::create_dir_all(&dir).map_err(|e| Error::CreateDir(dir.clone(), e))?;
+
+        let timestamp = snapshot
+            .timestamp
+            .parse::<Timestamp>()
+            .map_err(|e| Error::InvalidTimestamp(snapshot.timestamp.clone(), e))?;
+        let name = format!("{}.{SNAPSHOT_EXT}", timestamp.strftime("%Y%m%dT%H%M%SZ"));
+        let path = dir.join(name);
+
+        if path.exists() {
+            return Err(Error::Exists(path));
+        }
+
+        let data = serde_json::to_vec(snapshot).map_err(Error::Serialize)?;
+
+        // Write to a temporary file first so that interrupted writes never
+        // leave behind a truncated snapshot.
+        let temp_path = path.with_extension(format!("{SNAPSHOT_EXT}.tmp"));
+        fs::write(&temp_path, data).map_err(|e| Error::Write(temp_path.clone(), e))?;
+        fs::rename(&temp_path, &path).map_err(|e| Error::Write(path.clone(), e))?;
+
+        Ok(path)
+    }
+
+    /// List the paths of all snapshots for a brand and region, from oldest to
+    /// newest.
+    pub fn list(&self, brand: &str, region: &str) -> Result<Vec<PathBuf>> {
+        let dir = self.dir(brand, region);
+        let entries = match fs::read_dir(&dir) {
+            Ok(e) => e,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(Error::ListDir(dir, e)),
+        };
+        let mut paths = vec![];
+
+        for entry in entries {
+            let path = entry.map_err(|e| Error::ListDir(dir.clone(), e))?.path();
+
+            if path.extension().is_some_and(|e| e == SNAPSHOT_EXT) {
+                paths.push(path);
+            }
+        }
+
+        // The file names are timestamps that sort chronologically.
+        paths.sort();
+
+        Ok(paths)
+    }
+
+    /// List all regions that have snapshots for a brand.
+    pub fn regions(&self, brand: &str) -> Result<Vec<String>> {
+        let dir = self.root.join(brand);
+        let entries = match fs::read_dir(&dir) {
+            Ok(e) => e,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(Error::ListDir(dir, e)),
+        };
+        let mut regions = vec![];
+
+        for entry in entries {
+            let entry = entry.map_err(|e| Error::ListDir(dir.clone(), e))?;
+
+            if entry.path().is_dir()
+                && let Ok(name) = entry.file_name().into_string()
+            {
+                regions.push(name);
+            }
+        }
+
+        regions.sort();
+
+        Ok(regions)
+    }
+
+    pub fn load(path: &Path) -> Result<Snapshot> {
+        let data = fs::read(path).map_err(|e| Error::Read(path.to_owned(), e))?;
+
+        serde_json::from_slice(&data).map_err(|e| Error::Parse(path.to_owned(), e))
+    }
+}
+
+/// Difference between two snapshots.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Change {
+    ModelAdded {
+        model: String,
+        name: String,
+        versions: Vec<String>,
+    },
+    ModelRemoved {
+        model: String,
+        name: String,
+    },
+    VersionChanged {
+        model: String,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    FileAdded {
+        model: String,
+        path: String,
+        crc32: u32,
+        size: u64,
+    },
+    FileRemoved {
+        model: String,
+        path: String,
+    },
+    FileChanged {
+        model: String,
+        path: String,
+        old_crc32: u32,
+        new_crc32: u32,
+        old_size: u64,
+        new_size: u64,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ModelAdded {
+                model,
+                name,
+                versions,
+            } => write!(
+                f,
+                "Model added: {model} \"{name}\" ({})",
+                versions.join(", "),
+            ),
+            Self::ModelRemoved { model, name } => {
+                write!(f, "Model removed: {model} \"{name}\"")
+            }
+            Self::VersionChanged { model, old, new } => write!(
+                f,
+                "Version changed: {model}: {} -> {}",
+                old.join(", "),
+                new.join(", "),
+            ),
+            Self::FileAdded {
+                model,
+                path,
+                crc32,
+                size,
+            } => write!(f, "File added: {model}: {path} ({crc32:08X}, {size} bytes)"),
+            Self::FileRemoved { model, path } => write!(f, "File removed: {model}: {path}"),
+            Self::FileChanged {
+                model,
+                path,
+                old_crc32,
+                new_crc32,
+                old_size,
+                new_size,
+            } => write!(
+                f,
+                "File changed: {model}: {path} ({old_crc32:08X}, {old_size} bytes -> \
+                {new_crc32:08X}, {new_size} bytes)",
+            ),
+        }
+    }
+}
+
+struct ModelSummary {
+    name: String,
+    versions: Vec<String>,
+    /// CRC32 and size by path.
+    files: BTreeMap<String, (u32, u64)>,
+}
+
+/// Summarize each model in a snapshot, keyed by the model ID.
+fn summarize(snapshot: Snapshot) -> Result<BTreeMap<String, ModelSummary>> {
+    let mut downloads = snapshot.downloads;
+    let mut result = BTreeMap::new();
+
+    for car in snapshot.cars.cars {
+        let download = downloads.remove(&car.download_code);
+        let car = CarInfo::try_from(car)?;
+
+        let files = match download {
+            Some(d) => FirmwareInfo::try_from(d)?
+                .files
+                .iter()
+                .map(|f| (f.path(), (f.crc32, f.size)))
+                .collect(),
+            None => BTreeMap::new(),
+        };
+
+        result.insert(
+            car.id,
+            ModelSummary {
+                name: car.name,
+                versions: car.versions,
+                files,
+            },
+        );
+    }
+
+    Ok(result)
+}
+
+/// Compute the changes from the `old` snapshot to the `new` snapshot.
+pub fn diff(old: Snapshot, new: Snapshot) -> Result<Vec<Change>> {
+    let mut old = summarize(old)?;
+    let new = summarize(new)?;
+    let mut changes = vec![];
+
+    for (model, new_summary) in new {
+        let Some(old_summary) = old.remove(&model) else {
+            changes.push(Change::ModelAdded {
+                model,
+                name: new_summary.name,
+                versions: new_summary.versions,
+            });
+            continue;
+        };
+
+        if old_summary.versions != new_summary.versions {
+            changes.push(Change::VersionChanged {
+                model: model.clone(),
+                old: old_summary.versions,
+                new: new_summary.versions,
+            });
+        }
+
+        let mut old_files = old_summary.files;
+
+        for (path, (crc32, size)) in new_summary.files {
+            match old_files.remove(&path) {
+                None => changes.push(Change::FileAdded {
+                    model: model.clone(),
+                    path,
+                    crc32,
+                    size,
+                }),
+                Some((old_crc32, old_size)) if old_crc32 != crc32 || old_size != size => {
+                    changes.push(Change::FileChanged {
+                        model: model.clone(),
+                        path,
+                        old_crc32,
+                        new_crc32: crc32,
+                        old_size,
+                        new_size: size,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+
+        for path in old_files.into_keys() {
+            changes.push(Change::FileRemoved {
+                model: model.clone(),
+                path,
+            });
+        }
+    }
+
+    for (model, old_summary) in old {
+        changes.push(Change::ModelRemoved {
+            model,
+            name: old_summary.name,
+        });
+    }
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockCar, MockFile, MockServer, TempDir, mock_car, test_data};
+
+    async fn capture(cars: Vec<MockCar>) -> Snapshot {
+        let server = MockServer::start(cars).await;
+
+        Snapshot::capture(&server.client(), Brand::Kia, "US")
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_changes() {
+        let car = |id: &str, version: &str, files| MockCar {
+            id: id.to_owned(),
+            versions: vec![version.to_owned()],
+            files,
+            ..mock_car()
+        };
+
+        let old = capture(vec![
+            car(
+                "CAR1",
+                "CAR1.USA.S5W_L.001.001.260101",
+                vec![
+                    MockFile::new("a.bin", test_data(16, 1), 0),
+                    MockFile::new("b.bin", test_data(16, 2), 0),
+                ],
+            ),
+            car(
+                "CAR2",
+                "CAR2.USA.S5W_L.001.001.260101",
+                vec![MockFile::new("a.bin", test_data(16, 1), 0)],
+            ),
+        ])
+        .await;
+        let new = capture(vec![
+            car(
+                "CAR1",
+                "CAR1.USA.S5W_L.001.002.260201",
+                vec![
+                    MockFile::new("a.bin", test_data(16, 3), 0),
+                    MockFile::new("c.bin", test_data(16, 4), 0),
+                ],
+            ),
+            car(
+                "CAR3",
+                "CAR3.USA.S5W_L.001.001.260201",
+                vec![MockFile::new("a.bin", test_data(16, 1), 0)],
+            ),
+        ])
+        .await;
+
+        let temp_dir = TempDir::new();
+        let store = SnapshotStore::new(temp_dir.path().to_owned());
+        let old_path = store.save(&old).unwrap();
+        assert!(matches!(store.save(&old), Err(Error::Exists(_))));
+        assert_eq!(
+            store.list("KM", "US").unwrap(),
+            std::slice::from_ref(&old_path)
+        );
+        assert_eq!(store.regions("KM").unwrap(), ["US"]);
+
+        let old = SnapshotStore::load(&old_path).unwrap();
+        let changes = diff(old, new).unwrap();
+
+        assert_eq!(
+            changes,
+            [
+                Change::VersionChanged {
+                    model: "CAR1".to_owned(),
+                    old: vec!["CAR1.USA.S5W_L.001.001.260101".to_owned()],
+                    new: vec!["CAR1.USA.S5W_L.001.002.260201".to_owned()],
+                },
+                Change::FileChanged {
+                    model: "CAR1".to_owned(),
+                    path: "a.bin".to_owned(),
+                    old_crc32: crc32fast::hash(&test_data(16, 1)),
+                    new_crc32: crc32fast::hash(&test_data(16, 3)),
+                    old_size: 16,
+                    new_size: 16,
+                },
+                Change::FileAdded {
+                    model: "CAR1".to_owned(),
+                    path: "c.bin".to_owned(),
+                    crc32: crc32fast::hash(&test_data(16, 4)),
+                    size: 16,
+                },
+                Change::FileRemoved {
+                    model: "CAR1".to_owned(),
+                    path: "b.bin".to_owned(),
+                },
+                Change::ModelAdded {
+                    model: "CAR3".to_owned(),
+                    name: "2026 Car".to_owned(),
+                    versions: vec!["CAR3.USA.S5W_L.001.001.260201".to_owned()],
+                },
+                Change::ModelRemoved {
+                    model: "CAR2".to_owned(),
+                    name: "2026 Car".to_owned(),
+                },
+            ],
+        );
+    }
+}
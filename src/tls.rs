@@ -0,0 +1,448 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Custom TLS trust anchors and public key pinning. Pins use the same
+//! `sha256//<base64>` format as curl's `--pinnedpubkey`, which is the SHA-256
+//! digest of the DER-encoded SubjectPublicKeyInfo.
+
+use std::{
+    collections::BTreeSet,
+    error, fmt, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use ring::digest::{SHA256, SHA256_OUTPUT_LEN, digest};
+use rustls::{
+    CertificateError, DigitallySignedStruct, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    pki_types::{CertificateDer, ServerName, UnixTime, pem::PemObject},
+};
+use thiserror::Error;
+use tracing::error;
+
+const PIN_PREFIX: &str = "sha256//";
+
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_VERSION: u8 = 0xa0;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Expected {PIN_PREFIX}<base64 SHA-256 digest>: {0:?}")]
+    InvalidPin(String),
+    #[error("Failed to load certificates: {0:?}")]
+    LoadCerts(PathBuf, #[source] rustls::pki_types::pem::Error),
+    #[error("No certificates found: {0:?}")]
+    NoCerts(PathBuf),
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Load all certificates from a PEM file.
+pub fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| Error::LoadCerts(path.to_owned(), e))?;
+
+    if certs.is_empty() {
+        return Err(Error::NoCerts(path.to_owned()));
+    }
+
+    Ok(certs)
+}
+
+/// SHA-256 digest of a certificate's SubjectPublicKeyInfo.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SpkiPin([u8; SHA256_OUTPUT_LEN]);
+
+impl SpkiPin {
+    /// Compute the pin for a DER-encoded X.509 certificate. Returns [`None`]
+    /// if the certificate cannot be parsed.
+    pub fn from_cert(cert: &[u8]) -> Option<Self> {
+        let spki = subject_public_key_info(cert)?;
+        let hash = digest(&SHA256, spki);
+
+        Some(Self(hash.as_ref().try_into().unwrap()))
+    }
+}
+
+impl fmt::Debug for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl fmt::Display for SpkiPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{PIN_PREFIX}{}", STANDARD.encode(self.0))
+    }
+}
+
+impl FromStr for SpkiPin {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.strip_prefix(PIN_PREFIX)
+            .and_then(|d| STANDARD.decode(d).ok())
+            .and_then(|d| d.try_into().ok())
+            .map(Self)
+            .ok_or_else(|| Error::InvalidPin(s.to_owned()))
+    }
+}
+
+/// A DER-encoded tag-length-value.
+struct Tlv<'a> {
+    tag: u8,
+    /// The entire TLV, including the header.
+    raw: &'a [u8],
+    value: &'a [u8],
+    /// Data following the TLV.
+    rest: &'a [u8],
+}
+
+impl<'a> Tlv<'a> {
+    fn read(data: &'a [u8]) -> Option<Self> {
+        let (&tag, rest) = data.split_first()?;
+        let (&first, mut rest) = rest.split_first()?;
+
+        let len = if first & 0x80 == 0 {
+            usize::from(first)
+        } else {
+            let n = usize::from(first & 0x7f);
+            if n == 0 || n > size_of::<usize>() {
+                return None;
+            }
+
+            let (bytes, r) = rest.split_at_checked(n)?;
+            rest = r;
+
+            bytes.iter().fold(0, |acc, b| (acc << 8) | usize::from(*b))
+        };
+
+        let header_len = data.len() - rest.len();
+        let (value, rest) = rest.split_at_checked(len)?;
+
+        Some(Self {
+            tag,
+            raw: &data[..header_len + len],
+            value,
+            rest,
+        })
+    }
+
+    fn read_sequence(data: &'a [u8]) -> Option<Self> {
+        Self::read(data).filter(|t| t.tag == TAG_SEQUENCE)
+    }
+}
+
+/// Find the DER-encoded SubjectPublicKeyInfo in an X.509 certificate. This
+/// only walks the structure far enough to find the field and does not
+/// validate anything else.
+fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+    let cert = Tlv::read_sequence(cert)?;
+    let mut tbs = Tlv::read_sequence(cert.value)?.value;
+
+    // The version is optional.
+    let first = Tlv::read(tbs)?;
+    if first.tag == TAG_VERSION {
+        tbs = first.rest;
+    }
+
+    // Skip serialNumber, signature, issuer, validity, and subject.
+    for _ in 0..5 {
+        tbs = Tlv::read(tbs)?.rest;
+    }
+
+    Tlv::read_sequence(tbs).map(|t| t.raw)
+}
+
+/// Check if an error was caused by a server certificate that failed validation
+/// or pinning.
+pub(crate) fn is_certificate_error(error: &(dyn error::Error + 'static)) -> bool {
+    let mut source = Some(error);
+
+    while let Some(e) = source {
+        if let Some(rustls::Error::InvalidCertificate(_)) = e.downcast_ref::<rustls::Error>() {
+            return true;
+        }
+
+        // io::Error::source() skips the wrapped error itself.
+        if let Some(inner) = e.downcast_ref::<io::Error>().and_then(|e| e.get_ref())
+            && is_certificate_error(inner)
+        {
+            return true;
+        }
+
+        source = e.source();
+    }
+
+    false
+}
+
+/// Certificate verifier that additionally requires the end-entity certificate
+/// to match one of the pinned public keys for the specified hosts. Like curl's
+/// `--pinnedpubkey`, intermediates are never checked because the server can
+/// send any certificates it wants, including a real, publicly available
+/// intermediate alongside a forged leaf. Other hosts are only checked by the
+/// inner verifier.
+#[derive(Debug)]
+pub struct PinningVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    hosts: BTreeSet<String>,
+    pins: Vec<SpkiPin>,
+}
+
+impl PinningVerifier {
+    pub fn new(
+        inner: Arc<dyn ServerCertVerifier>,
+        hosts: BTreeSet<String>,
+        pins: Vec<SpkiPin>,
+    ) -> Self {
+        let hosts = hosts.into_iter().map(|h| h.to_ascii_lowercase()).collect();
+
+        Self { inner, hosts, pins }
+    }
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        let ServerName::DnsName(host) = server_name else {
+            return Ok(verified);
+        };
+        let host = host.as_ref().to_ascii_lowercase();
+
+        if !self.hosts.contains(&host) {
+            return Ok(verified);
+        }
+
+        let matched = SpkiPin::from_cert(end_entity).is_some_and(|p| self.pins.contains(&p));
+
+        if !matched {
+            error!("No certificate matches the pinned public keys: {host}");
+            return Err(CertificateError::ApplicationVerificationFailure.into());
+        }
+
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use super::*;
+
+    /// Build a DER TLV with a short length.
+    fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut result = vec![tag, value.len() as u8];
+        result.extend_from_slice(value);
+        result
+    }
+
+    /// Build a minimal certificate. Returns the certificate and its
+    /// SubjectPublicKeyInfo.
+    fn test_cert(key: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let spki = tlv(TAG_SEQUENCE, &tlv(0x03, &[b"\x00", key].concat()));
+        let tbs = [
+            tlv(TAG_VERSION, &tlv(0x02, b"\x02")),
+            tlv(0x02, b"\x01"),
+            tlv(TAG_SEQUENCE, b""),
+            tlv(TAG_SEQUENCE, b""),
+            tlv(TAG_SEQUENCE, b""),
+            tlv(TAG_SEQUENCE, b""),
+            spki.clone(),
+        ]
+        .concat();
+        let cert = tlv(
+            TAG_SEQUENCE,
+            &[tlv(TAG_SEQUENCE, &tbs), tlv(TAG_SEQUENCE, b"")].concat(),
+        );
+
+        (cert, spki)
+    }
+
+    /// Verifier that accepts every certificate, so that only the pinning is
+    /// tested.
+    #[derive(Debug)]
+    struct AcceptAll;
+
+    impl ServerCertVerifier for AcceptAll {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_spki_pin() {
+        let (cert, spki) = test_cert(b"key");
+
+        assert_eq!(subject_public_key_info(&cert), Some(spki.as_slice()));
+        assert_eq!(subject_public_key_info(&cert[..cert.len() - 1]), None);
+
+        let pin = SpkiPin::from_cert(&cert).unwrap();
+        let expected = SpkiPin(digest(&SHA256, &spki).as_ref().try_into().unwrap());
+        assert_eq!(pin, expected);
+        assert_eq!(pin.to_string().parse::<SpkiPin>().unwrap(), pin);
+
+        assert!(matches!(
+            "sha256//AAAA".parse::<SpkiPin>(),
+            Err(Error::InvalidPin(_)),
+        ));
+        assert!(matches!(
+            "sha1//AAAA".parse::<SpkiPin>(),
+            Err(Error::InvalidPin(_)),
+        ));
+    }
+
+    #[test]
+    fn test_tlv_lengths() {
+        // Long form length.
+        let data = [&[0x04, 0x81, 0x80][..], &[0u8; 0x80]].concat();
+        let t = Tlv::read(&data).unwrap();
+        assert_eq!(t.value.len(), 0x80);
+        assert_eq!(t.raw.len(), data.len());
+
+        // Missing header bytes.
+        assert!(Tlv::read(&[]).is_none());
+        assert!(Tlv::read(&[0x04]).is_none());
+        assert!(Tlv::read(&[0x04, 0x82, 0x01]).is_none());
+        // Value shorter than the length.
+        assert!(Tlv::read(&[0x04, 0x03, 0x00, 0x00]).is_none());
+        assert!(Tlv::read(&data[..data.len() - 1]).is_none());
+        // Indefinite length is not valid DER.
+        assert!(Tlv::read(&[0x04, 0x80, 0x00, 0x00]).is_none());
+        // Length does not fit in a usize.
+        assert!(Tlv::read(&[&[0x04, 0x89][..], &[0xff; 9]].concat()).is_none());
+        // Length is larger than the data.
+        assert!(Tlv::read(&[&[0x04, 0x88][..], &[0xff; 8]].concat()).is_none());
+    }
+
+    #[test]
+    fn test_pinning_verifier() {
+        let (end_entity, _) = test_cert(b"leaf");
+        let (intermediate, _) = test_cert(b"intermediate");
+        let end_entity = CertificateDer::from(end_entity);
+        let intermediate = CertificateDer::from(intermediate);
+        let leaf_pin = SpkiPin::from_cert(&end_entity).unwrap();
+        let intermediate_pin = SpkiPin::from_cert(&intermediate).unwrap();
+        let (other, _) = test_cert(b"other");
+        let other_pin = SpkiPin::from_cert(&other).unwrap();
+
+        let verify = |pins: Vec<SpkiPin>, host: &str, intermediates: &[CertificateDer<'_>]| {
+            let verifier = PinningVerifier::new(
+                Arc::new(AcceptAll),
+                BTreeSet::from(["API.example.com".to_owned()]),
+                pins,
+            );
+
+            verifier.verify_server_cert(
+                &end_entity,
+                intermediates,
+                &ServerName::try_from(host.to_owned()).unwrap(),
+                &[],
+                UnixTime::now(),
+            )
+        };
+
+        verify(vec![other_pin, leaf_pin], "api.example.com", &[]).unwrap();
+        verify(
+            vec![leaf_pin],
+            "api.example.com",
+            slice::from_ref(&intermediate),
+        )
+        .unwrap();
+
+        // A pinned intermediate does not vouch for a leaf that doesn't match,
+        // since anyone can append a public intermediate to a forged leaf.
+        let err = verify(
+            vec![intermediate_pin],
+            "api.example.com",
+            slice::from_ref(&intermediate),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure),
+        ));
+        assert!(verify(vec![other_pin], "api.example.com", &[]).is_err());
+        assert!(verify(vec![], "api.example.com", &[]).is_err());
+
+        // Failures are detected through the layers of wrapping in reqwest.
+        let wrapped = io::Error::other(io::Error::other(err));
+        assert!(is_certificate_error(&wrapped));
+        assert!(!is_certificate_error(&io::Error::other("Connection reset")));
+
+        // Other hosts are not pinned.
+        verify(vec![other_pin], "download.example.com", &[]).unwrap();
+        verify(vec![other_pin], "127.0.0.1", &[]).unwrap();
+    }
+}
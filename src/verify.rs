@@ -9,6 +9,7 @@ use std::{
 
 use cap_std::fs::Dir;
 use crc32fast::Hasher;
+use serde::Serialize;
 use thiserror::Error;
 use tokio::{
     sync::mpsc::{self, error::SendError},
@@ -18,11 +19,13 @@ use tracing::{debug, error};
 
 use crate::{
     cancel::{CancelOnDrop, check_cancel},
+    client::{CarInfo, FirmwareInfo},
     progress::{THROTTLE_DELAY, ThrottledProgress},
     version::{self, VersionEntry, VersionInfo},
 };
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum Error {
     #[error("Failed to list directory")]
     ListDir(#[source] io::Error),
@@ -46,8 +49,12 @@ pub enum Error {
         actual: u32,
         expected: u32,
     },
-    #[error("Verification failed")]
-    Failed,
+    #[error("Verification failed for {} files", .0.len())]
+    Failed(Vec<String>),
+    #[error("Failed to delete file: {0:?}")]
+    DeleteFile(String, #[source] io::Error),
+    #[error("Firmware on the server does not match the .ver file")]
+    FirmwareMismatch,
     #[error(transparent)]
     Progress(SendError<ProgressMessage>),
     #[error(transparent)]
@@ -58,9 +65,26 @@ pub enum Error {
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+/// Progress events. Files are identified by their index in the `.ver` file.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
 pub enum ProgressMessage {
-    Total(u64),
-    Progress(u64),
+    /// Total size of all files.
+    Total {
+        bytes: u64,
+    },
+    Progress {
+        file_index: usize,
+        bytes: u64,
+    },
+    FileStarted {
+        file_index: usize,
+        path: String,
+    },
+    FileCompleted {
+        file_index: usize,
+        success: bool,
+    },
 }
 
 pub struct Verifier {
@@ -82,7 +106,7 @@ impl Verifier {
         (result, progress_rx)
     }
 
-    fn read_version_file(directory: &Dir) -> Result<VersionInfo> {
+    pub fn read_version_file(directory: &Dir) -> Result<VersionInfo> {
         let mut ver_file = None;
 
         for entry in directory.entries().map_err(Error::ListDir)? {
@@ -113,6 +137,7 @@ impl Verifier {
 
     fn verify_entry(
         directory: &Dir,
+        file_index: usize,
         entry: &VersionEntry,
         progress_tx: mpsc::Sender<ProgressMessage>,
         cancel_signal: &AtomicBool,
@@ -139,8 +164,11 @@ impl Verifier {
         let mut hasher = Hasher::new();
         let mut buf = [0u8; 8192];
 
-        let mut progress =
-            ThrottledProgress::new(progress_tx, ProgressMessage::Progress, THROTTLE_DELAY);
+        let mut progress = ThrottledProgress::new(
+            progress_tx,
+            |bytes| ProgressMessage::Progress { file_index, bytes },
+            THROTTLE_DELAY,
+        );
 
         loop {
             check_cancel(cancel_signal).map_err(Error::Cancelled)?;
@@ -178,20 +206,24 @@ impl Verifier {
         directory: Arc<Dir>,
         entry: VersionEntry,
         progress_tx: mpsc::Sender<ProgressMessage>,
-    ) -> (usize, Result<()>) {
+    ) -> (usize, String, Result<()>) {
         let cancel_on_drop = CancelOnDrop::new();
         let cancel_signal = cancel_on_drop.handle();
 
+        let path = entry.path().into_owned();
+
         let result = task::spawn_blocking(move || {
-            Self::verify_entry(&directory, &entry, progress_tx, &cancel_signal)
+            Self::verify_entry(&directory, task_id, &entry, progress_tx, &cancel_signal)
         })
         .await
         .map_err(Error::Panic)
         .flatten();
 
-        (task_id, result)
+        (task_id, path, result)
     }
 
+    /// Verify all files listed in the `.ver` file. If any files are missing
+    /// or corrupt, [`Error::Failed`] is returned with the list of their paths.
     pub async fn verify(&self) -> Result<()> {
         // Read version info file. This is not cancellable because it's a
         // single read operation.
@@ -208,18 +240,14 @@ impl Verifier {
 
         // Report initial progress.
         self.progress_tx
-            .send(ProgressMessage::Total(total_size))
-            .await
-            .map_err(Error::Progress)?;
-        self.progress_tx
-            .send(ProgressMessage::Progress(0))
+            .send(ProgressMessage::Total { bytes: total_size })
             .await
             .map_err(Error::Progress)?;
 
         let mut tasks = JoinSet::new();
         let mut next_task_id = 0;
         let mut running = 0;
-        let mut failed = 0;
+        let mut failed = vec![];
 
         loop {
             while running < self.concurrency {
@@ -227,10 +255,18 @@ impl Verifier {
                     break;
                 };
 
+                // This is also the index of the entry in the .ver file.
                 let task_id = next_task_id;
                 next_task_id += 1;
 
                 debug!("[Verify#{task_id}] Task starting");
+                self.progress_tx
+                    .send(ProgressMessage::FileStarted {
+                        file_index: task_id,
+                        path: entry.path().into_owned(),
+                    })
+                    .await
+                    .map_err(Error::Progress)?;
                 running += 1;
                 tasks.spawn(Self::verify_entry_task(
                     task_id,
@@ -240,28 +276,219 @@ impl Verifier {
                 ));
             }
 
-            let (task_id, task_result) = match tasks.join_next().await {
+            let (task_id, path, task_result) = match tasks.join_next().await {
                 // All tasks exited.
                 None => break,
                 // Task panicked or cancelled.
                 Some(Err(e)) => return Err(Error::Panic(e)),
                 // Task completed.
-                Some(Ok((id, result))) => (id, result),
+                Some(Ok((id, path, result))) => (id, path, result),
             };
 
             debug!("[Verify#{task_id}] Task completed");
             running -= 1;
 
-            if let Err(e) = task_result {
-                error!("{:#}", anyhow::Error::from(e));
-                failed += 1;
-            }
+            let success = match task_result {
+                Ok(()) => true,
+                // Don't treat cancellation or internal errors as bad files.
+                Err(e @ (Error::Progress(_) | Error::Cancelled(_) | Error::Panic(_))) => {
+                    return Err(e);
+                }
+                Err(e) => {
+                    error!("{:#}", anyhow::Error::from(e));
+                    failed.push(path);
+                    false
+                }
+            };
+
+            self.progress_tx
+                .send(ProgressMessage::FileCompleted {
+                    file_index: task_id,
+                    success,
+                })
+                .await
+                .map_err(Error::Progress)?;
         }
 
-        if failed > 0 {
-            return Err(Error::Failed);
+        if !failed.is_empty() {
+            failed.sort();
+            return Err(Error::Failed(failed));
         }
 
         Ok(())
     }
+
+    /// Find the car that the `.ver` file was created for.
+    pub fn find_car<'a>(info: &VersionInfo, cars: &'a [CarInfo]) -> Option<&'a CarInfo> {
+        cars.iter().find(|c| {
+            c.id == info.header.id
+                && c.mcode == info.header.mcode
+                && c.versions.contains(&info.header.firmware_version)
+        })
+    }
+
+    /// Delete files that failed verification so that they are downloaded
+    /// again by a [`Downloader`](crate::download::Downloader). All other files
+    /// are skipped by the downloader.
+    ///
+    /// This fails without deleting anything if the server would not produce
+    /// the exact same files as the `.ver` file. Otherwise, the directory would
+    /// end up with a mix of old and new firmware files.
+    pub fn prepare_repair(
+        directory: &Dir,
+        info: &VersionInfo,
+        car: &CarInfo,
+        firmware: &FirmwareInfo,
+        paths: &[String],
+    ) -> Result<()> {
+        let file_key = |e: &VersionEntry| (e.path().into_owned(), e.crc32, e.size);
+        let mut local_files = info.entries.iter().map(file_key).collect::<Vec<_>>();
+        let mut remote_files = VersionInfo::new(car, firmware)
+            .entries
+            .iter()
+            .map(file_key)
+            .collect::<Vec<_>>();
+        local_files.sort();
+        remote_files.sort();
+
+        if local_files != remote_files {
+            return Err(Error::FirmwareMismatch);
+        }
+
+        for path in paths {
+            if let Err(e) = directory.remove_file(path)
+                && e.kind() != io::ErrorKind::NotFound
+            {
+                return Err(Error::DeleteFile(path.clone(), e));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, slice};
+
+    use super::*;
+    use crate::mock::{
+        MockCar, MockFile, MockServer, TempDir, download_options, drain_progress, mock_car,
+        test_data,
+    };
+
+    async fn verify(temp_dir: &TempDir) -> Result<()> {
+        let (verifier, progress_rx) = Verifier::new(temp_dir.open(), 2);
+        let progress = drain_progress(progress_rx);
+
+        let result = verifier.verify().await;
+        drop(verifier);
+        progress.await.unwrap();
+
+        result
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_verify() {
+        let car = MockCar {
+            files: vec![
+                MockFile::new("split.tar", test_data(8192, 1), 2),
+                MockFile::new("unsplit.bin", test_data(1024, 2), 0).with_directory("sub"),
+            ],
+            ..mock_car()
+        };
+        let server = MockServer::start(vec![car]).await;
+        let temp_dir = TempDir::new();
+
+        let options = download_options();
+
+        server.download(&temp_dir, options).await.unwrap();
+        verify(&temp_dir).await.unwrap();
+
+        // Corrupt data.
+        let path = temp_dir.path().join("sub/unsplit.bin");
+        let mut data = std::fs::read(&path).unwrap();
+        data[0] ^= 1;
+        std::fs::write(&path, &data).unwrap();
+
+        assert!(matches!(
+            verify(&temp_dir).await,
+            Err(Error::Failed(p)) if p == ["sub/unsplit.bin"],
+        ));
+
+        // Missing file.
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            verify(&temp_dir).await,
+            Err(Error::Failed(p)) if p == ["sub/unsplit.bin"],
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_repair() {
+        let car = MockCar {
+            files: vec![
+                MockFile::new("split.tar", test_data(8192, 1), 3),
+                MockFile::new("unsplit.bin", test_data(1024, 2), 0).with_directory("sub"),
+            ],
+            ..mock_car()
+        };
+        let server = MockServer::start(vec![car.clone()]).await;
+        let temp_dir = TempDir::new();
+
+        let options = download_options();
+
+        server.download(&temp_dir, options.clone()).await.unwrap();
+
+        let path = temp_dir.path().join("split.tar");
+        let mut data = std::fs::read(&path).unwrap();
+        data[100] ^= 1;
+        std::fs::write(&path, &data).unwrap();
+
+        let Err(Error::Failed(paths)) = verify(&temp_dir).await else {
+            panic!("Verification succeeded");
+        };
+        assert_eq!(paths, ["split.tar"]);
+
+        let directory = temp_dir.open();
+        let info = Verifier::read_version_file(&directory).unwrap();
+        let (car_info, mut firmware) = server.firmware().await;
+        assert_eq!(
+            Verifier::find_car(&info, slice::from_ref(&car_info)).map(|c| &c.id),
+            Some(&car_info.id),
+        );
+
+        // Nothing is deleted if the server has different firmware.
+        firmware.files[1].crc32 ^= 1;
+        assert!(matches!(
+            Verifier::prepare_repair(&directory, &info, &car_info, &firmware, &paths),
+            Err(Error::FirmwareMismatch),
+        ));
+        assert!(path.exists());
+        firmware.files[1].crc32 ^= 1;
+
+        Verifier::prepare_repair(&directory, &info, &car_info, &firmware, &paths).unwrap();
+        assert!(!path.exists());
+
+        let skip = server.requests().len();
+        server.download(&temp_dir, options).await.unwrap();
+        verify(&temp_dir).await.unwrap();
+
+        // Only the corrupt file was downloaded again.
+        let downloads = server.requests()[skip..]
+            .iter()
+            .filter(|r| r.contains(" /firmware/"))
+            .map(|r| r.split(' ').nth(1).unwrap().to_owned())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            downloads,
+            BTreeSet::from([
+                "/firmware/CAR1%2Fsplit.z01".to_owned(),
+                "/firmware/CAR1%2Fsplit.z02".to_owned(),
+                "/firmware/CAR1%2Fsplit.zip".to_owned(),
+            ]),
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), car.files[0].data);
+    }
 }
@@ -8,6 +8,7 @@ use thiserror::Error;
 use crate::client::{BrandInfo, CarInfo, FirmwareInfo};
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum Error {
     #[error("Version file is empty")]
     EmptyFile,
@@ -0,0 +1,296 @@
+// SPDX-FileCopyrightText: 2026 Andrew Gunnerson
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Sequential parsing of single-entry zip files, as produced by Info-ZIP's
+//! `zip -s`. Unlike [`rawzip`], this never seeks, so the input can be consumed
+//! while later splits are still being downloaded. Because the data is read in
+//! order, the split-relative offsets in the headers never need to be fixed up.
+
+use std::io::{self, Read};
+
+use thiserror::Error;
+
+const SIG_SPANNING: u32 = 0x08074b50;
+const SIG_SPANNING_SINGLE: u32 = 0x30304b50;
+const SIG_LOCAL_HEADER: u32 = 0x04034b50;
+const SIG_DATA_DESCRIPTOR: u32 = 0x08074b50;
+const SIG_CENTRAL_HEADER: u32 = 0x02014b50;
+const SIG_ZIP64_EOCD: u32 = 0x06064b50;
+const SIG_ZIP64_EOCD_LOCATOR: u32 = 0x07064b50;
+const SIG_EOCD: u32 = 0x06054b50;
+
+const FLAG_ENCRYPTED: u16 = 1 << 0;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+
+const EXTRA_ZIP64: u16 = 0x0001;
+
+pub const METHOD_STORE: u16 = 0;
+pub const METHOD_DEFLATE: u16 = 8;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid {0} signature: {1:08X}")]
+    BadSignature(&'static str, u32),
+    #[error("Encrypted zip entries are not supported")]
+    Encrypted,
+    #[error("Stored zip entry has unknown size")]
+    UnknownSize,
+    #[error("Unexpected zip entry: {0:?}")]
+    UnexpectedEntry(String),
+    #[error("Expected 1 zip entry, but have {0}")]
+    EntryCount(u64),
+    #[error("Zip trailer is too large")]
+    TrailerTooLarge,
+    #[error("Failed to read zip data")]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+fn read_u16(reader: &mut impl Read) -> Result<u16> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_vec(reader: &mut impl Read, len: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Find the data for an extra field with the specified ID.
+fn find_extra(extra: &[u8], id: u16) -> Option<&[u8]> {
+    let mut data = extra;
+
+    while data.len() >= 4 {
+        let field_id = u16::from_le_bytes([data[0], data[1]]);
+        let len = usize::from(u16::from_le_bytes([data[2], data[3]]));
+        let value = data.get(4..4 + len)?;
+
+        if field_id == id {
+            return Some(value);
+        }
+
+        data = &data[4 + len..];
+    }
+
+    None
+}
+
+/// Local file header for the first and only entry in a zip.
+#[derive(Debug)]
+pub struct LocalHeader {
+    pub name: String,
+    pub compression_method: u16,
+    /// CRC32 of the uncompressed data. This is only known if the sizes and
+    /// CRC32 are not stored in a data descriptor after the data.
+    pub crc32: Option<u32>,
+    /// Compressed size. This is only known if the sizes and CRC32 are not
+    /// stored in a data descriptor after the data.
+    pub compressed_size: Option<u64>,
+    /// Whether the sizes and CRC32 follow the data.
+    has_data_descriptor: bool,
+    /// Whether the data descriptor uses 64-bit sizes.
+    is_zip64: bool,
+}
+
+impl LocalHeader {
+    /// Read the local file header from the beginning of a (possibly split)
+    /// zip file. The reader will be positioned at the start of the entry data.
+    pub fn read(reader: &mut impl Read) -> Result<Self> {
+        let mut signature = read_u32(reader)?;
+        if signature == SIG_SPANNING || signature == SIG_SPANNING_SINGLE {
+            signature = read_u32(reader)?;
+        }
+        if signature != SIG_LOCAL_HEADER {
+            return Err(Error::BadSignature("local header", signature));
+        }
+
+        let _version = read_u16(reader)?;
+        let flags = read_u16(reader)?;
+        let compression_method = read_u16(reader)?;
+        let _mtime = read_u16(reader)?;
+        let _mdate = read_u16(reader)?;
+        let crc32 = read_u32(reader)?;
+        let compressed_size = read_u32(reader)?;
+        let uncompressed_size = read_u32(reader)?;
+        let name_len = read_u16(reader)?;
+        let extra_len = read_u16(reader)?;
+        let name = read_vec(reader, name_len.into())?;
+        let extra = read_vec(reader, extra_len.into())?;
+
+        if flags & FLAG_ENCRYPTED != 0 {
+            return Err(Error::Encrypted);
+        }
+
+        let name = String::from_utf8(name)
+            .map_err(|e| Error::UnexpectedEntry(String::from_utf8_lossy(e.as_bytes()).into()))?;
+        let has_data_descriptor = flags & FLAG_DATA_DESCRIPTOR != 0;
+        let zip64 = find_extra(&extra, EXTRA_ZIP64);
+
+        let compressed_size = if has_data_descriptor {
+            None
+        } else if compressed_size == u32::MAX {
+            // The uncompressed size comes first if it is present.
+            let offset = if uncompressed_size == u32::MAX { 8 } else { 0 };
+
+            zip64
+                .and_then(|v| v.get(offset..offset + 8))
+                .map(|v| u64::from_le_bytes(v.try_into().unwrap()))
+        } else {
+            Some(compressed_size.into())
+        };
+
+        Ok(Self {
+            name,
+            compression_method,
+            crc32: (!has_data_descriptor).then_some(crc32),
+            compressed_size,
+            has_data_descriptor,
+            is_zip64: zip64.is_some(),
+        })
+    }
+
+    /// Read everything after the entry data and make sure that it only
+    /// describes this one entry. Returns the CRC32 from the data descriptor if
+    /// one is present.
+    pub fn read_trailer(&self, reader: &mut impl Read, max_size: u64) -> Result<Option<u32>> {
+        let mut trailer = vec![];
+        reader.take(max_size + 1).read_to_end(&mut trailer)?;
+        if trailer.len() as u64 > max_size {
+            return Err(Error::TrailerTooLarge);
+        }
+
+        let mut reader = trailer.as_slice();
+        let mut crc32 = None;
+
+        if self.has_data_descriptor {
+            // The signature is optional.
+            let mut value = read_u32(&mut reader)?;
+            if value == SIG_DATA_DESCRIPTOR {
+                value = read_u32(&mut reader)?;
+            }
+            crc32 = Some(value);
+
+            if self.is_zip64 {
+                read_u64(&mut reader)?;
+                read_u64(&mut reader)?;
+            } else {
+                read_u32(&mut reader)?;
+                read_u32(&mut reader)?;
+            }
+        }
+
+        let mut count = 0;
+
+        loop {
+            let signature = read_u32(&mut reader)?;
+
+            match signature {
+                SIG_CENTRAL_HEADER => {
+                    let fixed = read_vec(&mut reader, 24)?;
+                    let name_len = read_u16(&mut reader)?;
+                    let extra_len = read_u16(&mut reader)?;
+                    let comment_len = read_u16(&mut reader)?;
+                    let _rest = read_vec(&mut reader, 12)?;
+                    let name = read_vec(&mut reader, name_len.into())?;
+                    read_vec(
+                        &mut reader,
+                        usize::from(extra_len) + usize::from(comment_len),
+                    )?;
+
+                    if name != self.name.as_bytes() {
+                        return Err(Error::UnexpectedEntry(
+                            String::from_utf8_lossy(&name).into_owned(),
+                        ));
+                    }
+
+                    let cd_crc32 = u32::from_le_bytes(fixed[12..16].try_into().unwrap());
+                    crc32.get_or_insert(cd_crc32);
+
+                    count += 1;
+                }
+                SIG_ZIP64_EOCD => {
+                    let size = read_u64(&mut reader)?;
+                    read_vec(&mut reader, usize::try_from(size).unwrap_or(usize::MAX))?;
+                }
+                SIG_ZIP64_EOCD_LOCATOR => {
+                    read_vec(&mut reader, 16)?;
+                }
+                SIG_EOCD => break,
+                s => return Err(Error::BadSignature("central directory", s)),
+            }
+        }
+
+        if count != 1 {
+            return Err(Error::EntryCount(count));
+        }
+
+        Ok(crc32)
+    }
+
+    /// Size to read for stored entries.
+    pub fn stored_size(&self) -> Result<u64> {
+        self.compressed_size.ok_or(Error::UnknownSize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::bufread::DeflateDecoder;
+
+    use super::*;
+    use crate::mock::{split_zip, test_data};
+
+    #[test]
+    fn test_read_split_zip() {
+        let data = test_data(16384, 1);
+        let zip = split_zip("test.tar", &data, 3).concat();
+        let mut reader = zip.as_slice();
+
+        let header = LocalHeader::read(&mut reader).unwrap();
+        assert_eq!(header.name, "test.tar");
+        assert_eq!(header.compression_method, METHOD_DEFLATE);
+        assert_eq!(header.crc32, Some(crc32fast::hash(&data)));
+
+        let mut decoder = DeflateDecoder::new(&mut reader);
+        let mut output = vec![];
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+
+        let crc32 = header.read_trailer(&mut reader, 4096).unwrap();
+        assert_eq!(crc32, Some(crc32fast::hash(&data)));
+    }
+
+    #[test]
+    fn test_read_wrong_entry() {
+        let data = test_data(1024, 1);
+        let mut zip = split_zip("test.tar", &data, 1).remove(0);
+        // Rename the entry in the central directory only.
+        let n = zip.len();
+        zip[n - 22 - 8..n - 22].copy_from_slice(b"fake.tar");
+        let mut reader = zip.as_slice();
+
+        let header = LocalHeader::read(&mut reader).unwrap();
+        let mut decoder = DeflateDecoder::new(&mut reader);
+        io::copy(&mut decoder, &mut io::sink()).unwrap();
+
+        assert!(matches!(
+            header.read_trailer(&mut reader, 4096),
+            Err(Error::UnexpectedEntry(n)) if n == "fake.tar",
+        ));
+    }
+}